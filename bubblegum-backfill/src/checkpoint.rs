@@ -0,0 +1,249 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::ErrorKind;
+use crate::gap::TreeGapFill;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, FromQueryResult, Statement, Value};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+const FIND_CHECKPOINTS_SQL: &str = r#"
+SELECT * FROM ld_backfill_checkpoints WHERE tree = $1 ORDER BY id ASC;
+"#;
+
+const CREATE_CHECKPOINT_SQL: &str = r#"
+INSERT INTO ld_backfill_checkpoints (tree, gap_before, gap_until)
+VALUES ($1, $2, $3)
+RETURNING *;
+"#;
+
+const RECORD_CRAWL_SQL: &str = r#"
+UPDATE ld_backfill_checkpoints
+SET crawl_cursor = COALESCE($2, crawl_cursor), crawled = $3, crawl_complete = $4, updated_at = CURRENT_TIMESTAMP
+WHERE id = $1;
+"#;
+
+const RECORD_APPLIED_SQL: &str = r#"
+UPDATE ld_backfill_checkpoints
+SET applied = $2, last_applied_seq = COALESCE($3, last_applied_seq), updated_at = CURRENT_TIMESTAMP
+WHERE id = $1;
+"#;
+
+const RESET_CHECKPOINT_SQL: &str = r#"
+UPDATE ld_backfill_checkpoints
+SET crawl_cursor = NULL, crawled = 0, crawl_complete = FALSE, applied = 0, updated_at = CURRENT_TIMESTAMP
+WHERE id = $1;
+"#;
+
+const DELETE_CHECKPOINT_SQL: &str = r#"
+DELETE FROM ld_backfill_checkpoints WHERE id = $1;
+"#;
+
+/// Progress of the backfill of a gap, persisted in `ld_backfill_checkpoints` so a restarted
+/// backfill resumes the crawl from `crawl_cursor` and the replay after the `applied` oldest
+/// signatures.
+#[derive(Debug, FromQueryResult, PartialEq, Clone)]
+pub struct BackfillCheckpoint {
+    pub id: i64,
+    pub tree: Vec<u8>,
+    pub gap_before: Option<Vec<u8>>,
+    pub gap_until: Option<Vec<u8>>,
+    /// Oldest signature crawled and flushed to the spill file.
+    pub crawl_cursor: Option<Vec<u8>>,
+    /// Signatures flushed to the spill file.
+    pub crawled: i64,
+    pub crawl_complete: bool,
+    /// Oldest signatures of the spill file already applied.
+    pub applied: i64,
+    pub last_applied_seq: Option<i64>,
+}
+
+fn bytes(bytes: Option<&[u8]>) -> Value {
+    Value::Bytes(bytes.map(|bytes| Box::new(bytes.to_vec())))
+}
+
+impl BackfillCheckpoint {
+    pub async fn find(conn: &DatabaseConnection, tree: Pubkey) -> Result<Vec<Self>, ErrorKind> {
+        let statement = Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            FIND_CHECKPOINTS_SQL,
+            vec![bytes(Some(tree.as_ref()))],
+        );
+
+        Self::find_by_statement(statement)
+            .all(conn)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn create(conn: &DatabaseConnection, gap: &TreeGapFill) -> Result<Self, ErrorKind> {
+        let statement = Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            CREATE_CHECKPOINT_SQL,
+            vec![
+                bytes(Some(gap.tree().as_ref())),
+                bytes(gap.before().as_ref().map(AsRef::as_ref)),
+                bytes(gap.until().as_ref().map(AsRef::as_ref)),
+            ],
+        );
+
+        Self::find_by_statement(statement)
+            .one(conn)
+            .await?
+            .ok_or(ErrorKind::Generic("checkpoint not created".to_string()))
+    }
+
+    /// The file the crawled signatures are spilled to.
+    pub fn spill_path(&self, dir: &Path) -> PathBuf {
+        let tree = bs58::encode(&self.tree).into_string();
+
+        dir.join(format!("lightdas-backfill-{}-{}.signatures", tree, self.id))
+    }
+
+    /// Records that `crawled` signatures were flushed to the spill file, the oldest being `cursor`.
+    pub async fn record_crawl(
+        &mut self,
+        conn: &DatabaseConnection,
+        cursor: Option<Signature>,
+        crawled: u64,
+        crawl_complete: bool,
+    ) -> Result<(), ErrorKind> {
+        let cursor = cursor.map(|cursor| cursor.as_ref().to_vec());
+
+        if cursor.is_some() {
+            self.crawl_cursor = cursor.clone();
+        }
+        self.crawled = crawled as i64;
+        self.crawl_complete = crawl_complete;
+
+        conn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            RECORD_CRAWL_SQL,
+            vec![
+                self.id.into(),
+                bytes(cursor.as_deref()),
+                self.crawled.into(),
+                crawl_complete.into(),
+            ],
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records that the oldest `applied` signatures of the spill file were applied.
+    pub async fn record_applied(
+        &mut self,
+        conn: &DatabaseConnection,
+        applied: u64,
+        last_applied_seq: Option<u64>,
+    ) -> Result<(), ErrorKind> {
+        self.applied = applied as i64;
+        self.last_applied_seq = last_applied_seq
+            .map(|seq| seq as i64)
+            .or(self.last_applied_seq);
+
+        conn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            RECORD_APPLIED_SQL,
+            vec![
+                self.id.into(),
+                self.applied.into(),
+                last_applied_seq.map(|seq| seq as i64).into(),
+            ],
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets the progress of the gap, to crawl it again from the start.
+    pub async fn reset(&mut self, conn: &DatabaseConnection) -> Result<(), ErrorKind> {
+        self.crawl_cursor = None;
+        self.crawled = 0;
+        self.crawl_complete = false;
+        self.applied = 0;
+
+        conn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            RESET_CHECKPOINT_SQL,
+            vec![self.id.into()],
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, conn: &DatabaseConnection) -> Result<(), ErrorKind> {
+        conn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            DELETE_CHECKPOINT_SQL,
+            vec![self.id.into()],
+        ))
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// The part of the gap of a checkpoint left to crawl.
+impl TryFrom<&BackfillCheckpoint> for TreeGapFill {
+    type Error = ErrorKind;
+
+    fn try_from(checkpoint: &BackfillCheckpoint) -> Result<Self, Self::Error> {
+        let signature =
+            |bytes: &Option<Vec<u8>>| bytes.as_deref().map(Signature::try_from).transpose();
+
+        let tree =
+            Pubkey::try_from(checkpoint.tree.as_slice()).map_err(|_| ErrorKind::TryFromPubkey)?;
+        let cursor =
+            signature(&checkpoint.crawl_cursor).map_err(|_| ErrorKind::TryFromSignature)?;
+        let before = match cursor {
+            Some(cursor) => Some(cursor),
+            None => signature(&checkpoint.gap_before).map_err(|_| ErrorKind::TryFromSignature)?,
+        };
+        let until = signature(&checkpoint.gap_until).map_err(|_| ErrorKind::TryFromSignature)?;
+
+        Ok(Self::new(tree, before, until))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumes_the_crawl_from_the_cursor() {
+        let tree = Pubkey::new_unique();
+        let (before, until, cursor) = (
+            Signature::new_unique(),
+            Signature::new_unique(),
+            Signature::new_unique(),
+        );
+        let mut checkpoint = BackfillCheckpoint {
+            id: 7,
+            tree: tree.as_ref().to_vec(),
+            gap_before: Some(before.as_ref().to_vec()),
+            gap_until: Some(until.as_ref().to_vec()),
+            crawl_cursor: None,
+            crawled: 0,
+            crawl_complete: false,
+            applied: 0,
+            last_applied_seq: None,
+        };
+
+        let gap = TreeGapFill::try_from(&checkpoint).unwrap();
+        assert_eq!(gap.before(), Some(before));
+        assert_eq!(gap.until(), Some(until));
+
+        checkpoint.crawl_cursor = Some(cursor.as_ref().to_vec());
+
+        let gap = TreeGapFill::try_from(&checkpoint).unwrap();
+        assert_eq!(gap.tree(), tree);
+        assert_eq!(gap.before(), Some(cursor));
+        assert_eq!(gap.until(), Some(until));
+
+        assert_eq!(
+            checkpoint.spill_path(Path::new("/spill")),
+            PathBuf::from(format!("/spill/lightdas-backfill-{}-7.signatures", tree))
+        );
+    }
+}
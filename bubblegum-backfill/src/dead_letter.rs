@@ -0,0 +1,133 @@
+use std::array::TryFromSliceError;
+
+use crate::error::ErrorKind;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, FromQueryResult, Statement, Value};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+const RECORD_DEAD_LETTER_SQL: &str = r#"
+INSERT INTO ld_dead_letters (tree, signature, slot, error_kind, error)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (tree, signature) DO UPDATE
+SET slot = COALESCE(EXCLUDED.slot, ld_dead_letters.slot), error_kind = EXCLUDED.error_kind, error = EXCLUDED.error, attempts = ld_dead_letters.attempts + 1, updated_at = CURRENT_TIMESTAMP;
+"#;
+
+const FIND_DEAD_LETTERS_SQL: &str = r#"
+SELECT tree, signature, slot, error_kind, error, attempts FROM ld_dead_letters
+WHERE ($1::bytea IS NULL OR tree = $1) AND ($2::integer IS NULL OR attempts < $2)
+ORDER BY slot ASC NULLS FIRST, created_at ASC;
+"#;
+
+const DELETE_DEAD_LETTER_SQL: &str = r#"
+DELETE FROM ld_dead_letters WHERE tree = $1 AND signature = $2;
+"#;
+
+/// The step a dead letter failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadLetterErrorKind {
+    /// The transaction could not be fetched from the RPC.
+    Fetch,
+    /// The fetched transaction could not be decoded.
+    Parse,
+    /// The program transformer failed to write the transaction.
+    Index,
+}
+
+impl DeadLetterErrorKind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Fetch => "fetch",
+            Self::Parse => "parse",
+            Self::Index => "index",
+        }
+    }
+}
+
+/// A transaction of a tree that failed to index, persisted in `ld_dead_letters` until it is
+/// replayed or discarded.
+#[derive(Debug, FromQueryResult, PartialEq, Clone)]
+pub struct DeadLetter {
+    pub tree: Vec<u8>,
+    pub signature: Vec<u8>,
+    /// Unknown when the transaction could not be fetched.
+    pub slot: Option<i64>,
+    pub error_kind: String,
+    /// The last error.
+    pub error: String,
+    pub attempts: i32,
+}
+
+fn bytes(bytes: Option<&[u8]>) -> Value {
+    Value::Bytes(bytes.map(|bytes| Box::new(bytes.to_vec())))
+}
+
+impl DeadLetter {
+    /// Records that `signature` failed to index for `tree`, counting an attempt more if it already
+    /// failed before.
+    pub async fn record(
+        conn: &DatabaseConnection,
+        tree: Pubkey,
+        signature: Signature,
+        slot: Option<u64>,
+        error_kind: DeadLetterErrorKind,
+        error: &str,
+    ) -> Result<(), ErrorKind> {
+        conn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            RECORD_DEAD_LETTER_SQL,
+            vec![
+                bytes(Some(tree.as_ref())),
+                bytes(Some(signature.as_ref())),
+                slot.map(|slot| slot as i64).into(),
+                error_kind.as_str().into(),
+                error.into(),
+            ],
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    /// The dead letters of `tree`, or of every tree, attempted fewer than `max_attempts` times,
+    /// oldest first.
+    pub async fn find(
+        conn: &DatabaseConnection,
+        tree: Option<Pubkey>,
+        max_attempts: Option<u32>,
+    ) -> Result<Vec<Self>, ErrorKind> {
+        let statement = Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            FIND_DEAD_LETTERS_SQL,
+            vec![
+                bytes(tree.as_ref().map(AsRef::as_ref)),
+                max_attempts.map(|attempts| attempts as i32).into(),
+            ],
+        );
+
+        Self::find_by_statement(statement)
+            .all(conn)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn delete(&self, conn: &DatabaseConnection) -> Result<(), ErrorKind> {
+        conn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            DELETE_DEAD_LETTER_SQL,
+            vec![
+                bytes(Some(self.tree.as_slice())),
+                bytes(Some(self.signature.as_slice())),
+            ],
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn tree(&self) -> Result<Pubkey, TryFromSliceError> {
+        Pubkey::try_from(self.tree.as_slice())
+    }
+
+    pub fn signature(&self) -> Result<Signature, TryFromSliceError> {
+        Signature::try_from(self.signature.as_slice())
+    }
+}
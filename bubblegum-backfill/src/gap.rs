@@ -93,10 +93,28 @@ impl TryFrom<TreeGapModel> for TreeGapFill {
     }
 }
 
+/// How far back the crawls of a tree go, on top of the bounds of their gap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CrawlLowerBound {
+    /// Transactions landed before this slot are not crawled.
+    pub slot: Option<u64>,
+    /// This transaction and the ones landed before it are not crawled.
+    pub signature: Option<Signature>,
+}
+
+impl CrawlLowerBound {
+    /// Whether the crawl, going from the newest transactions to the oldest, reached the bound at
+    /// `signature` landed at `slot`.
+    pub fn reached(&self, signature: &Signature, slot: u64) -> bool {
+        self.slot.is_some_and(|bound| slot < bound) || self.signature.as_ref() == Some(signature)
+    }
+}
+
 pub struct TreeGapFill {
     tree: Pubkey,
     before: Option<Signature>,
     until: Option<Signature>,
+    lower_bound: CrawlLowerBound,
 }
 
 impl TreeGapFill {
@@ -105,9 +123,31 @@ impl TreeGapFill {
             tree,
             before,
             until,
+            lower_bound: CrawlLowerBound {
+                slot: None,
+                signature: None,
+            },
         }
     }
 
+    /// Stops the crawl at `lower_bound` if it is reached before `until`.
+    pub const fn with_lower_bound(mut self, lower_bound: CrawlLowerBound) -> Self {
+        self.lower_bound = lower_bound;
+        self
+    }
+
+    pub const fn tree(&self) -> Pubkey {
+        self.tree
+    }
+
+    pub const fn before(&self) -> Option<Signature> {
+        self.before
+    }
+
+    pub const fn until(&self) -> Option<Signature> {
+        self.until
+    }
+
     pub async fn crawl(&self, client: Rpc, sender: Sender<Signature>) -> Result<()> {
         let mut before = self.before;
 
@@ -122,8 +162,12 @@ impl TreeGapFill {
                 .filter(|transaction| transaction.err.is_none())
                 .collect::<Vec<RpcConfirmedTransactionStatusWithSignature>>();
 
-            for sig in successful_transactions.iter() {
-                let sig = Signature::from_str(&sig.signature)?;
+            for transaction in successful_transactions.iter() {
+                let sig = Signature::from_str(&transaction.signature)?;
+
+                if self.lower_bound.reached(&sig, transaction.slot) {
+                    return Ok(());
+                }
 
                 sender.send(sig).await?;
 
@@ -138,3 +182,23 @@ impl TreeGapFill {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_the_crawl_at_the_lower_bound() {
+        let (signature, other) = (Signature::new_unique(), Signature::new_unique());
+
+        assert!(!CrawlLowerBound::default().reached(&signature, 0));
+
+        let bound = CrawlLowerBound {
+            slot: Some(100),
+            signature: Some(signature),
+        };
+        assert!(!bound.reached(&other, 100));
+        assert!(bound.reached(&other, 99));
+        assert!(bound.reached(&signature, 100));
+    }
+}
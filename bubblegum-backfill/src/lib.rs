@@ -1,15 +1,27 @@
+mod checkpoint;
+mod dead_letter;
 mod error;
 mod gap;
+mod snapshot;
+mod spill;
 mod tree;
+mod verify;
 pub mod worker;
 
+pub use checkpoint::BackfillCheckpoint;
+pub use dead_letter::{DeadLetter, DeadLetterErrorKind};
 pub use error::ErrorKind;
+pub use gap::{CrawlLowerBound, TreeGapFill};
+pub use snapshot::{SnapshotNode, TreeChangeLog, TreeSnapshot};
+pub use spill::SignatureSpill;
+pub use tree::{TreeHeaderResponse, TreeResponse};
+pub use verify::{reset_tree_index, verify_tree, TreeIntegrity, TreeVerification};
 
 use anyhow::Result;
 use clap::Parser;
 use das_core::Rpc;
 use futures::{stream::FuturesUnordered, StreamExt};
-use tree::TreeResponse;
+use tokio_util::sync::CancellationToken;
 use worker::TreeWorkerArgs;
 
 #[derive(Clone)]
@@ -41,9 +53,14 @@ pub struct BubblegumBackfillArgs {
     pub tree_worker: TreeWorkerArgs,
 }
 
+/// Backfills the trees of `args`.
+///
+/// Cancelling `shutdown` stops the crawls and the replays at the end of their current window, what
+/// is left is resumed from the backfill checkpoints on the next backfill.
 pub async fn start_bubblegum_backfill(
     context: BubblegumBackfillContext,
     args: BubblegumBackfillArgs,
+    shutdown: CancellationToken,
 ) -> Result<()> {
     let trees = if let Some(ref only_trees) = args.only_trees {
         TreeResponse::find(&context.solana_rpc, only_trees.clone()).await?
@@ -57,8 +74,11 @@ pub async fn start_bubblegum_backfill(
         if crawl_handles.len() >= args.tree_crawler_count {
             crawl_handles.next().await;
         }
+        if shutdown.is_cancelled() {
+            break;
+        }
         let context = context.clone();
-        let handle = args.tree_worker.start(context, tree);
+        let handle = args.tree_worker.start(context, tree, shutdown.clone());
 
         crawl_handles.push(handle);
     }
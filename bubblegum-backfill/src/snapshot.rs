@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use borsh::BorshDeserialize;
+use das_core::Rpc;
+use digital_asset_types::dao::cl_items;
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue, ConnectionTrait, DatabaseConnection, DbBackend,
+    EntityTrait, QueryTrait,
+};
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use spl_account_compression::state::{
+    merkle_tree_get_size, ConcurrentMerkleTreeHeader, CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1,
+};
+
+/// Size of a node of the tree, a 32 bytes hash.
+const NODE_SIZE: usize = 32;
+/// Size of the sequence number, the active index and the buffer size preceding the change logs.
+const TREE_COUNTERS_SIZE: usize = 24;
+/// Rows upserted per statement.
+const SAVE_BATCH_SIZE: usize = 1000;
+
+pub type Node = [u8; NODE_SIZE];
+
+/// A change log kept in the buffer of a tree account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeChangeLog {
+    pub seq: u64,
+    /// Index of the changed leaf.
+    pub index: u32,
+    /// Root of the tree after the change.
+    pub root: Node,
+    /// The changed leaf and its ancestors, from the leaf up, without the root.
+    pub path: Vec<Node>,
+}
+
+/// A node of the tree at the seq it was last known to have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotNode {
+    pub node_idx: u64,
+    pub level: u32,
+    pub seq: u64,
+    pub hash: Node,
+}
+
+/// The state of a tree held by its account: the change logs of its buffer, the proof of its
+/// rightmost leaf and its canopy.
+///
+/// The account doesn't hold the whole tree, so the nodes of the snapshot only cover the leaves
+/// changed by the buffered change logs, the rightmost leaf and the levels of the canopy.
+#[derive(Debug, Clone)]
+pub struct TreeSnapshot {
+    pub tree: Pubkey,
+    pub max_depth: u32,
+    pub seq: u64,
+    /// Buffered change logs, oldest first.
+    pub change_logs: Vec<TreeChangeLog>,
+    /// Number of leaves appended to the tree.
+    pub num_leaves: u64,
+    pub rightmost_leaf: Node,
+    /// Siblings of the rightmost leaf and its ancestors, from the leaf up.
+    pub rightmost_proof: Vec<Node>,
+    /// Nodes of the levels closest to the root, stored breadth first from the children of the
+    /// root.
+    pub canopy: Vec<Node>,
+}
+
+fn node(bytes: &[u8], offset: usize) -> Node {
+    let mut node = [0; NODE_SIZE];
+    node.copy_from_slice(&bytes[offset..offset + NODE_SIZE]);
+    node
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    let mut le_bytes = [0; 8];
+    le_bytes.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(le_bytes)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    let mut le_bytes = [0; 4];
+    le_bytes.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(le_bytes)
+}
+
+impl TreeSnapshot {
+    /// Decodes the tree account `account` of `tree`, laid out as a `ConcurrentMerkleTree` of the
+    /// depth and buffer size of its header followed by its canopy.
+    pub fn try_from_account(tree: Pubkey, account: &Account) -> Result<Self> {
+        let bytes = account.data.as_slice();
+        if bytes.len() < CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1 {
+            return Err(anyhow!("{} is not a tree account", tree));
+        }
+
+        let (header_bytes, rest) = bytes.split_at(CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1);
+        let header = ConcurrentMerkleTreeHeader::try_from_slice(header_bytes)?;
+        let merkle_tree_size = merkle_tree_get_size(&header)?;
+        if rest.len() < merkle_tree_size {
+            return Err(anyhow!("{} is truncated", tree));
+        }
+        let (tree_bytes, canopy_bytes) = rest.split_at(merkle_tree_size);
+
+        let max_depth = header.get_max_depth();
+        let max_buffer_size = header.get_max_buffer_size() as u64;
+        let depth = max_depth as usize;
+
+        let seq = u64_at(tree_bytes, 0);
+        let active_index = u64_at(tree_bytes, 8);
+        let buffer_size = u64_at(tree_bytes, 16);
+
+        // A change log is its root, its path, its leaf index and padding
+        let change_log_size = NODE_SIZE * (depth + 1) + 8;
+        let read_change_log = |position: u64, seq: u64| {
+            let offset = TREE_COUNTERS_SIZE + position as usize * change_log_size;
+
+            TreeChangeLog {
+                seq,
+                index: u32_at(tree_bytes, offset + NODE_SIZE * (depth + 1)),
+                root: node(tree_bytes, offset),
+                path: (0..depth)
+                    .map(|level| node(tree_bytes, offset + NODE_SIZE * (level + 1)))
+                    .collect(),
+            }
+        };
+
+        // The change log of seq 0 is the empty tree, the buffer wraps around the active index
+        let change_logs = (0..buffer_size.min(seq))
+            .rev()
+            .map(|age| {
+                let position = (active_index + max_buffer_size - age) % max_buffer_size;
+                read_change_log(position, seq - age)
+            })
+            .collect();
+
+        let rightmost_offset = TREE_COUNTERS_SIZE + max_buffer_size as usize * change_log_size;
+        let rightmost_proof = (0..depth)
+            .map(|level| node(tree_bytes, rightmost_offset + NODE_SIZE * level))
+            .collect();
+        let rightmost_leaf = node(tree_bytes, rightmost_offset + NODE_SIZE * depth);
+        let num_leaves = u32_at(tree_bytes, rightmost_offset + NODE_SIZE * (depth + 1)) as u64;
+
+        let canopy = canopy_bytes
+            .chunks_exact(NODE_SIZE)
+            .map(|chunk| node(chunk, 0))
+            .collect();
+
+        Ok(Self {
+            tree,
+            max_depth,
+            seq,
+            change_logs,
+            num_leaves,
+            rightmost_leaf,
+            rightmost_proof,
+            canopy,
+        })
+    }
+
+    /// Fetches the tree account of `tree` at the commitment of `rpc` and decodes it.
+    pub async fn fetch(rpc: &Rpc, tree: Pubkey) -> Result<Self> {
+        let account = rpc
+            .get_account(&tree)
+            .await?
+            .value
+            .ok_or_else(|| anyhow!("tree account {} not found", tree))?;
+
+        Self::try_from_account(tree, &account)
+    }
+
+    const fn node_idx(&self, level: u32, leaf_index: u64) -> u64 {
+        (1 << (self.max_depth - level)) + (leaf_index >> level)
+    }
+
+    /// The nodes known from the snapshot, each at the newest seq it is known at.
+    pub fn nodes(&self) -> Vec<SnapshotNode> {
+        let mut nodes: HashMap<u64, SnapshotNode> = HashMap::new();
+        let mut insert = |node: SnapshotNode| {
+            let known = nodes.entry(node.node_idx).or_insert(node);
+            if node.seq > known.seq {
+                *known = node;
+            }
+        };
+
+        for change_log in &self.change_logs {
+            for (level, hash) in change_log.path.iter().enumerate() {
+                let level = level as u32;
+                insert(SnapshotNode {
+                    node_idx: self.node_idx(level, change_log.index as u64),
+                    level,
+                    seq: change_log.seq,
+                    hash: *hash,
+                });
+            }
+            insert(SnapshotNode {
+                node_idx: 1,
+                level: self.max_depth,
+                seq: change_log.seq,
+                hash: change_log.root,
+            });
+        }
+
+        // The rightmost proof stops being updated once the tree is full
+        if self.num_leaves > 0 && self.num_leaves < 1 << self.max_depth {
+            let rightmost_index = self.num_leaves - 1;
+
+            insert(SnapshotNode {
+                node_idx: self.node_idx(0, rightmost_index),
+                level: 0,
+                seq: self.seq,
+                hash: self.rightmost_leaf,
+            });
+            for (level, hash) in self.rightmost_proof.iter().enumerate() {
+                let level = level as u32;
+                insert(SnapshotNode {
+                    node_idx: self.node_idx(level, rightmost_index) ^ 1,
+                    level,
+                    seq: self.seq,
+                    hash: *hash,
+                });
+            }
+        }
+
+        // The canopy is updated along with every change, its first node is at index 2
+        if self.seq > 0 {
+            for (position, hash) in self.canopy.iter().enumerate() {
+                let node_idx = position as u64 + 2;
+                insert(SnapshotNode {
+                    node_idx,
+                    level: self.max_depth - node_idx.ilog2(),
+                    seq: self.seq,
+                    hash: *hash,
+                });
+            }
+        }
+
+        let mut nodes = nodes.into_values().collect::<Vec<_>>();
+        nodes.sort_by_key(|node| node.node_idx);
+        nodes
+    }
+
+    /// Stores the nodes of the snapshot in `cl_items`, the ones already known at a newer seq are
+    /// left as they are.
+    pub async fn save(&self, conn: &DatabaseConnection) -> Result<()> {
+        let nodes = self.nodes();
+        let leaf_offset = 1u64 << self.max_depth;
+
+        for batch in nodes.chunks(SAVE_BATCH_SIZE) {
+            let items = batch.iter().map(|node| cl_items::ActiveModel {
+                tree: ActiveValue::Set(self.tree.to_bytes().to_vec()),
+                node_idx: ActiveValue::Set(node.node_idx as i64),
+                leaf_idx: ActiveValue::Set(
+                    (node.level == 0).then(|| (node.node_idx - leaf_offset) as i64),
+                ),
+                seq: ActiveValue::Set(node.seq as i64),
+                level: ActiveValue::Set(node.level as i64),
+                hash: ActiveValue::Set(node.hash.to_vec()),
+                ..Default::default()
+            });
+
+            let mut query = cl_items::Entity::insert_many(items)
+                .on_conflict(
+                    OnConflict::columns([cl_items::Column::Tree, cl_items::Column::NodeIdx])
+                        .update_columns([
+                            cl_items::Column::Hash,
+                            cl_items::Column::Seq,
+                            cl_items::Column::LeafIdx,
+                            cl_items::Column::Level,
+                        ])
+                        .to_owned(),
+                )
+                .build(DbBackend::Postgres);
+            query.sql = format!("{} WHERE excluded.seq > cl_items.seq", query.sql);
+
+            conn.execute(query).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+    use spl_account_compression::ConcurrentMerkleTree;
+    use spl_concurrent_merkle_tree::hash::recompute;
+
+    const DEPTH: usize = 3;
+    const BUFFER_SIZE: usize = 8;
+
+    fn tree_account(merkle_tree: &ConcurrentMerkleTree<DEPTH, BUFFER_SIZE>) -> Account {
+        let mut header =
+            ConcurrentMerkleTreeHeader::try_from_slice(&[0; CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1])
+                .unwrap();
+        header.initialize(DEPTH as u32, BUFFER_SIZE as u32, &Pubkey::new_unique(), 42);
+
+        let mut data = header.try_to_vec().unwrap();
+        data.extend(bytemuck::bytes_of(merkle_tree));
+
+        // A canopy of depth 1 holds the children of the root, one is on the path of the last
+        // appended leaf and the other is its sibling
+        let last_change_log = merkle_tree.change_logs[merkle_tree.active_index as usize];
+        let path_node = last_change_log.path[DEPTH - 1];
+        let sibling = merkle_tree.rightmost_proof.proof[DEPTH - 1];
+        if last_change_log.index >> (DEPTH - 1) == 0 {
+            data.extend(path_node);
+            data.extend(sibling);
+        } else {
+            data.extend(sibling);
+            data.extend(path_node);
+        }
+
+        Account {
+            data,
+            owner: spl_account_compression::id(),
+            ..Account::default()
+        }
+    }
+
+    fn merkle_tree(leaves: u8) -> ConcurrentMerkleTree<DEPTH, BUFFER_SIZE> {
+        let mut merkle_tree = ConcurrentMerkleTree::<DEPTH, BUFFER_SIZE>::new();
+        merkle_tree.initialize().unwrap();
+        for leaf in 1..=leaves {
+            merkle_tree.append([leaf; NODE_SIZE]).unwrap();
+        }
+        merkle_tree
+    }
+
+    fn proof(nodes: &[SnapshotNode], leaf_index: u64) -> Option<(Node, Vec<Node>)> {
+        let hash = |node_idx: u64| {
+            nodes
+                .iter()
+                .find(|node| node.node_idx == node_idx)
+                .map(|node| node.hash)
+        };
+        let leaf_node_idx = (1 << DEPTH) + leaf_index;
+
+        let proof = (0..DEPTH)
+            .map(|level| hash((leaf_node_idx >> level) ^ 1))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some((hash(leaf_node_idx)?, proof))
+    }
+
+    #[test]
+    fn reads_the_change_logs_and_the_rightmost_proof() {
+        let tree = Pubkey::new_unique();
+        let merkle_tree = merkle_tree(5);
+
+        let snapshot = TreeSnapshot::try_from_account(tree, &tree_account(&merkle_tree)).unwrap();
+        assert_eq!(snapshot.seq, 5);
+        assert_eq!(snapshot.num_leaves, 5);
+        assert_eq!(
+            snapshot
+                .change_logs
+                .iter()
+                .map(|change_log| (change_log.seq, change_log.index))
+                .collect::<Vec<_>>(),
+            [(1, 0), (2, 1), (3, 2), (4, 3), (5, 4)]
+        );
+        assert_eq!(snapshot.change_logs[4].root, merkle_tree.get_root());
+        assert_eq!(snapshot.canopy.len(), 2);
+
+        let nodes = snapshot.nodes();
+        let root = nodes.iter().find(|node| node.node_idx == 1).unwrap();
+        assert_eq!((root.seq, root.hash), (5, merkle_tree.get_root()));
+
+        // Every leaf appended so far has a proof of the current root
+        for leaf_index in 0..5 {
+            let (leaf, proof) = proof(&nodes, leaf_index).unwrap();
+            assert_eq!(leaf, [leaf_index as u8 + 1; NODE_SIZE]);
+            assert_eq!(
+                recompute(leaf, &proof, leaf_index as u32),
+                merkle_tree.get_root()
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_nodes_once_the_buffer_wrapped() {
+        let tree = Pubkey::new_unique();
+        let merkle_tree = merkle_tree(8);
+
+        let snapshot = TreeSnapshot::try_from_account(tree, &tree_account(&merkle_tree)).unwrap();
+        assert_eq!(snapshot.change_logs.len(), BUFFER_SIZE);
+        assert_eq!(snapshot.change_logs[0].seq, 1);
+        assert_eq!(snapshot.change_logs[BUFFER_SIZE - 1].seq, 8);
+
+        // The rightmost proof is not maintained once the tree is full
+        let nodes = snapshot.nodes();
+        for leaf_index in 0..8 {
+            let (_, proof) = proof(&nodes, leaf_index).unwrap();
+            let leaf = [leaf_index as u8 + 1; NODE_SIZE];
+            assert_eq!(
+                recompute(leaf, &proof, leaf_index as u32),
+                merkle_tree.get_root()
+            );
+        }
+        assert!(nodes.iter().all(|node| node.node_idx != 1 || node.seq == 8));
+    }
+}
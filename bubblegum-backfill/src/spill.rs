@@ -0,0 +1,255 @@
+use std::io::SeekFrom;
+use std::path::Path;
+
+use anyhow::{ensure, Result};
+use solana_sdk::signature::{Signature, SIGNATURE_BYTES};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+/// Signatures of a gap, pushed newest first as they are crawled and read back oldest first.
+///
+/// Signatures are buffered in memory and written to a file every `threshold` signatures, so a
+/// crawl can be resumed from the last flushed signature and the file can be read back from the
+/// last applied window.
+pub struct SignatureSpill {
+    threshold: usize,
+    memory: Vec<Signature>,
+    file: File,
+    /// Signatures written to the file.
+    written: u64,
+    pushed: u64,
+    /// Signatures written to the file and not read back yet, the oldest are at its end.
+    unread: u64,
+}
+
+impl SignatureSpill {
+    /// Creates the spill file at `path`, replacing any previous one.
+    pub async fn create(path: &Path, threshold: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await?;
+
+        Ok(Self {
+            threshold,
+            memory: Vec::new(),
+            file,
+            written: 0,
+            pushed: 0,
+            unread: 0,
+        })
+    }
+
+    /// Reopens the spill file at `path` after `written` signatures were flushed and the oldest
+    /// `read` of them were read back. Signatures written past `written` are discarded.
+    pub async fn open(path: &Path, threshold: usize, written: u64, read: u64) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path).await?;
+        let size = written * SIGNATURE_BYTES as u64;
+
+        ensure!(
+            file.metadata().await?.len() >= size,
+            "spill file {} is shorter than {} signatures",
+            path.display(),
+            written
+        );
+        ensure!(read <= written, "read more signatures than were written");
+
+        file.set_len(size).await?;
+
+        Ok(Self {
+            threshold,
+            memory: Vec::new(),
+            file,
+            written,
+            pushed: written,
+            unread: written - read,
+        })
+    }
+
+    /// Signatures pushed so far, including the ones already read back.
+    pub const fn pushed(&self) -> u64 {
+        self.pushed
+    }
+
+    /// Signatures not read back yet.
+    pub const fn len(&self) -> u64 {
+        self.unread + self.memory.len() as u64
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a signature older than the ones already pushed.
+    ///
+    /// Returns `true` if every pushed signature was flushed to the file.
+    pub async fn push(&mut self, signature: Signature) -> Result<bool> {
+        self.memory.push(signature);
+        self.pushed += 1;
+
+        if self.memory.len() < self.threshold {
+            return Ok(false);
+        }
+
+        self.flush().await?;
+
+        Ok(true)
+    }
+
+    /// Writes the signatures in memory to the end of the file.
+    pub async fn flush(&mut self) -> Result<()> {
+        if self.memory.is_empty() {
+            return Ok(());
+        }
+
+        let bytes = self
+            .memory
+            .iter()
+            .flat_map(|signature| <[u8; SIGNATURE_BYTES]>::from(*signature))
+            .collect::<Vec<u8>>();
+
+        // Reading back moves the cursor, the file is appended to after the last written signature
+        self.file
+            .seek(SeekFrom::Start(self.written * SIGNATURE_BYTES as u64))
+            .await?;
+        self.file.write_all(&bytes).await?;
+        self.file.sync_data().await?;
+
+        // Spills are only read back once every signature was pushed
+        self.written += self.memory.len() as u64;
+        self.unread = self.written;
+        self.memory.clear();
+
+        Ok(())
+    }
+
+    /// Pops up to `size` of the oldest remaining signatures, oldest first.
+    ///
+    /// Returns an empty window once every signature was read.
+    pub async fn next_window(&mut self, size: usize) -> Result<Vec<Signature>> {
+        // The signatures in memory are older than the ones in the file
+        if !self.memory.is_empty() {
+            let start = self.memory.len().saturating_sub(size);
+            let mut window = self.memory.split_off(start);
+            window.reverse();
+
+            return Ok(window);
+        }
+
+        let count = self.unread.min(size as u64);
+        let start = self.unread - count;
+
+        let mut bytes = vec![0; count as usize * SIGNATURE_BYTES];
+        self.file
+            .seek(SeekFrom::Start(start * SIGNATURE_BYTES as u64))
+            .await?;
+        self.file.read_exact(&mut bytes).await?;
+
+        self.unread = start;
+
+        Ok(bytes
+            .chunks_exact(SIGNATURE_BYTES)
+            .rev()
+            .map(|bytes| Signature::try_from(bytes).expect("chunks are signature sized"))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn drain(spill: &mut SignatureSpill, size: usize) -> Vec<Vec<Signature>> {
+        let mut windows = Vec::new();
+
+        loop {
+            let window = spill.next_window(size).await.unwrap();
+
+            if window.is_empty() {
+                return windows;
+            }
+
+            windows.push(window);
+        }
+    }
+
+    fn signatures(count: usize) -> Vec<Signature> {
+        (0..count).map(|_| Signature::new_unique()).collect()
+    }
+
+    #[tokio::test]
+    async fn reads_back_oldest_first_across_memory_and_disk() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let newest_first = signatures(10);
+        let mut spill = SignatureSpill::create(&path, 3).await.unwrap();
+
+        for (index, signature) in newest_first.iter().enumerate() {
+            assert_eq!(spill.push(*signature).await.unwrap(), (index + 1) % 3 == 0);
+        }
+
+        assert_eq!(spill.len(), 10);
+        assert_eq!(spill.memory.len(), 1);
+
+        let windows = drain(&mut spill, 4).await;
+
+        assert_eq!(
+            windows.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![1, 4, 4, 1]
+        );
+        assert_eq!(
+            windows.concat(),
+            newest_first.into_iter().rev().collect::<Vec<_>>()
+        );
+        assert!(spill.is_empty());
+        assert_eq!(spill.pushed(), 10);
+    }
+
+    #[tokio::test]
+    async fn resumes_from_the_last_flush_and_read() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let newest_first = signatures(8);
+        let mut spill = SignatureSpill::create(&path, 3).await.unwrap();
+
+        for signature in &newest_first[..7] {
+            spill.push(*signature).await.unwrap();
+        }
+
+        // The 7th signature was never flushed, the crawl resumes after the 6th
+        let mut spill = SignatureSpill::open(&path, 3, 6, 0).await.unwrap();
+
+        assert_eq!(spill.len(), 6);
+
+        for signature in &newest_first[6..] {
+            spill.push(*signature).await.unwrap();
+        }
+        spill.flush().await.unwrap();
+
+        assert_eq!(
+            spill.next_window(3).await.unwrap(),
+            vec![newest_first[7], newest_first[6], newest_first[5]]
+        );
+
+        // Only the first window was applied before restarting
+        let mut spill = SignatureSpill::open(&path, 3, 8, 3).await.unwrap();
+
+        assert_eq!(
+            drain(&mut spill, 3).await.concat(),
+            newest_first[..5].iter().rev().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_spill_files() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let mut spill = SignatureSpill::create(&path, 2).await.unwrap();
+
+        for signature in signatures(2) {
+            spill.push(signature).await.unwrap();
+        }
+
+        assert!(SignatureSpill::open(&path, 2, 4, 0).await.is_err());
+    }
+}
@@ -10,6 +10,12 @@ use spl_account_compression::state::{
 };
 use std::str::FromStr;
 
+/// Size of a node of the tree, a 32 bytes hash.
+const NODE_SIZE: usize = 32;
+/// Offset of the authority in a tree account, after the account type, the header version, the
+/// max buffer size and the max depth.
+const AUTHORITY_OFFSET: usize = 10;
+
 #[derive(Debug, Clone)]
 pub struct TreeHeaderResponse {
     pub max_depth: u32,
@@ -38,6 +44,15 @@ pub struct TreeResponse {
     pub pubkey: Pubkey,
     pub tree_header: TreeHeaderResponse,
     pub seq: u64,
+    pub canopy_depth: u32,
+}
+
+/// The depth of a canopy stored in `canopy_bytes_len` bytes, a canopy of depth `d` holds the
+/// `2^(d+1) - 2` nodes closest to the root.
+const fn canopy_depth(canopy_bytes_len: usize) -> u32 {
+    let nodes = canopy_bytes_len / NODE_SIZE;
+
+    (nodes + 2).ilog2() - 1
 }
 
 impl TreeResponse {
@@ -49,7 +64,7 @@ impl TreeResponse {
             ConcurrentMerkleTreeHeader::try_from_slice(header_bytes)?;
 
         let merkle_tree_size = merkle_tree_get_size(&header)?;
-        let (tree_bytes, _canopy_bytes) = rest.split_at(merkle_tree_size);
+        let (tree_bytes, canopy_bytes) = rest.split_at(merkle_tree_size);
 
         let seq_bytes = tree_bytes[0..8].try_into()?;
         let seq = u64::from_le_bytes(seq_bytes);
@@ -64,18 +79,41 @@ impl TreeResponse {
             pubkey,
             tree_header,
             seq,
+            canopy_depth: canopy_depth(canopy_bytes.len()),
         })
     }
 
     pub async fn all(client: &Rpc) -> Result<Vec<Self>, ErrorKind> {
+        Self::all_with_filters(client, vec![]).await
+    }
+
+    /// The trees whose authority is `authority`, for Bubblegum trees the tree config PDA.
+    pub async fn all_by_authority(
+        client: &Rpc,
+        authority: &Pubkey,
+    ) -> Result<Vec<Self>, ErrorKind> {
+        Self::all_with_filters(
+            client,
+            vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                AUTHORITY_OFFSET,
+                authority.to_bytes().to_vec(),
+            ))],
+        )
+        .await
+    }
+
+    async fn all_with_filters(
+        client: &Rpc,
+        filters: Vec<RpcFilterType>,
+    ) -> Result<Vec<Self>, ErrorKind> {
+        let filters = [
+            vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, vec![1u8]))],
+            filters,
+        ]
+        .concat();
+
         Ok(client
-            .get_program_accounts(
-                &id(),
-                Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
-                    0,
-                    vec![1u8],
-                ))]),
-            )
+            .get_program_accounts(&id(), Some(filters))
             .await?
             .into_iter()
             .filter_map(|(pubkey, account)| Self::try_from_rpc(pubkey, account).ok())
@@ -116,3 +154,65 @@ impl TreeResponse {
         Ok(trees)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+
+    fn tree_account(
+        tree: &Pubkey,
+        max_depth: u32,
+        max_buffer_size: u32,
+        canopy_depth: u32,
+        seq: u64,
+    ) -> Account {
+        let mut header =
+            ConcurrentMerkleTreeHeader::try_from_slice(&[0; CONCURRENT_MERKLE_TREE_HEADER_SIZE_V1])
+                .unwrap();
+        let (authority, _) = Pubkey::find_program_address(&[tree.as_ref()], &mpl_bubblegum::ID);
+        header.initialize(max_depth, max_buffer_size, &authority, 42);
+
+        let mut tree_bytes = vec![0; merkle_tree_get_size(&header).unwrap()];
+        tree_bytes[0..8].copy_from_slice(&seq.to_le_bytes());
+        let canopy_bytes = vec![0; ((1 << (canopy_depth + 1)) - 2) * NODE_SIZE];
+
+        let mut data = header.try_to_vec().unwrap();
+        data.extend(tree_bytes);
+        data.extend(canopy_bytes);
+
+        Account {
+            data,
+            owner: id(),
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn reads_the_header_seq_and_canopy_depth() {
+        let tree = Pubkey::new_unique();
+
+        for canopy in [0, 1, 5] {
+            let response =
+                TreeResponse::try_from_rpc(tree, tree_account(&tree, 14, 64, canopy, 7)).unwrap();
+
+            assert_eq!(response.tree_header.max_depth, 14);
+            assert_eq!(response.tree_header.max_buffer_size, 64);
+            assert_eq!(response.tree_header.creation_slot, 42);
+            assert_eq!(response.seq, 7);
+            assert_eq!(response.canopy_depth, canopy);
+        }
+    }
+
+    #[test]
+    fn filters_on_the_authority_offset() {
+        let tree = Pubkey::new_unique();
+        let (authority, _) = Pubkey::find_program_address(&[tree.as_ref()], &mpl_bubblegum::ID);
+        let account = tree_account(&tree, 14, 64, 0, 0);
+
+        assert_eq!(
+            &account.data[AUTHORITY_OFFSET..AUTHORITY_OFFSET + 32],
+            authority.as_ref()
+        );
+    }
+}
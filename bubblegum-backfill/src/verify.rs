@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::snapshot::{Node, SnapshotNode, TreeSnapshot};
+use crate::BackfillCheckpoint;
+use anyhow::Result;
+use das_core::Rpc;
+use log::error;
+use sea_orm::{
+    AccessMode, ConnectionTrait, DatabaseConnection, DbBackend, FromQueryResult, IsolationLevel,
+    Statement, TransactionTrait, Value,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use spl_concurrent_merkle_tree::node::empty_node;
+
+const FIND_NODES_SQL: &str = r#"
+SELECT node_idx, seq, hash FROM cl_items WHERE tree = $1 AND node_idx = ANY($2::bigint[]);
+"#;
+
+const RESET_TREE_SQL: [&str; 3] = [
+    "DELETE FROM cl_items WHERE tree = $1",
+    "DELETE FROM cl_audits_v2 WHERE tree = $1",
+    "DELETE FROM ld_backfill_checkpoints WHERE tree = $1",
+];
+
+/// Nodes looked up per statement.
+const FIND_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, FromQueryResult)]
+struct IndexedNode {
+    node_idx: i64,
+    seq: i64,
+    hash: Vec<u8>,
+}
+
+/// How the nodes of a tree in `cl_items` compare to its account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeIntegrity {
+    /// The indexed nodes the account knows of match it.
+    Verified { checked_nodes: usize },
+    /// The indexed root is not among the buffered change logs, the nodes can't be compared.
+    Unverifiable { reason: String },
+    /// The indexed nodes differ from the account, a transaction was missed or misapplied.
+    Mismatch { nodes: Vec<u64> },
+}
+
+impl fmt::Display for TreeIntegrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Verified { checked_nodes } => write!(f, "verified {} nodes", checked_nodes),
+            Self::Unverifiable { reason } => write!(f, "unverifiable, {}", reason),
+            Self::Mismatch { nodes } => write!(f, "mismatch of nodes {:?}", nodes),
+        }
+    }
+}
+
+/// The verification of a tree against its account.
+#[derive(Debug, Clone)]
+pub struct TreeVerification {
+    pub tree: Pubkey,
+    pub onchain_seq: u64,
+    /// Seq of the indexed root.
+    pub indexed_seq: Option<u64>,
+    pub integrity: TreeIntegrity,
+}
+
+/// Compares the nodes of `tree` in `cl_items` to the change logs buffered in its account, and to
+/// its rightmost proof and its canopy when the index caught up with it.
+///
+/// The nodes are compared as of the seq of the indexed root, which needs to be buffered.
+pub async fn verify_tree(
+    rpc: &Rpc,
+    conn: &DatabaseConnection,
+    tree: Pubkey,
+) -> Result<TreeVerification> {
+    // Live transactions are applied at `confirmed`, the account is at least as recent as them
+    let snapshot =
+        TreeSnapshot::fetch(&rpc.with_commitment(CommitmentConfig::confirmed()), tree).await?;
+
+    let indexed = find_indexed_nodes(conn, tree, &snapshot.nodes()).await?;
+    let indexed_seq = indexed.get(&1).map(|(seq, _)| *seq);
+
+    Ok(TreeVerification {
+        tree,
+        onchain_seq: snapshot.seq,
+        indexed_seq,
+        integrity: compare(&snapshot, &indexed),
+    })
+}
+
+/// The indexed nodes of `tree` among `nodes`, by node index.
+async fn find_indexed_nodes(
+    conn: &DatabaseConnection,
+    tree: Pubkey,
+    nodes: &[SnapshotNode],
+) -> Result<HashMap<u64, (u64, Vec<u8>)>> {
+    // Read from a single snapshot of the database while transactions are being applied
+    let txn = conn
+        .begin_with_config(
+            Some(IsolationLevel::RepeatableRead),
+            Some(AccessMode::ReadOnly),
+        )
+        .await?;
+
+    let mut indexed = HashMap::new();
+    for batch in nodes.chunks(FIND_BATCH_SIZE) {
+        let node_idxs = batch
+            .iter()
+            .map(|node| node.node_idx.to_string())
+            .collect::<Vec<_>>();
+        let statement = Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            FIND_NODES_SQL,
+            vec![
+                Value::Bytes(Some(Box::new(tree.to_bytes().to_vec()))),
+                Value::String(Some(Box::new(format!("{{{}}}", node_idxs.join(","))))),
+            ],
+        );
+
+        for node in IndexedNode::find_by_statement(statement).all(&txn).await? {
+            indexed.insert(node.node_idx as u64, (node.seq as u64, node.hash));
+        }
+    }
+    txn.commit().await?;
+
+    Ok(indexed)
+}
+
+fn compare(snapshot: &TreeSnapshot, indexed: &HashMap<u64, (u64, Vec<u8>)>) -> TreeIntegrity {
+    let Some((indexed_seq, _)) = indexed.get(&1) else {
+        return TreeIntegrity::Unverifiable {
+            reason: "the tree is not indexed".to_string(),
+        };
+    };
+
+    if *indexed_seq > snapshot.seq {
+        return TreeIntegrity::Unverifiable {
+            reason: format!(
+                "the index is at seq {} past the account at seq {}",
+                indexed_seq, snapshot.seq
+            ),
+        };
+    }
+
+    if !snapshot
+        .change_logs
+        .iter()
+        .any(|change_log| change_log.seq == *indexed_seq)
+    {
+        return TreeIntegrity::Unverifiable {
+            reason: format!(
+                "seq {} is no longer buffered by the account, which holds {} change logs up to \
+                 seq {}",
+                indexed_seq,
+                snapshot.change_logs.len(),
+                snapshot.seq
+            ),
+        };
+    }
+
+    // The value of a node at the indexed seq is the one of the newest change log up to it that
+    // changed the node
+    let mut expected = HashMap::new();
+    for change_log in snapshot
+        .change_logs
+        .iter()
+        .filter(|change_log| change_log.seq <= *indexed_seq)
+    {
+        for (level, hash) in change_log.path.iter().enumerate() {
+            let level = level as u32;
+            let node_idx = (1 << (snapshot.max_depth - level)) + (change_log.index as u64 >> level);
+            expected.insert(node_idx, (level, *hash));
+        }
+        expected.insert(1, (snapshot.max_depth, change_log.root));
+    }
+
+    // The rightmost proof and the canopy hold the nodes as of the account
+    if *indexed_seq == snapshot.seq {
+        for node in snapshot.nodes() {
+            expected
+                .entry(node.node_idx)
+                .or_insert((node.level, node.hash));
+        }
+    }
+
+    let mut mismatched = expected
+        .iter()
+        .filter(|(node_idx, (level, hash))| match indexed.get(node_idx) {
+            Some((_, indexed_hash)) => indexed_hash.as_slice() != hash.as_slice(),
+            // Nodes of empty subtrees are not indexed
+            None => *hash != Node::default() && *hash != empty_node(*level),
+        })
+        .map(|(node_idx, _)| *node_idx)
+        .collect::<Vec<_>>();
+    mismatched.sort_unstable();
+
+    if mismatched.is_empty() {
+        TreeIntegrity::Verified {
+            checked_nodes: expected.len(),
+        }
+    } else {
+        TreeIntegrity::Mismatch { nodes: mismatched }
+    }
+}
+
+/// Deletes the nodes, audits and backfill checkpoints of `tree` along with their spill files in
+/// `spill_dir`, so that its next backfill replays it from its first transaction.
+pub async fn reset_tree_index(
+    conn: &DatabaseConnection,
+    tree: Pubkey,
+    spill_dir: &Path,
+) -> Result<()> {
+    let checkpoints = BackfillCheckpoint::find(conn, tree).await?;
+
+    let txn = conn.begin().await?;
+    for sql in RESET_TREE_SQL {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            sql,
+            vec![Value::Bytes(Some(Box::new(tree.to_bytes().to_vec())))],
+        ))
+        .await?;
+    }
+    txn.commit().await?;
+
+    for checkpoint in checkpoints {
+        match tokio::fs::remove_file(checkpoint.spill_path(spill_dir)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                error!("remove spill file: {:?}", e)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::TreeChangeLog;
+
+    fn change_log(seq: u64, index: u32, root: u8, path: Vec<u8>) -> TreeChangeLog {
+        TreeChangeLog {
+            seq,
+            index,
+            root: [root; 32],
+            path: path.into_iter().map(|node| [node; 32]).collect(),
+        }
+    }
+
+    fn indexed(nodes: &[(u64, u64, u8)]) -> HashMap<u64, (u64, Vec<u8>)> {
+        nodes
+            .iter()
+            .map(|(node_idx, seq, hash)| (*node_idx, (*seq, vec![*hash; 32])))
+            .collect()
+    }
+
+    // A tree of depth 2 whose leaves 0 and 1 were appended at seqs 1 and 2
+    fn snapshot() -> TreeSnapshot {
+        TreeSnapshot {
+            tree: Pubkey::new_unique(),
+            max_depth: 2,
+            seq: 2,
+            change_logs: vec![
+                change_log(1, 0, 10, vec![1, 11]),
+                change_log(2, 1, 20, vec![2, 12]),
+            ],
+            num_leaves: 2,
+            rightmost_leaf: [2; 32],
+            rightmost_proof: vec![[1; 32], empty_node(1)],
+            canopy: vec![],
+        }
+    }
+
+    #[test]
+    fn verifies_the_nodes_the_account_knows() {
+        let snapshot = snapshot();
+
+        assert_eq!(
+            compare(
+                &snapshot,
+                &indexed(&[(1, 2, 20), (2, 2, 12), (4, 1, 1), (5, 2, 2)])
+            ),
+            TreeIntegrity::Verified { checked_nodes: 5 }
+        );
+
+        // Lagging by a seq, the nodes changed at seq 2 can't be checked yet
+        assert_eq!(
+            compare(&snapshot, &indexed(&[(1, 1, 10), (2, 1, 11), (4, 1, 1)])),
+            TreeIntegrity::Verified { checked_nodes: 3 }
+        );
+    }
+
+    #[test]
+    fn reports_the_nodes_that_differ() {
+        let snapshot = snapshot();
+
+        // A node missing the change of seq 2, a missing leaf and a wrong sibling
+        assert_eq!(
+            compare(
+                &snapshot,
+                &indexed(&[(1, 2, 20), (2, 1, 11), (3, 2, 9), (4, 1, 1)])
+            ),
+            TreeIntegrity::Mismatch {
+                nodes: vec![2, 3, 5]
+            }
+        );
+
+        // The index can't be compared past the account or the buffer
+        assert!(matches!(
+            compare(&snapshot, &indexed(&[(1, 3, 30)])),
+            TreeIntegrity::Unverifiable { .. }
+        ));
+        assert!(matches!(
+            compare(&snapshot, &indexed(&[])),
+            TreeIntegrity::Unverifiable { .. }
+        ));
+        let snapshot = TreeSnapshot {
+            change_logs: vec![change_log(2, 1, 20, vec![2, 12])],
+            ..snapshot
+        };
+        assert!(matches!(
+            compare(&snapshot, &indexed(&[(1, 1, 10)])),
+            TreeIntegrity::Unverifiable { .. }
+        ));
+    }
+}
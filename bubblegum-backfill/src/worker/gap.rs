@@ -1,17 +1,26 @@
+use std::path::PathBuf;
+use std::pin::pin;
+
 use anyhow::Result;
 use clap::Parser;
 use das_core::Rpc;
-use futures::{stream::FuturesUnordered, StreamExt};
+use futures::{stream, StreamExt};
 use log::error;
+use sea_orm::{DatabaseConnection, SqlxPostgresConnector};
 use solana_sdk::signature::Signature;
 use tokio::{
     sync::mpsc::{channel, Sender},
     task::JoinHandle,
 };
+use tokio_util::sync::CancellationToken;
 
-use crate::gap::TreeGapFill;
+use crate::checkpoint::BackfillCheckpoint;
+use crate::gap::{CrawlLowerBound, TreeGapFill};
+use crate::spill::SignatureSpill;
 use crate::BubblegumBackfillContext;
 
+const CRAWL_CHANNEL_SIZE: usize = 1000;
+
 #[derive(Parser, Debug, Clone)]
 pub struct GapWorkerArgs {
     /// The size of the signature channel.
@@ -21,45 +30,162 @@ pub struct GapWorkerArgs {
     /// The number of gap workers.
     #[arg(long, env, default_value = "25")]
     pub gap_worker_count: usize,
+
+    /// The number of crawled signatures of a gap kept in memory before spilling to disk. Crawls
+    /// resume from the last spilled signature after a restart.
+    #[arg(long, env, default_value = "100000")]
+    pub gap_spill_threshold: usize,
+
+    /// The directory spilled signatures are written to, created if missing. It needs to survive
+    /// restarts for backfills to resume, relative paths are resolved from the working directory.
+    #[arg(long, env, default_value = "gap-spill")]
+    pub gap_spill_dir: PathBuf,
+}
+
+/// The signatures crawled for a gap, along with its checkpoint.
+pub struct CrawledGap {
+    pub checkpoint: BackfillCheckpoint,
+    pub spill_path: PathBuf,
+    pub spill: SignatureSpill,
 }
 
 impl GapWorkerArgs {
+    /// Crawls the gaps received concurrently, down to `lower_bound`, and forwards their signatures
+    /// in the order the gaps were received, so they can be applied in chain order.
+    ///
+    /// Once `shutdown` is cancelled, the crawls record their progress and nothing is forwarded.
     pub fn start(
         &self,
         context: BubblegumBackfillContext,
-        forward: Sender<Signature>,
-    ) -> Result<(JoinHandle<()>, Sender<TreeGapFill>)> {
-        let (gap_sender, mut gap_receiver) = channel::<TreeGapFill>(self.gap_channel_size);
+        forward: Sender<CrawledGap>,
+        lower_bound: CrawlLowerBound,
+        shutdown: CancellationToken,
+    ) -> Result<(JoinHandle<()>, Sender<BackfillCheckpoint>)> {
+        let (gap_sender, gap_receiver) = channel::<BackfillCheckpoint>(self.gap_channel_size);
         let gap_worker_count = self.gap_worker_count;
+        let spill_threshold = self.gap_spill_threshold;
+        let spill_dir = self.gap_spill_dir.clone();
+        std::fs::create_dir_all(&spill_dir)?;
 
         let handler = tokio::spawn(async move {
-            let mut handlers = FuturesUnordered::new();
-            let sender = forward.clone();
+            let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool);
 
-            while let Some(gap) = gap_receiver.recv().await {
-                if handlers.len() >= gap_worker_count {
-                    handlers.next().await;
-                }
-
-                let client = context.solana_rpc.clone();
-                let sender = sender.clone();
+            let gaps = stream::unfold(gap_receiver, |mut gap_receiver| async move {
+                let checkpoint = gap_receiver.recv().await?;
+                Some((checkpoint, gap_receiver))
+            })
+            .map(|checkpoint| {
+                let spill_path = checkpoint.spill_path(&spill_dir);
 
-                let handle = spawn_crawl_worker(client, sender, gap);
+                crawl_gap(
+                    context.solana_rpc.clone(),
+                    &conn,
+                    checkpoint,
+                    spill_path,
+                    spill_threshold,
+                    lower_bound,
+                    &shutdown,
+                )
+            })
+            .buffered(gap_worker_count);
+            let mut gaps = pin!(gaps);
 
-                handlers.push(handle);
+            while let Some(gap) = gaps.next().await {
+                match gap {
+                    // Left to its checkpoint
+                    Ok(_) if shutdown.is_cancelled() => {}
+                    Ok(gap) => {
+                        if forward.send(gap).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => error!("crawl gap: {:?}", e),
+                }
             }
-
-            futures::future::join_all(handlers).await;
         });
 
         Ok((handler, gap_sender))
     }
 }
 
-fn spawn_crawl_worker(client: Rpc, sender: Sender<Signature>, gap: TreeGapFill) -> JoinHandle<()> {
-    tokio::spawn(async move {
-        if let Err(e) = gap.crawl(client, sender).await {
-            error!("tree transaction: {:?}", e);
+/// Crawls what is left of the gap of `checkpoint`, down to `lower_bound`, into its spill file.
+///
+/// The checkpoint records the crawl cursor every time the spill is flushed. A failed or
+/// interrupted crawl keeps its checkpoint so it resumes from the cursor on the next backfill
+/// instead of being applied partially.
+async fn crawl_gap(
+    client: Rpc,
+    conn: &DatabaseConnection,
+    mut checkpoint: BackfillCheckpoint,
+    spill_path: PathBuf,
+    spill_threshold: usize,
+    lower_bound: CrawlLowerBound,
+    shutdown: &CancellationToken,
+) -> Result<CrawledGap> {
+    let mut spill = if checkpoint.crawled > 0 {
+        match SignatureSpill::open(
+            &spill_path,
+            spill_threshold,
+            checkpoint.crawled as u64,
+            checkpoint.applied as u64,
+        )
+        .await
+        {
+            Ok(spill) => spill,
+            Err(e) => {
+                error!("reopen spill, crawling the gap again: {:?}", e);
+
+                checkpoint.reset(conn).await?;
+                SignatureSpill::create(&spill_path, spill_threshold).await?
+            }
         }
+    } else {
+        SignatureSpill::create(&spill_path, spill_threshold).await?
+    };
+
+    if !checkpoint.crawl_complete {
+        let gap = TreeGapFill::try_from(&checkpoint)?.with_lower_bound(lower_bound);
+        let (sender, mut receiver) = channel::<Signature>(CRAWL_CHANNEL_SIZE);
+
+        let crawl = tokio::spawn(async move { gap.crawl(client, sender).await });
+
+        let mut cursor = None;
+        loop {
+            let signature = tokio::select! {
+                signature = receiver.recv() => signature,
+                _ = shutdown.cancelled() => break,
+            };
+            let Some(signature) = signature else {
+                break;
+            };
+            cursor = Some(signature);
+
+            if spill.push(signature).await? {
+                checkpoint
+                    .record_crawl(conn, cursor, spill.pushed(), false)
+                    .await?;
+            }
+        }
+
+        let crawled = if shutdown.is_cancelled() {
+            crawl.abort();
+            Err(anyhow::anyhow!("crawl interrupted by shutdown"))
+        } else {
+            crawl.await?
+        };
+
+        // Keep what was crawled so far, the crawl resumes from the last flushed signature
+        spill.flush().await?;
+        checkpoint
+            .record_crawl(conn, cursor, spill.pushed(), crawled.is_ok())
+            .await?;
+
+        crawled?;
+    }
+
+    Ok(CrawledGap {
+        checkpoint,
+        spill_path,
+        spill,
     })
 }
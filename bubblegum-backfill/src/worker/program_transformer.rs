@@ -2,49 +2,270 @@ use anyhow::Result;
 use clap::Parser;
 use das_core::{create_download_metadata_notifier, DownloadMetadataInfo};
 use log::error;
+use program_transformers::error::ProgramTransformerError;
 use program_transformers::{ProgramTransformer, TransactionInfo};
+use sea_orm::SqlxPostgresConnector;
+use solana_sdk::pubkey::Pubkey;
 use tokio::sync::mpsc::{channel, Sender, UnboundedSender};
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
+use super::transaction::TransactionWindow;
+use crate::dead_letter::{DeadLetter, DeadLetterErrorKind};
 use crate::BubblegumBackfillContext;
 
 #[derive(Parser, Debug, Clone)]
 pub struct ProgramTransformerWorkerArgs {
-    #[arg(long, env, default_value = "100000")]
+    /// The number of transaction windows buffered ahead of the program transformer.
+    #[arg(long, env, default_value = "4")]
     pub program_transformer_channel_size: usize,
 }
 
 impl ProgramTransformerWorkerArgs {
+    /// Applies the windows received and records them in their checkpoint. Transactions that fail
+    /// to be applied are recorded as dead letters.
+    ///
+    /// Once `shutdown` is cancelled, the window being applied is finished and the ones queued are
+    /// left to their checkpoint.
     pub fn start(
         &self,
         context: BubblegumBackfillContext,
+        tree: Pubkey,
         forwarder: UnboundedSender<DownloadMetadataInfo>,
-    ) -> Result<(JoinHandle<()>, Sender<TransactionInfo>)> {
+        shutdown: CancellationToken,
+    ) -> Result<(JoinHandle<()>, Sender<TransactionWindow>)> {
         let (sender, mut receiver) =
-            channel::<TransactionInfo>(self.program_transformer_channel_size);
+            channel::<TransactionWindow>(self.program_transformer_channel_size);
 
         let handle = tokio::spawn(async move {
-            let mut transactions = Vec::new();
             let pool = context.database_pool.clone();
+            let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool);
 
             let download_metadata_notifier = create_download_metadata_notifier(forwarder).await;
 
             let program_transformer =
                 ProgramTransformer::new(pool, download_metadata_notifier, true);
 
-            while let Some(gap) = receiver.recv().await {
-                transactions.push(gap);
-            }
+            while let Some(mut window) = receiver.recv().await {
+                if shutdown.is_cancelled() {
+                    break;
+                }
+
+                sort_transactions(&program_transformer, &tree, &mut window.transactions);
+
+                let last_applied_seq = window
+                    .transactions
+                    .iter()
+                    .flat_map(|transaction| program_transformer.change_log_seqs(transaction, &tree))
+                    .max();
 
-            transactions.sort_by(|a, b| b.signature.cmp(&a.signature));
+                for transaction in &window.transactions {
+                    match program_transformer.handle_transaction(transaction).await {
+                        Ok(()) | Err(ProgramTransformerError::NotImplemented) => {}
+                        Err(e) => {
+                            error!("handle transaction: {:?}", e);
 
-            for transaction in transactions {
-                if let Err(e) = program_transformer.handle_transaction(&transaction).await {
-                    error!("handle transaction: {:?}", e)
-                };
+                            if let Err(e) = DeadLetter::record(
+                                &conn,
+                                tree,
+                                transaction.signature,
+                                Some(transaction.slot),
+                                DeadLetterErrorKind::Index,
+                                &e.to_string(),
+                            )
+                            .await
+                            {
+                                error!("record dead letter: {:?}", e);
+                            }
+                        }
+                    }
+                }
+
+                if window.last {
+                    if let Err(e) = window.checkpoint.delete(&conn).await {
+                        error!("delete backfill checkpoint: {:?}", e);
+                    }
+                    if let Err(e) = tokio::fs::remove_file(&window.spill_path).await {
+                        error!("remove spill file: {:?}", e);
+                    }
+                } else if let Err(e) = window
+                    .checkpoint
+                    .record_applied(&conn, window.applied, last_applied_seq)
+                    .await
+                {
+                    error!("record backfill checkpoint: {:?}", e);
+                }
             }
         });
 
         Ok((handle, sender))
     }
 }
+
+/// Sorts `transactions` in the order they were applied to `tree`.
+///
+/// Transactions are ordered by slot, then by the first change log seq they emitted for the tree,
+/// which orders the transactions of a slot. The signature only breaks ties between transactions
+/// without change logs, so that replays are deterministic.
+fn sort_transactions(
+    program_transformer: &ProgramTransformer,
+    tree: &Pubkey,
+    transactions: &mut [TransactionInfo],
+) {
+    transactions.sort_by_cached_key(|transaction| {
+        (
+            transaction.slot,
+            program_transformer
+                .change_log_seqs(transaction, tree)
+                .into_iter()
+                .min(),
+            transaction.signature,
+        )
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::transaction::FetchedEncodedTransactionWithStatusMeta;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use futures::FutureExt;
+    use solana_sdk::signature::Signature;
+    use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+    use spl_account_compression::events::{AccountCompressionEvent, ChangeLogEvent};
+    use sqlx::postgres::PgPoolOptions;
+
+    fn program_transformer() -> ProgramTransformer {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/das")
+            .unwrap();
+
+        ProgramTransformer::new(
+            pool,
+            Box::new(|_info| futures::future::ready(Ok(())).boxed()),
+            false,
+        )
+    }
+
+    fn load_fixture(name: &str) -> TransactionInfo {
+        let path = format!(
+            "{}/../blockbuster/tests/fixtures/{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        let transaction: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        FetchedEncodedTransactionWithStatusMeta(transaction)
+            .try_into()
+            .unwrap()
+    }
+
+    /// Replays `transaction` as another update of the same tree, landing at `slot` with its change
+    /// logs renumbered from `seq`.
+    fn replay(
+        transaction: &TransactionInfo,
+        signature: u8,
+        slot: u64,
+        seq: u64,
+    ) -> TransactionInfo {
+        let mut replayed = transaction.clone();
+        let mut seqs = seq..;
+
+        replayed.slot = slot;
+        replayed.signature = Signature::from([signature; 64]);
+
+        for instruction in replayed
+            .meta_inner_instructions
+            .iter_mut()
+            .flat_map(|inner| inner.instructions.iter_mut())
+        {
+            let data = &mut instruction.instruction.data;
+
+            if let Ok(AccountCompressionEvent::ChangeLog(ChangeLogEvent::V1(mut change_log))) =
+                AccountCompressionEvent::try_from_slice(data)
+            {
+                change_log.seq = seqs.next().unwrap();
+                *data = AccountCompressionEvent::ChangeLog(ChangeLogEvent::V1(change_log))
+                    .try_to_vec()
+                    .unwrap();
+            }
+        }
+
+        replayed
+    }
+
+    fn change_log_tree(transaction: &TransactionInfo) -> Pubkey {
+        transaction
+            .meta_inner_instructions
+            .iter()
+            .flat_map(|inner| inner.instructions.iter())
+            .find_map(|instruction| {
+                match AccountCompressionEvent::try_from_slice(&instruction.instruction.data) {
+                    Ok(AccountCompressionEvent::ChangeLog(ChangeLogEvent::V1(change_log))) => {
+                        Some(change_log.id)
+                    }
+                    _ => None,
+                }
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn extracts_change_log_seqs_from_fixtures() {
+        let program_transformer = program_transformer();
+
+        for name in [
+            "double_bubblegum_mint",
+            "helium_mint_double_tree",
+            "helium_nested",
+        ] {
+            let transaction = load_fixture(name);
+            let tree = change_log_tree(&transaction);
+            let seqs = program_transformer.change_log_seqs(&transaction, &tree);
+
+            assert!(!seqs.is_empty(), "{name} has no change log for {tree}");
+            assert!(
+                seqs.windows(2).all(|seqs| seqs[0] <= seqs[1]),
+                "{name} emitted seqs out of order {seqs:?}"
+            );
+            assert!(program_transformer
+                .change_log_seqs(&transaction, &Pubkey::new_unique())
+                .is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn sorts_by_slot_then_change_log_seq() {
+        let program_transformer = program_transformer();
+        let mint = load_fixture("double_bubblegum_mint");
+        let tree = change_log_tree(&mint);
+
+        // Signatures are ascending in chain order, sorting on them alone reverses or shuffles
+        // the updates of a slot
+        let expected = vec![
+            replay(&mint, 1, 100, 8),
+            replay(&mint, 4, 101, 10),
+            replay(&mint, 2, 101, 12),
+            replay(&mint, 3, 101, 14),
+            replay(&mint, 0, 102, 16),
+        ];
+
+        let mut transactions = expected.clone();
+        transactions.reverse();
+        transactions.swap(1, 3);
+
+        sort_transactions(&program_transformer, &tree, &mut transactions);
+
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|transaction| (transaction.slot, transaction.signature))
+                .collect::<Vec<_>>(),
+            expected
+                .iter()
+                .map(|transaction| (transaction.slot, transaction.signature))
+                .collect::<Vec<_>>()
+        );
+    }
+}
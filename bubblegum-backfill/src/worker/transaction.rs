@@ -1,10 +1,14 @@
+use super::gap::CrawledGap;
+use crate::checkpoint::BackfillCheckpoint;
+use crate::dead_letter::{DeadLetter, DeadLetterErrorKind};
 use crate::error::ErrorKind;
 use anyhow::Result;
 use clap::Parser;
 use das_core::Rpc;
-use futures::{stream::FuturesUnordered, StreamExt};
+use futures::{stream, StreamExt};
 use log::error;
 use program_transformers::TransactionInfo;
+use sea_orm::SqlxPostgresConnector;
 use solana_program::pubkey::Pubkey;
 use solana_sdk::instruction::CompiledInstruction;
 use solana_sdk::signature::Signature;
@@ -13,10 +17,12 @@ use solana_transaction_status::{
     option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
     InnerInstruction, InnerInstructions, UiInstruction,
 };
+use std::path::PathBuf;
 use tokio::{
     sync::mpsc::{channel, Sender},
     task::JoinHandle,
 };
+use tokio_util::sync::CancellationToken;
 
 pub struct PubkeyString(pub String);
 
@@ -140,6 +146,17 @@ impl TryFrom<FetchedEncodedTransactionWithStatusMeta> for TransactionInfo {
     }
 }
 
+/// Transactions of a window of a crawled gap, oldest first.
+pub struct TransactionWindow {
+    pub checkpoint: BackfillCheckpoint,
+    pub spill_path: PathBuf,
+    /// Oldest signatures of the spill file read up to and including this window.
+    pub applied: u64,
+    /// Whether this is the last window of the gap.
+    pub last: bool,
+    pub transactions: Vec<TransactionInfo>,
+}
+
 #[derive(Parser, Clone, Debug)]
 pub struct SignatureWorkerArgs {
     /// The size of the signature channel.
@@ -148,62 +165,124 @@ pub struct SignatureWorkerArgs {
     /// The number of transaction workers.
     #[arg(long, env, default_value = "50")]
     pub signature_worker_count: usize,
+    /// The number of transactions fetched and applied together.
+    #[arg(long, env, default_value = "1000")]
+    pub signature_window_size: usize,
 }
 
 impl SignatureWorkerArgs {
+    /// Fetches the transactions of every received gap oldest first, forwarding them in windows of
+    /// `signature_window_size` so that only a window per worker stage is held in memory.
+    ///
+    /// Every gap read back entirely ends with a `last` window, possibly empty. No more windows are
+    /// forwarded once `shutdown` is cancelled. Transactions that fail to be fetched or decoded are
+    /// recorded as dead letters.
     pub fn start(
         &self,
         context: crate::BubblegumBackfillContext,
-        forwarder: Sender<TransactionInfo>,
-    ) -> Result<(JoinHandle<()>, Sender<Signature>)> {
-        let (sig_sender, mut sig_receiver) = channel::<Signature>(self.signature_channel_size);
+        forwarder: Sender<TransactionWindow>,
+        shutdown: CancellationToken,
+    ) -> Result<(JoinHandle<()>, Sender<CrawledGap>)> {
+        let (gap_sender, mut gap_receiver) = channel::<CrawledGap>(self.signature_channel_size);
         let worker_count = self.signature_worker_count;
+        let window_size = self.signature_window_size;
 
         let handle = tokio::spawn(async move {
-            let mut handlers = FuturesUnordered::new();
+            let conn =
+                SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+
+            while let Some(CrawledGap {
+                checkpoint,
+                spill_path,
+                mut spill,
+            }) = gap_receiver.recv().await
+            {
+                let tree = match Pubkey::try_from(checkpoint.tree.as_slice()) {
+                    Ok(tree) => tree,
+                    Err(e) => {
+                        error!("checkpoint tree: {:?}", e);
+                        continue;
+                    }
+                };
+
+                loop {
+                    let signatures = match spill.next_window(window_size).await {
+                        Ok(signatures) => signatures,
+                        Err(e) => {
+                            // The checkpoint is kept, the gap is read again on the next backfill
+                            error!("read spilled signatures: {:?}", e);
+                            break;
+                        }
+                    };
+                    let last = signatures.is_empty();
+
+                    let transactions = stream::iter(signatures)
+                        .map(|signature| fetch_transaction(context.solana_rpc.clone(), signature))
+                        .buffered(worker_count)
+                        .filter_map(|transaction| {
+                            let conn = &conn;
+
+                            async move {
+                                let (signature, error_kind, e) = match transaction {
+                                    Ok(transaction) => return Some(transaction),
+                                    Err(e) => e,
+                                };
+
+                                error!("queue transaction: {:?}", e);
+                                // The error kinds only describe themselves, their sources the cause
+                                let e = format!("{:#}", anyhow::Error::from(e));
+                                if let Err(e) =
+                                    DeadLetter::record(conn, tree, signature, None, error_kind, &e)
+                                        .await
+                                {
+                                    error!("record dead letter: {:?}", e);
+                                }
+
+                                None
+                            }
+                        })
+                        .collect::<Vec<_>>();
+
+                    // The checkpoint is kept, the window is read again on the next backfill
+                    let transactions = tokio::select! {
+                        transactions = transactions => transactions,
+                        _ = shutdown.cancelled() => return,
+                    };
+
+                    let window = TransactionWindow {
+                        checkpoint: checkpoint.clone(),
+                        spill_path: spill_path.clone(),
+                        applied: spill.pushed() - spill.len(),
+                        last,
+                        transactions,
+                    };
+
+                    if forwarder.send(window).await.is_err() {
+                        return;
+                    }
 
-            while let Some(signature) = sig_receiver.recv().await {
-                if handlers.len() >= worker_count {
-                    handlers.next().await;
+                    if last {
+                        break;
+                    }
                 }
-
-                let solana_rpc = context.solana_rpc.clone();
-                let transaction_sender = forwarder.clone();
-
-                let handle = spawn_transaction_worker(solana_rpc, transaction_sender, signature);
-
-                handlers.push(handle);
             }
-
-            futures::future::join_all(handlers).await;
         });
 
-        Ok((handle, sig_sender))
+        Ok((handle, gap_sender))
     }
 }
 
-async fn queue_transaction<'a>(
+/// Fetches and decodes `signature`, failing with the step that failed.
+async fn fetch_transaction(
     client: Rpc,
-    sender: Sender<TransactionInfo>,
     signature: Signature,
-) -> Result<(), ErrorKind> {
-    let transaction = client.get_transaction(&signature).await?;
-    sender
-        .send(FetchedEncodedTransactionWithStatusMeta(transaction).try_into()?)
+) -> Result<TransactionInfo, (Signature, DeadLetterErrorKind, ErrorKind)> {
+    let transaction = client
+        .get_transaction(&signature)
         .await
-        .map_err(|e| ErrorKind::Generic(e.to_string()))?;
-
-    Ok(())
-}
+        .map_err(|e| (signature, DeadLetterErrorKind::Fetch, e.into()))?;
 
-fn spawn_transaction_worker(
-    client: Rpc,
-    sender: Sender<TransactionInfo>,
-    signature: Signature,
-) -> JoinHandle<()> {
-    tokio::spawn(async move {
-        if let Err(e) = queue_transaction(client, sender, signature).await {
-            error!("queue transaction: {:?}", e);
-        }
-    })
+    FetchedEncodedTransactionWithStatusMeta(transaction)
+        .try_into()
+        .map_err(|e| (signature, DeadLetterErrorKind::Parse, e))
 }
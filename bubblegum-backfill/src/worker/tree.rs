@@ -1,5 +1,7 @@
 use crate::{
-    gap::{TreeGapFill, TreeGapModel},
+    checkpoint::BackfillCheckpoint,
+    gap::{CrawlLowerBound, TreeGapFill, TreeGapModel},
+    snapshot::TreeSnapshot,
     tree::TreeResponse,
     BubblegumBackfillContext,
 };
@@ -8,9 +10,13 @@ use clap::Parser;
 use das_core::MetadataJsonDownloadWorkerArgs;
 use digital_asset_types::dao::cl_audits_v2;
 use log::error;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, SqlxPostgresConnector};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, SqlxPostgresConnector,
+};
 use solana_sdk::signature::Signature;
+use tokio::sync::mpsc::unbounded_channel;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 use super::{GapWorkerArgs, ProgramTransformerWorkerArgs, SignatureWorkerArgs};
 
@@ -27,12 +33,26 @@ pub struct TreeWorkerArgs {
 
     #[clap(flatten)]
     pub program_transformer_worker: ProgramTransformerWorkerArgs,
+
+    /// Only backfills the transactions landed at or after this slot.
+    #[arg(long, env)]
+    pub backfill_from_slot: Option<u64>,
+
+    /// Only backfills the transactions landed after this signature.
+    #[arg(long, env)]
+    pub backfill_after_signature: Option<Signature>,
+
+    /// Leaves the metadata JSON of the backfilled assets to download, they stay flagged for
+    /// `reindex`.
+    #[arg(long, env)]
+    pub skip_metadata_json_download: bool,
 }
 impl TreeWorkerArgs {
     pub fn start(
         &self,
         context: BubblegumBackfillContext,
         tree: TreeResponse,
+        shutdown: CancellationToken,
     ) -> JoinHandle<Result<()>> {
         let db_pool = context.database_pool.clone();
         let metadata_json_download_db_pool = context.database_pool.clone();
@@ -44,57 +64,65 @@ impl TreeWorkerArgs {
         let program_transformer_worker_args = self.program_transformer_worker.clone();
         let signature_worker_args = self.signature_worker.clone();
         let gap_worker_args = self.gap_worker.clone();
+        let backfill_from_slot = self.backfill_from_slot;
+        let backfill_after_signature = self.backfill_after_signature;
+        let skip_metadata_json_download = self.skip_metadata_json_download;
 
         tokio::spawn(async move {
-            let (metadata_json_download_worker, metadata_json_download_sender) =
-                metadata_json_download_worker_args.start(metadata_json_download_db_pool)?;
-
-            let (program_transformer_worker, transaction_info_sender) =
-                program_transformer_worker_args
-                    .start(program_transformer_context, metadata_json_download_sender)?;
-
-            let (signature_worker, signature_sender) =
-                signature_worker_args.start(signature_context, transaction_info_sender)?;
+            let lower_bound =
+                crawl_lower_bound(&context, backfill_from_slot, backfill_after_signature).await?;
+
+            // The history before the lower bound is not replayed, the nodes the tree account
+            // still holds stand in for it
+            if lower_bound != CrawlLowerBound::default() {
+                let snapshot = TreeSnapshot::fetch(&context.solana_rpc, tree.pubkey).await?;
+                let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(db_pool.clone());
+                snapshot.save(&conn).await?;
+            }
 
-            let (gap_worker, tree_gap_sender) = gap_worker_args.start(context, signature_sender)?;
+            let (metadata_json_download_worker, metadata_json_download_sender) =
+                if skip_metadata_json_download {
+                    let (sender, mut receiver) = unbounded_channel();
+                    let discard =
+                        tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+
+                    (discard, sender)
+                } else {
+                    metadata_json_download_worker_args.start(metadata_json_download_db_pool)?
+                };
+
+            let (program_transformer_worker, transaction_window_sender) =
+                program_transformer_worker_args.start(
+                    program_transformer_context,
+                    tree.pubkey,
+                    metadata_json_download_sender,
+                    shutdown.clone(),
+                )?;
+
+            let (signature_worker, crawled_gap_sender) = signature_worker_args.start(
+                signature_context,
+                transaction_window_sender,
+                shutdown.clone(),
+            )?;
+
+            let (gap_worker, tree_gap_sender) =
+                gap_worker_args.start(context, crawled_gap_sender, lower_bound, shutdown)?;
 
             {
                 let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(db_pool);
 
-                let mut gaps = TreeGapModel::find(&conn, tree.pubkey)
-                    .await?
-                    .into_iter()
-                    .map(TryInto::try_into)
-                    .collect::<Result<Vec<_>, _>>()?;
-
-                let upper_known_seq = cl_audits_v2::Entity::find()
-                    .filter(cl_audits_v2::Column::Tree.eq(tree.pubkey.as_ref().to_vec()))
-                    .order_by_desc(cl_audits_v2::Column::Seq)
-                    .one(&conn)
-                    .await?;
-
-                let lower_known_seq = cl_audits_v2::Entity::find()
-                    .filter(cl_audits_v2::Column::Tree.eq(tree.pubkey.as_ref().to_vec()))
-                    .order_by_asc(cl_audits_v2::Column::Seq)
-                    .one(&conn)
-                    .await?;
-
-                if let Some(upper_seq) = upper_known_seq {
-                    let signature = Signature::try_from(upper_seq.tx.as_ref())?;
-
-                    gaps.push(TreeGapFill::new(tree.pubkey, None, Some(signature)));
-                } else if tree.seq > 0 {
-                    gaps.push(TreeGapFill::new(tree.pubkey, None, None));
-                }
-
-                if let Some(lower_seq) = lower_known_seq.filter(|seq| seq.seq > 1) {
-                    let signature = Signature::try_from(lower_seq.tx.as_ref())?;
+                // Resume the gaps of an interrupted backfill before looking for new ones, which
+                // would overlap with them
+                let mut checkpoints = BackfillCheckpoint::find(&conn, tree.pubkey).await?;
 
-                    gaps.push(TreeGapFill::new(tree.pubkey, Some(signature), None));
+                if checkpoints.is_empty() {
+                    for gap in find_gaps(&conn, &tree).await? {
+                        checkpoints.push(BackfillCheckpoint::create(&conn, &gap).await?);
+                    }
                 }
 
-                for gap in gaps {
-                    if let Err(e) = tree_gap_sender.send(gap).await {
+                for checkpoint in checkpoints {
+                    if let Err(e) = tree_gap_sender.send(checkpoint).await {
                         error!("send gap: {:?}", e);
                     }
                 }
@@ -113,3 +141,63 @@ impl TreeWorkerArgs {
         })
     }
 }
+
+/// The lower bound of the crawls, the slot of `after_signature` bounds them as well so that the
+/// gaps that never reach the signature stop at its slot.
+async fn crawl_lower_bound(
+    context: &BubblegumBackfillContext,
+    from_slot: Option<u64>,
+    after_signature: Option<Signature>,
+) -> Result<CrawlLowerBound> {
+    let signature_slot = match after_signature {
+        Some(signature) => Some(context.solana_rpc.get_transaction(&signature).await?.slot),
+        None => None,
+    };
+
+    Ok(CrawlLowerBound {
+        slot: from_slot.max(signature_slot),
+        signature: after_signature,
+    })
+}
+
+/// The gaps between the change logs of `tree` in `cl_audits_v2` and its ends, oldest first.
+async fn find_gaps(conn: &DatabaseConnection, tree: &TreeResponse) -> Result<Vec<TreeGapFill>> {
+    let inner_gaps = TreeGapModel::find(conn, tree.pubkey)
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let upper_known_seq = cl_audits_v2::Entity::find()
+        .filter(cl_audits_v2::Column::Tree.eq(tree.pubkey.as_ref().to_vec()))
+        .order_by_desc(cl_audits_v2::Column::Seq)
+        .one(conn)
+        .await?;
+
+    let lower_known_seq = cl_audits_v2::Entity::find()
+        .filter(cl_audits_v2::Column::Tree.eq(tree.pubkey.as_ref().to_vec()))
+        .order_by_asc(cl_audits_v2::Column::Seq)
+        .one(conn)
+        .await?;
+
+    // Gaps are applied in the order they are queued, so queue them oldest first
+    let mut gaps = Vec::with_capacity(inner_gaps.len() + 2);
+
+    if let Some(lower_seq) = lower_known_seq.filter(|seq| seq.seq > 1) {
+        let signature = Signature::try_from(lower_seq.tx.as_ref())?;
+
+        gaps.push(TreeGapFill::new(tree.pubkey, Some(signature), None));
+    }
+
+    gaps.extend(inner_gaps);
+
+    if let Some(upper_seq) = upper_known_seq {
+        let signature = Signature::try_from(upper_seq.tx.as_ref())?;
+
+        gaps.push(TreeGapFill::new(tree.pubkey, None, Some(signature)));
+    } else if tree.seq > 0 {
+        gaps.push(TreeGapFill::new(tree.pubkey, None, None));
+    }
+
+    Ok(gaps)
+}
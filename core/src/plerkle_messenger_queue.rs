@@ -2,8 +2,8 @@ use anyhow::Result;
 use clap::Parser;
 use figment::value::{Dict, Value};
 use plerkle_messenger::{
-    Messenger, MessengerConfig, MessengerType, ACCOUNT_BACKFILL_STREAM, ACCOUNT_STREAM,
-    TRANSACTION_BACKFILL_STREAM, TRANSACTION_STREAM,
+    ConsumptionType, Messenger, MessengerConfig, MessengerType, RecvData, ACCOUNT_BACKFILL_STREAM,
+    ACCOUNT_STREAM, TRANSACTION_BACKFILL_STREAM, TRANSACTION_STREAM,
 };
 use std::num::TryFromIntError;
 use std::sync::Arc;
@@ -18,6 +18,12 @@ pub struct QueueArgs {
     pub messenger_redis_batch_size: String,
     #[arg(long, env, default_value = "25")]
     pub messenger_queue_connections: u64,
+    #[arg(long, env)]
+    pub messenger_consumer_group_name: Option<String>,
+    #[arg(long, env)]
+    pub messenger_consumer_id: Option<String>,
+    #[arg(long, env)]
+    pub messenger_idle_timeout_ms: Option<u64>,
 }
 
 impl From<QueueArgs> for MessengerConfig {
@@ -37,6 +43,21 @@ impl From<QueueArgs> for MessengerConfig {
             Value::from(1u128.to_string()),
         );
 
+        if let Some(consumer_group_name) = args.messenger_consumer_group_name {
+            connection_config.insert(
+                "consumer_group_name".to_string(),
+                Value::from(consumer_group_name),
+            );
+        }
+
+        if let Some(consumer_id) = args.messenger_consumer_id {
+            connection_config.insert("consumer_id".to_string(), Value::from(consumer_id));
+        }
+
+        if let Some(idle_timeout_ms) = args.messenger_idle_timeout_ms {
+            connection_config.insert("idle_timeout".to_string(), Value::from(idle_timeout_ms));
+        }
+
         Self {
             messenger_type: MessengerType::Redis,
             connection_config,
@@ -164,6 +185,85 @@ impl QueuePool {
         self.push(TRANSACTION_STREAM, bytes).await
     }
 
+    /// Receives transaction data from the appropriate stream.
+    ///
+    /// This method reads the next batch of messages from the `TRANSACTION_STREAM`,
+    /// including messages delivered earlier that were not acknowledged in time.
+    /// Every received message must be acknowledged with `ack_transactions`.
+    ///
+    /// # Returns
+    ///
+    /// This method returns a `Result` which is `Ok` with the received messages,
+    /// possibly none, or an `Err` with a `QueuePoolError` if the read fails.
+    pub async fn recv_transactions(&self) -> Result<Vec<RecvData>, QueuePoolError> {
+        self.recv(TRANSACTION_STREAM).await
+    }
+
+    /// Receives account data from the appropriate stream.
+    ///
+    /// This method reads the next batch of messages from the `ACCOUNT_STREAM`,
+    /// including messages delivered earlier that were not acknowledged in time.
+    /// Every received message must be acknowledged with `ack_accounts`.
+    ///
+    /// # Returns
+    ///
+    /// This method returns a `Result` which is `Ok` with the received messages,
+    /// possibly none, or an `Err` with a `QueuePoolError` if the read fails.
+    pub async fn recv_accounts(&self) -> Result<Vec<RecvData>, QueuePoolError> {
+        self.recv(ACCOUNT_STREAM).await
+    }
+
+    /// Acknowledges messages received from the `TRANSACTION_STREAM`.
+    ///
+    /// Acknowledged messages are removed from the stream and are not redelivered.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The ids of the received messages to acknowledge.
+    pub async fn ack_transactions(&self, ids: &[String]) -> Result<(), QueuePoolError> {
+        self.ack(TRANSACTION_STREAM, ids).await
+    }
+
+    /// Acknowledges messages received from the `ACCOUNT_STREAM`.
+    ///
+    /// Acknowledged messages are removed from the stream and are not redelivered.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The ids of the received messages to acknowledge.
+    pub async fn ack_accounts(&self, ids: &[String]) -> Result<(), QueuePoolError> {
+        self.ack(ACCOUNT_STREAM, ids).await
+    }
+
+    async fn recv(&self, stream_key: &'static str) -> Result<Vec<RecvData>, QueuePoolError> {
+        let mut messenger = self.take_messenger().await?;
+
+        let messages = messenger.recv(stream_key, ConsumptionType::All).await;
+
+        self.tx.send(messenger).await?;
+
+        Ok(messages?)
+    }
+
+    async fn ack(&self, stream_key: &'static str, ids: &[String]) -> Result<(), QueuePoolError> {
+        let mut messenger = self.take_messenger().await?;
+
+        let acked = messenger.ack_msg(stream_key, ids).await;
+
+        self.tx.send(messenger).await?;
+
+        Ok(acked?)
+    }
+
+    async fn take_messenger(&self) -> Result<Box<dyn Messenger>, QueuePoolError> {
+        self.rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or(QueuePoolError::RecvMessengerConnection)
+    }
+
     async fn push(&self, stream_key: &'static str, bytes: &[u8]) -> Result<(), QueuePoolError> {
         let mut rx = self.rx.lock().await;
         let mut messenger = rx
@@ -28,11 +28,28 @@ pub struct SolanaRpcArgs {
 }
 
 #[derive(Clone)]
-pub struct Rpc(Arc<RpcClient>);
+pub struct Rpc {
+    client: Arc<RpcClient>,
+    commitment: CommitmentConfig,
+}
 
 impl Rpc {
     pub fn from_config(config: &SolanaRpcArgs) -> Self {
-        Rpc(Arc::new(RpcClient::new(config.solana_rpc_url.clone())))
+        Rpc {
+            client: Arc::new(RpcClient::new(config.solana_rpc_url.clone())),
+            commitment: CommitmentConfig {
+                commitment: CommitmentLevel::Finalized,
+            },
+        }
+    }
+
+    /// Returns a handle sharing the same underlying client that queries at `commitment`
+    /// instead of the default `Finalized`.
+    pub fn with_commitment(&self, commitment: CommitmentConfig) -> Self {
+        Rpc {
+            client: Arc::clone(&self.client),
+            commitment,
+        }
     }
 
     pub async fn get_transaction(
@@ -40,15 +57,13 @@ impl Rpc {
         signature: &Signature,
     ) -> Result<EncodedConfirmedTransactionWithStatusMeta, ClientError> {
         (|| async {
-            self.0
+            self.client
                 .get_transaction_with_config(
                     signature,
                     RpcTransactionConfig {
                         encoding: Some(UiTransactionEncoding::Base58),
                         max_supported_transaction_version: Some(0),
-                        commitment: Some(CommitmentConfig {
-                            commitment: CommitmentLevel::Finalized,
-                        }),
+                        commitment: Some(self.commitment),
                     },
                 )
                 .await
@@ -64,15 +79,13 @@ impl Rpc {
         until: Option<Signature>,
     ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, ClientError> {
         (|| async {
-            self.0
+            self.client
                 .get_signatures_for_address_with_config(
                     pubkey,
                     GetConfirmedSignaturesForAddress2Config {
                         before,
                         until,
-                        commitment: Some(CommitmentConfig {
-                            commitment: CommitmentLevel::Finalized,
-                        }),
+                        commitment: Some(self.commitment),
                         ..GetConfirmedSignaturesForAddress2Config::default()
                     },
                 )
@@ -90,14 +103,12 @@ impl Rpc {
         ClientError,
     > {
         (|| async {
-            self.0
+            self.client
                 .get_account_with_config(
                     pubkey,
                     RpcAccountInfoConfig {
                         encoding: Some(UiAccountEncoding::Base64),
-                        commitment: Some(CommitmentConfig {
-                            commitment: CommitmentLevel::Finalized,
-                        }),
+                        commitment: Some(self.commitment),
                         ..RpcAccountInfoConfig::default()
                     },
                 )
@@ -115,16 +126,14 @@ impl Rpc {
         (|| async {
             let filters = filters.clone();
 
-            self.0
+            self.client
                 .get_program_accounts_with_config(
                     program,
                     RpcProgramAccountsConfig {
                         filters,
                         account_config: RpcAccountInfoConfig {
                             encoding: Some(UiAccountEncoding::Base64),
-                            commitment: Some(CommitmentConfig {
-                                commitment: CommitmentLevel::Finalized,
-                            }),
+                            commitment: Some(self.commitment),
                             ..RpcAccountInfoConfig::default()
                         },
                         ..RpcProgramAccountsConfig::default()
@@ -140,21 +149,29 @@ impl Rpc {
         &self,
         pubkeys: &[Pubkey],
     ) -> Result<Vec<Option<Account>>, ClientError> {
-        Ok((|| async {
-            self.0
+        Ok(self
+            .get_multiple_accounts_with_context(pubkeys)
+            .await?
+            .value)
+    }
+
+    /// Like `get_multiple_accounts`, along with the slot the accounts were read at.
+    pub async fn get_multiple_accounts_with_context(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> Result<solana_client::rpc_response::Response<Vec<Option<Account>>>, ClientError> {
+        (|| async {
+            self.client
                 .get_multiple_accounts_with_config(
                     pubkeys,
                     RpcAccountInfoConfig {
-                        commitment: Some(CommitmentConfig {
-                            commitment: CommitmentLevel::Finalized,
-                        }),
+                        commitment: Some(self.commitment),
                         ..RpcAccountInfoConfig::default()
                     },
                 )
                 .await
         })
         .retry(&ExponentialBuilder::default())
-        .await?
-        .value)
+        .await
     }
 }
@@ -11,6 +11,7 @@ pub use super::asset_v1_account_attachments::Entity as AssetV1AccountAttachments
 pub use super::backfill_items::Entity as BackfillItems;
 pub use super::cl_audits_v2::Entity as ClAuditsV2;
 pub use super::cl_items::Entity as ClItems;
+pub use super::ld_merkle_trees::Entity as LdMerkleTrees;
 pub use super::raw_txn::Entity as RawTxn;
 pub use super::tasks::Entity as Tasks;
 pub use super::token_accounts::Entity as TokenAccounts;
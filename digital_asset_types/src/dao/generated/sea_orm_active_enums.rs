@@ -163,3 +163,19 @@ pub enum Instruction {
     #[sea_orm(string_value = "verify_creator")]
     VerifyCreator,
 }
+#[derive(Debug, Clone, PartialEq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "ld_merkle_tree_status"
+)]
+pub enum LdMerkleTreeStatus {
+    #[sea_orm(string_value = "backfilling")]
+    Backfilling,
+    #[sea_orm(string_value = "failed")]
+    Failed,
+    #[sea_orm(string_value = "indexing")]
+    Indexing,
+    #[sea_orm(string_value = "pending")]
+    Pending,
+}
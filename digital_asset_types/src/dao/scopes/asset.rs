@@ -521,7 +521,7 @@ pub async fn get_asset_signatures(
     }
 }
 
-fn filter_out_stale_creators(creators: &mut Vec<asset_creators::Model>) {
+pub(crate) fn filter_out_stale_creators(creators: &mut Vec<asset_creators::Model>) {
     // If the first creator is an empty Vec, it means the creator array is empty (which is allowed
     // for compressed assets in Bubblegum).
     if !creators.is_empty() && creators[0].creator.is_empty() {
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::dao::scopes::asset::filter_out_stale_creators;
+use crate::dao::sea_orm_active_enums::ChainMutability;
+use crate::dao::{asset, asset_creators, asset_data, asset_grouping};
+use crate::json::ChainDataV1;
+use blockbuster::token_metadata::types::UseMethod as TokenMetadataUseMethod;
+use mpl_bubblegum::hash::{hash_creators, hash_metadata};
+use mpl_bubblegum::types::{
+    Collection, Creator, LeafSchema, MetadataArgs, TokenProgramVersion, TokenStandard, UseMethod,
+    Uses,
+};
+use sea_orm::{entity::*, query::*, DatabaseConnection, DbErr};
+use solana_sdk::pubkey::Pubkey;
+
+/// Compressed assets checked per statement.
+const ASSETS_PER_BATCH: u64 = 1000;
+
+/// Indexed columns of an asset that no longer hash to its leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeafDrift {
+    /// The id, owner, delegate and nonce, with the stored data and creator hashes, don't hash to
+    /// the stored leaf.
+    Ownership,
+    /// The `asset_data` row, royalty, collection and creators don't hash to the data hash.
+    Metadata,
+    /// The `asset_creators` rows don't hash to the creator hash.
+    Creators,
+}
+
+impl fmt::Display for LeafDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ownership => write!(f, "ownership"),
+            Self::Metadata => write!(f, "metadata"),
+            Self::Creators => write!(f, "creators"),
+        }
+    }
+}
+
+/// A compressed asset whose indexed rows drifted from its leaf.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftedAsset {
+    pub id: Vec<u8>,
+    pub nonce: Option<i64>,
+    pub leaf: Option<Vec<u8>>,
+    /// The leaf the asset columns hash to, `None` when some of them are missing.
+    pub computed_leaf: Option<[u8; 32]>,
+    pub drifts: Vec<LeafDrift>,
+}
+
+/// Recomputes the Bubblegum leaf of `asset` from its indexed rows, along with its data and
+/// creator hashes, returning the asset as drifted when they differ from the stored ones.
+///
+/// `creators` are all the creator rows of the asset, stale ones included, and `groups` its
+/// groupings, unverified ones included.
+///
+/// Redeemed assets are skipped, their leaf and hashes are cleared until they are decompressed.
+pub fn check_asset_leaf(
+    asset: &asset::Model,
+    data: Option<&asset_data::Model>,
+    creators: &[asset_creators::Model],
+    groups: &[asset_grouping::Model],
+) -> Option<DriftedAsset> {
+    if asset
+        .leaf
+        .as_deref()
+        .is_some_and(|leaf| leaf.iter().all(|byte| *byte == 0))
+    {
+        return None;
+    }
+
+    let decode =
+        |hash: &str| -> Option<[u8; 32]> { bs58::decode(hash).into_vec().ok()?.try_into().ok() };
+    let data_hash = asset.data_hash.as_deref().and_then(decode);
+    let creator_hash = asset.creator_hash.as_deref().and_then(decode);
+
+    let computed_leaf = data_hash
+        .zip(creator_hash)
+        .and_then(|(data_hash, creator_hash)| leaf_schema(asset, data_hash, creator_hash))
+        .map(|schema| schema.hash());
+
+    let mut creators = creators.to_vec();
+    filter_out_stale_creators(&mut creators);
+    let creators = creators
+        .iter()
+        .map(|creator| {
+            Some(Creator {
+                address: Pubkey::try_from(creator.creator.as_slice()).ok()?,
+                verified: creator.verified,
+                share: u8::try_from(creator.share).ok()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>();
+
+    let computed_creator_hash = creators.as_deref().map(hash_creators);
+    let computed_data_hash = data
+        .zip(creators)
+        .and_then(|(data, creators)| metadata_args(asset, data, groups, creators))
+        .and_then(|metadata| hash_metadata(&metadata).ok());
+
+    let mut drifts = vec![];
+    if computed_leaf.is_none()
+        || computed_leaf.as_ref().map(|leaf| &leaf[..]) != asset.leaf.as_deref()
+    {
+        drifts.push(LeafDrift::Ownership);
+    }
+    if computed_data_hash.is_none() || computed_data_hash != data_hash {
+        drifts.push(LeafDrift::Metadata);
+    }
+    if computed_creator_hash.is_none() || computed_creator_hash != creator_hash {
+        drifts.push(LeafDrift::Creators);
+    }
+
+    (!drifts.is_empty()).then(|| DriftedAsset {
+        id: asset.id.clone(),
+        nonce: asset.nonce,
+        leaf: asset.leaf.clone(),
+        computed_leaf,
+        drifts,
+    })
+}
+
+fn leaf_schema(
+    asset: &asset::Model,
+    data_hash: [u8; 32],
+    creator_hash: [u8; 32],
+) -> Option<LeafSchema> {
+    let owner = Pubkey::try_from(asset.owner.as_deref()?).ok()?;
+    // The delegate is only stored when it isn't the owner
+    let delegate = match &asset.delegate {
+        Some(delegate) => Pubkey::try_from(delegate.as_slice()).ok()?,
+        None => owner,
+    };
+
+    Some(LeafSchema::V1 {
+        id: Pubkey::try_from(asset.id.as_slice()).ok()?,
+        owner,
+        delegate,
+        nonce: u64::try_from(asset.nonce?).ok()?,
+        data_hash,
+        creator_hash,
+    })
+}
+
+/// The metadata of a compressed asset as passed to Bubblegum, rebuilt from its rows.
+fn metadata_args(
+    asset: &asset::Model,
+    data: &asset_data::Model,
+    groups: &[asset_grouping::Model],
+    creators: Vec<Creator>,
+) -> Option<MetadataArgs> {
+    let chain_data: ChainDataV1 = serde_json::from_value(data.chain_data.clone()).ok()?;
+    // The chain data strips the null bytes of the name and symbol, which are hashed as they are
+    let name = match &data.raw_name {
+        Some(name) => String::from_utf8(name.clone()).ok()?,
+        None => chain_data.name,
+    };
+    let symbol = match &data.raw_symbol {
+        Some(symbol) => String::from_utf8(symbol.clone()).ok()?,
+        None => chain_data.symbol,
+    };
+
+    let collection = match groups.iter().find(|group| group.group_key == "collection") {
+        Some(asset_grouping::Model {
+            group_value: Some(key),
+            verified,
+            ..
+        }) => Some(Collection {
+            verified: *verified,
+            key: Pubkey::from_str(key).ok()?,
+        }),
+        _ => None,
+    };
+
+    Some(MetadataArgs {
+        name,
+        symbol,
+        uri: data.metadata_url.clone(),
+        seller_fee_basis_points: u16::try_from(asset.royalty_amount).ok()?,
+        primary_sale_happened: chain_data.primary_sale_happened,
+        is_mutable: data.chain_data_mutability == ChainMutability::Mutable,
+        edition_nonce: chain_data.edition_nonce,
+        // Bubblegum only mints non fungibles of the original token program
+        token_standard: Some(TokenStandard::NonFungible),
+        collection,
+        uses: chain_data.uses.map(|uses| Uses {
+            use_method: match uses.use_method {
+                TokenMetadataUseMethod::Burn => UseMethod::Burn,
+                TokenMetadataUseMethod::Multiple => UseMethod::Multiple,
+                TokenMetadataUseMethod::Single => UseMethod::Single,
+            },
+            remaining: uses.remaining,
+            total: uses.total,
+        }),
+        token_program_version: TokenProgramVersion::Original,
+        creators,
+    })
+}
+
+/// Checks the leaf of every compressed asset of `tree_id` against its indexed rows, returning
+/// the drifted ones ordered by nonce.
+pub async fn get_drifted_assets_for_tree(
+    db: &DatabaseConnection,
+    tree_id: Vec<u8>,
+) -> Result<Vec<DriftedAsset>, DbErr> {
+    let mut drifted = vec![];
+    let mut last_nonce = -1;
+
+    loop {
+        let assets = asset::Entity::find()
+            .filter(asset::Column::TreeId.eq(tree_id.clone()))
+            .filter(asset::Column::Compressed.eq(true))
+            .filter(asset::Column::Nonce.gt(last_nonce))
+            .order_by_asc(asset::Column::Nonce)
+            .limit(ASSETS_PER_BATCH)
+            .find_also_related(asset_data::Entity)
+            .all(db)
+            .await?;
+
+        let Some(nonce) = assets.last().and_then(|(asset, _)| asset.nonce) else {
+            break;
+        };
+        last_nonce = nonce;
+
+        let ids = assets
+            .iter()
+            .map(|(asset, _)| asset.id.clone())
+            .collect::<Vec<_>>();
+
+        let mut creators: HashMap<Vec<u8>, Vec<asset_creators::Model>> = HashMap::new();
+        for creator in asset_creators::Entity::find()
+            .filter(asset_creators::Column::AssetId.is_in(ids.clone()))
+            .order_by_asc(asset_creators::Column::AssetId)
+            .order_by_asc(asset_creators::Column::Position)
+            .all(db)
+            .await?
+        {
+            creators
+                .entry(creator.asset_id.clone())
+                .or_default()
+                .push(creator);
+        }
+
+        let mut groups: HashMap<Vec<u8>, Vec<asset_grouping::Model>> = HashMap::new();
+        for group in asset_grouping::Entity::find()
+            .filter(asset_grouping::Column::AssetId.is_in(ids))
+            .all(db)
+            .await?
+        {
+            groups
+                .entry(group.asset_id.clone())
+                .or_default()
+                .push(group);
+        }
+
+        drifted.extend(assets.iter().filter_map(|(asset, data)| {
+            check_asset_leaf(
+                asset,
+                data.as_ref(),
+                creators.get(&asset.id).map_or(&[], Vec::as_slice),
+                groups.get(&asset.id).map_or(&[], Vec::as_slice),
+            )
+        }));
+
+        if (assets.len() as u64) < ASSETS_PER_BATCH {
+            break;
+        }
+    }
+
+    Ok(drifted)
+}
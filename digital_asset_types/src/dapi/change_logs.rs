@@ -6,7 +6,10 @@ use {
     crate::dao::cl_items,
     crate::rpc::AssetProof,
     sea_orm::{entity::*, query::*, DbErr, FromQueryResult},
-    spl_concurrent_merkle_tree::node::empty_node,
+    spl_concurrent_merkle_tree::{
+        hash::recompute,
+        node::{empty_node, Node},
+    },
 };
 
 #[derive(FromQueryResult, Debug, Default, Clone, Eq, PartialEq)]
@@ -33,6 +36,40 @@ struct Leaf {
     leaf_idx: i64,
 }
 
+/// A proof whose leaf and nodes don't hash up to its root.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidProof {
+    /// Index of the leaf in the tree.
+    pub leaf_idx: i64,
+    pub proof: AssetProof,
+    /// The root the leaf and the nodes hash up to, `None` when they aren't 32 bytes long.
+    pub computed_root: Option<String>,
+}
+
+/// Checks that the leaf and the nodes of `proof` hash up to its root, returning it as invalid
+/// otherwise.
+pub fn check_asset_proof(proof: &AssetProof) -> Option<InvalidProof> {
+    let decode =
+        |hash: &str| -> Option<Node> { bs58::decode(hash).into_vec().ok()?.try_into().ok() };
+    let leaf_idx = proof.node_index - (1 << proof.proof.len());
+
+    let computed_root = decode(&proof.leaf)
+        .zip(
+            proof
+                .proof
+                .iter()
+                .map(|node| decode(node))
+                .collect::<Option<Vec<_>>>(),
+        )
+        .map(|(leaf, nodes)| bs58::encode(recompute(leaf, &nodes, leaf_idx as u32)).into_string());
+
+    (computed_root.as_ref() != Some(&proof.root)).then(|| InvalidProof {
+        leaf_idx,
+        proof: proof.clone(),
+        computed_root,
+    })
+}
+
 pub async fn get_proof_for_asset(
     db: &DatabaseConnection,
     asset_id: Vec<u8>,
@@ -254,3 +291,64 @@ pub fn get_required_nodes_for_proof(index: i64) -> Vec<i64> {
     indexes.push(1);
     indexes
 }
+
+/// Drops the nodes of `proof` a canopy of `canopy_depth` holds, the ones closest to the root,
+/// which are not passed to the instructions of the tree.
+pub fn truncate_canopy(proof: &mut AssetProof, canopy_depth: u32) {
+    let len = proof.proof.len().saturating_sub(canopy_depth as usize);
+    proof.proof.truncate(len);
+}
+
+/// Checks the proof of every leaf of `tree_id` against its root, returning the invalid ones
+/// ordered by leaf index.
+pub async fn get_invalid_proofs_for_tree(
+    db: &DatabaseConnection,
+    tree_id: Vec<u8>,
+) -> Result<Vec<InvalidProof>, DbErr> {
+    let nodes: Vec<SimpleChangeLog> = cl_items::Entity::find()
+        .select_only()
+        .column(cl_items::Column::NodeIdx)
+        .column(cl_items::Column::Hash)
+        .column(cl_items::Column::Level)
+        .column(cl_items::Column::Seq)
+        .column(cl_items::Column::Tree)
+        .filter(cl_items::Column::Tree.eq(tree_id.clone()))
+        .order_by_asc(cl_items::Column::Seq)
+        .order_by_asc(cl_items::Column::Id)
+        .into_model()
+        .all(db)
+        .await?;
+
+    // map: node_idx -> newest SimpleChangeLog
+    let node_map: HashMap<i64, SimpleChangeLog> = nodes
+        .into_iter()
+        .map(|node| (node.node_idx, node))
+        .collect();
+
+    let mut leaves = node_map
+        .values()
+        .filter(|node| node.level == 0)
+        .collect::<Vec<_>>();
+    leaves.sort_by_key(|leaf| leaf.node_idx);
+
+    Ok(leaves
+        .into_iter()
+        .filter_map(|leaf| {
+            let req_indexes = get_required_nodes_for_proof(leaf.node_idx);
+            let required_nodes: Vec<SimpleChangeLog> = req_indexes
+                .iter()
+                .filter_map(|n| node_map.get(n).cloned())
+                .collect();
+
+            let asset_proof = build_asset_proof(
+                tree_id.clone(),
+                leaf.node_idx,
+                leaf.hash.clone(),
+                &req_indexes,
+                &required_nodes,
+            );
+
+            check_asset_proof(&asset_proof)
+        })
+        .collect())
+}
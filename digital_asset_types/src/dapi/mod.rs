@@ -1,3 +1,4 @@
+mod asset_leaves;
 mod assets_by_authority;
 mod assets_by_creator;
 mod assets_by_group;
@@ -9,6 +10,7 @@ mod search_assets;
 
 pub mod common;
 
+pub use asset_leaves::*;
 pub use assets_by_authority::*;
 pub use assets_by_creator::*;
 pub use assets_by_group::*;
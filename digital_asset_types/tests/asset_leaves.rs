@@ -0,0 +1,263 @@
+#[cfg(test)]
+mod common;
+
+use blockbuster::token_metadata::types::{Creator, TokenStandard};
+use common::*;
+use digital_asset_types::dao::sea_orm_active_enums::*;
+use digital_asset_types::dao::{asset, asset_creators, asset_data, asset_grouping};
+use digital_asset_types::dapi::{check_asset_leaf, get_drifted_assets_for_tree, LeafDrift};
+use digital_asset_types::json::ChainDataV1;
+use mpl_bubblegum::hash::{hash_creators, hash_metadata};
+use mpl_bubblegum::types::{
+    Collection, Creator as BubblegumCreator, LeafSchema, MetadataArgs, TokenProgramVersion,
+    TokenStandard as BubblegumTokenStandard,
+};
+use sea_orm::{entity::prelude::*, DatabaseBackend, MockDatabase};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+
+#[derive(Clone)]
+struct CompressedAsset {
+    asset: asset::Model,
+    data: asset_data::Model,
+    creators: Vec<asset_creators::Model>,
+    groups: Vec<asset_grouping::Model>,
+}
+
+/// A compressed asset minted to a verified collection, with the leaf and hashes Bubblegum
+/// computes for it.
+fn compressed_asset(tree: Pubkey, nonce: u64) -> CompressedAsset {
+    let id = Keypair::new().pubkey();
+    let owner = Keypair::new().pubkey();
+    let creator = Keypair::new().pubkey();
+    let collection = Keypair::new().pubkey();
+
+    let metadata = MockMetadataArgs {
+        name: format!("Test #{}", nonce),
+        symbol: String::from("BUBBLE"),
+        uri: format!("https://example.com/{}.json", nonce),
+        primary_sale_happened: false,
+        is_mutable: true,
+        edition_nonce: Some(1),
+        token_standard: Some(TokenStandard::NonFungible),
+        collection: None,
+        uses: None,
+        creators: vec![Creator {
+            address: creator,
+            share: 100,
+            verified: true,
+        }],
+        seller_fee_basis_points: 500,
+    };
+
+    let args = MetadataArgs {
+        name: metadata.name.clone(),
+        symbol: metadata.symbol.clone(),
+        uri: metadata.uri.clone(),
+        seller_fee_basis_points: metadata.seller_fee_basis_points,
+        primary_sale_happened: metadata.primary_sale_happened,
+        is_mutable: metadata.is_mutable,
+        edition_nonce: metadata.edition_nonce,
+        token_standard: Some(BubblegumTokenStandard::NonFungible),
+        collection: Some(Collection {
+            verified: true,
+            key: collection,
+        }),
+        uses: None,
+        token_program_version: TokenProgramVersion::Original,
+        creators: vec![BubblegumCreator {
+            address: creator,
+            verified: true,
+            share: 100,
+        }],
+    };
+    let data_hash = hash_metadata(&args).unwrap();
+    let creator_hash = hash_creators(&args.creators);
+    let leaf = LeafSchema::V1 {
+        id,
+        owner,
+        delegate: owner,
+        nonce,
+        data_hash,
+        creator_hash,
+    }
+    .hash();
+
+    let (_, asset) = create_asset(
+        id.to_bytes().to_vec(),
+        owner.to_bytes().to_vec(),
+        OwnerType::Single,
+        None,
+        false,
+        1,
+        None,
+        true,
+        false,
+        Some(tree.to_bytes().to_vec()),
+        Some(SpecificationVersions::V1),
+        Some(nonce as i64),
+        Some(leaf.to_vec()),
+        RoyaltyTargetType::Creators,
+        None,
+        metadata.seller_fee_basis_points as i32,
+    );
+    let (_, data) = create_asset_data(metadata.clone(), id.to_bytes().to_vec());
+    let (_, creator) = create_asset_creator(
+        id.to_bytes().to_vec(),
+        creator.to_bytes().to_vec(),
+        100,
+        true,
+        1,
+    );
+    let (_, group) = create_asset_grouping(id.to_bytes().to_vec(), collection, 1);
+
+    CompressedAsset {
+        asset: asset::Model {
+            data_hash: Some(bs58::encode(data_hash).into_string()),
+            creator_hash: Some(bs58::encode(creator_hash).into_string()),
+            ..asset
+        },
+        data: asset_data::Model {
+            chain_data: serde_json::to_value(ChainDataV1 {
+                name: metadata.name,
+                symbol: metadata.symbol,
+                edition_nonce: metadata.edition_nonce,
+                primary_sale_happened: metadata.primary_sale_happened,
+                token_standard: metadata.token_standard,
+                uses: None,
+            })
+            .unwrap(),
+            metadata_url: metadata.uri,
+            ..data
+        },
+        creators: vec![creator],
+        groups: vec![asset_grouping::Model {
+            verified: true,
+            ..group
+        }],
+    }
+}
+
+fn check(asset: &CompressedAsset) -> Option<Vec<LeafDrift>> {
+    check_asset_leaf(
+        &asset.asset,
+        Some(&asset.data),
+        &asset.creators,
+        &asset.groups,
+    )
+    .map(|drifted| drifted.drifts)
+}
+
+#[test]
+fn recomputes_the_leaf_from_the_asset_rows() {
+    let asset = compressed_asset(Keypair::new().pubkey(), 3);
+    assert_eq!(check(&asset), None);
+
+    // The creators left by a previous update are stale
+    let current = asset_creators::Model {
+        seq: Some(5),
+        slot_updated: Some(10),
+        ..asset.creators[0].clone()
+    };
+    let stale = asset_creators::Model {
+        position: 1,
+        creator: Keypair::new().pubkey().to_bytes().to_vec(),
+        seq: Some(2),
+        slot_updated: Some(4),
+        ..current.clone()
+    };
+    let updated = CompressedAsset {
+        creators: vec![current, stale],
+        ..asset
+    };
+    assert_eq!(check(&updated), None);
+}
+
+#[test]
+fn reports_the_columns_that_drifted_from_the_leaf() {
+    let asset = compressed_asset(Keypair::new().pubkey(), 3);
+
+    let transferred = CompressedAsset {
+        asset: asset::Model {
+            owner: Some(Keypair::new().pubkey().to_bytes().to_vec()),
+            ..asset.asset.clone()
+        },
+        ..asset.clone()
+    };
+    assert_eq!(check(&transferred), Some(vec![LeafDrift::Ownership]));
+
+    let renamed = CompressedAsset {
+        data: asset_data::Model {
+            raw_name: Some(b"Renamed".to_vec()),
+            ..asset.data.clone()
+        },
+        ..asset.clone()
+    };
+    assert_eq!(check(&renamed), Some(vec![LeafDrift::Metadata]));
+
+    let unverified = CompressedAsset {
+        creators: vec![asset_creators::Model {
+            verified: false,
+            ..asset.creators[0].clone()
+        }],
+        ..asset.clone()
+    };
+    assert_eq!(
+        check(&unverified),
+        Some(vec![LeafDrift::Metadata, LeafDrift::Creators])
+    );
+
+    assert_eq!(
+        check_asset_leaf(&asset.asset, None, &asset.creators, &asset.groups)
+            .map(|drifted| drifted.drifts),
+        Some(vec![LeafDrift::Metadata])
+    );
+}
+
+#[test]
+fn skips_redeemed_assets() {
+    let asset = compressed_asset(Keypair::new().pubkey(), 3);
+
+    // Redeeming clears the leaf and hashes of the asset until it is decompressed
+    let redeemed = CompressedAsset {
+        asset: asset::Model {
+            leaf: Some(vec![0; 32]),
+            data_hash: Some(bs58::encode([0; 32]).into_string()),
+            creator_hash: Some(bs58::encode([0; 32]).into_string()),
+            ..asset.asset.clone()
+        },
+        ..asset
+    };
+    assert_eq!(check(&redeemed), None);
+}
+
+#[tokio::test]
+async fn lists_the_drifted_assets_of_a_tree() -> Result<(), DbErr> {
+    let tree = Keypair::new().pubkey();
+    let asset = compressed_asset(tree, 0);
+    let moved = compressed_asset(tree, 1);
+    let moved = CompressedAsset {
+        asset: asset::Model {
+            nonce: Some(2),
+            ..moved.asset
+        },
+        ..moved
+    };
+
+    let db = MockDatabase::new(DatabaseBackend::Postgres)
+        .append_query_results(vec![vec![
+            (asset.asset.clone(), asset.data.clone()),
+            (moved.asset.clone(), moved.data.clone()),
+        ]])
+        .append_query_results(vec![
+            [asset.creators.clone(), moved.creators.clone()].concat()
+        ])
+        .append_query_results(vec![[asset.groups.clone(), moved.groups.clone()].concat()])
+        .into_connection();
+
+    let drifted = get_drifted_assets_for_tree(&db, tree.to_bytes().to_vec()).await?;
+    assert_eq!(drifted.len(), 1);
+    assert_eq!(drifted[0].id, moved.asset.id);
+    assert_eq!(drifted[0].drifts, vec![LeafDrift::Ownership]);
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use digital_asset_types::dao::cl_items;
+use digital_asset_types::dapi::{check_asset_proof, get_invalid_proofs_for_tree, truncate_canopy};
+use digital_asset_types::rpc::AssetProof;
+use sea_orm::{entity::prelude::*, DatabaseBackend, MockDatabase};
+use solana_sdk::{signature::Keypair, signer::Signer};
+use spl_concurrent_merkle_tree::concurrent_merkle_tree::ConcurrentMerkleTree;
+use spl_concurrent_merkle_tree::hash::hash_to_parent;
+use spl_concurrent_merkle_tree::node::{empty_node, Node};
+
+fn parent(left: Node, right: Node) -> Node {
+    let mut node = left;
+    hash_to_parent(&mut node, &right, true);
+    node
+}
+
+fn encode(node: &[u8]) -> String {
+    bs58::encode(node).into_string()
+}
+
+/// The nodes of a tree of depth 3 holding leaves `[1; 32]`, `[2; 32]` and `[3; 32]`, by node
+/// index.
+fn tree_nodes() -> Vec<(i64, i64, Node)> {
+    let leaves = [[1; 32], [2; 32], [3; 32]];
+    let node_4 = parent(leaves[0], leaves[1]);
+    let node_5 = parent(leaves[2], empty_node(0));
+    let node_2 = parent(node_4, node_5);
+    let root = parent(node_2, empty_node(2));
+
+    vec![
+        (1, 3, root),
+        (2, 2, node_2),
+        (4, 1, node_4),
+        (5, 1, node_5),
+        (8, 0, leaves[0]),
+        (9, 0, leaves[1]),
+        (10, 0, leaves[2]),
+    ]
+}
+
+fn cl_items(tree: &[u8], nodes: &[(i64, i64, Node)]) -> Vec<cl_items::Model> {
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(id, (node_idx, level, hash))| cl_items::Model {
+            id: id as i64,
+            tree: tree.to_vec(),
+            node_idx: *node_idx,
+            leaf_idx: (*level == 0).then_some(node_idx - 8),
+            seq: 3,
+            level: *level,
+            hash: hash.to_vec(),
+        })
+        .collect()
+}
+
+#[test]
+fn checks_that_the_proof_hashes_up_to_the_root() {
+    let mut tree = ConcurrentMerkleTree::<3, 8>::new();
+    tree.initialize().unwrap();
+    for leaf in 1..=3 {
+        tree.append([leaf; 32]).unwrap();
+    }
+
+    // The proof of leaf 1, whose sibling is leaf 0 and whose uncle holds leaf 2
+    let proof = AssetProof {
+        root: encode(&tree.get_root()),
+        proof: vec![
+            encode(&[1; 32]),
+            encode(&parent([3; 32], empty_node(0))),
+            encode(&empty_node(2)),
+        ],
+        node_index: 9,
+        leaf: encode(&[2; 32]),
+        tree_id: encode(&[0; 32]),
+    };
+    assert_eq!(check_asset_proof(&proof), None);
+
+    let stale = AssetProof {
+        proof: vec![
+            encode(&[1; 32]),
+            encode(&empty_node(1)),
+            encode(&empty_node(2)),
+        ],
+        ..proof.clone()
+    };
+    let invalid = check_asset_proof(&stale).unwrap();
+    assert_eq!(invalid.leaf_idx, 1);
+    assert!(invalid.computed_root.is_some_and(|root| root != proof.root));
+
+    let truncated = AssetProof {
+        leaf: encode(&[2; 31]),
+        ..proof
+    };
+    assert_eq!(check_asset_proof(&truncated).unwrap().computed_root, None);
+}
+
+#[test]
+fn leaves_out_the_nodes_held_by_the_canopy() {
+    let mut proof = AssetProof {
+        root: encode(&[0; 32]),
+        proof: vec![encode(&[1; 32]), encode(&[2; 32]), encode(&[3; 32])],
+        node_index: 9,
+        leaf: encode(&[4; 32]),
+        tree_id: encode(&[5; 32]),
+    };
+
+    truncate_canopy(&mut proof, 0);
+    assert_eq!(proof.proof.len(), 3);
+
+    // The nodes closest to the root are in the canopy
+    truncate_canopy(&mut proof, 1);
+    assert_eq!(proof.proof, vec![encode(&[1; 32]), encode(&[2; 32])]);
+
+    truncate_canopy(&mut proof, 5);
+    assert!(proof.proof.is_empty());
+}
+
+#[tokio::test]
+async fn lists_the_invalid_proofs_of_a_tree() -> Result<(), DbErr> {
+    let tree = Keypair::new().pubkey().to_bytes();
+
+    let mut broken = tree_nodes();
+    broken[6].2 = [4; 32];
+
+    let db = MockDatabase::new(DatabaseBackend::Postgres)
+        .append_query_results(vec![cl_items(&tree, &tree_nodes())])
+        .append_query_results(vec![cl_items(&tree, &broken)])
+        .into_connection();
+
+    assert!(get_invalid_proofs_for_tree(&db, tree.to_vec())
+        .await?
+        .is_empty());
+
+    let invalid = get_invalid_proofs_for_tree(&db, tree.to_vec()).await?;
+    assert_eq!(
+        invalid
+            .iter()
+            .map(|invalid| invalid.leaf_idx)
+            .collect::<Vec<_>>(),
+        vec![2]
+    );
+    assert_eq!(invalid[0].proof.leaf, encode(&[4; 32]));
+
+    Ok(())
+}
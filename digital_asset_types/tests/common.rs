@@ -201,6 +201,7 @@ pub fn create_asset_creator(
     )
 }
 
+#[allow(dead_code)]
 pub fn create_asset_authority(
     asset_id: Vec<u8>,
     update_authority: Vec<u8>,
@@ -1,12 +1,28 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20261018_000001_create_ld_merkle_trees;
+mod m20261018_000002_create_ld_backfill_checkpoints;
+mod m20261018_000003_add_ld_merkle_trees_metadata;
+mod m20261018_000004_notify_ld_merkle_trees_changes;
+mod m20261018_000005_create_ld_dead_letters;
+mod m20261018_000006_add_ld_merkle_trees_settings;
+
+pub use m20261018_000004_notify_ld_merkle_trees_changes::LD_MERKLE_TREES_CHANNEL;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261018_000001_create_ld_merkle_trees::Migration),
+            Box::new(m20261018_000002_create_ld_backfill_checkpoints::Migration),
+            Box::new(m20261018_000003_add_ld_merkle_trees_metadata::Migration),
+            Box::new(m20261018_000004_notify_ld_merkle_trees_changes::Migration),
+            Box::new(m20261018_000005_create_ld_dead_letters::Migration),
+            Box::new(m20261018_000006_add_ld_merkle_trees_settings::Migration),
+        ]
     }
 }
@@ -0,0 +1,185 @@
+use sea_orm_migration::prelude::extension::postgres::Type;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Superseded by the tree registry, it was never read
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(MerkleTrees::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(LdMerkleTreeStatus::Table)
+                    .values([
+                        LdMerkleTreeStatus::Pending,
+                        LdMerkleTreeStatus::Backfilling,
+                        LdMerkleTreeStatus::Indexing,
+                        LdMerkleTreeStatus::Failed,
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+
+        if manager.has_table("ld_merkle_trees").await? {
+            // Created by earlier versions of LightDAS without a primary key nor a status
+            manager
+                .get_connection()
+                .execute_unprepared(
+                    "DELETE FROM ld_merkle_trees WHERE address IS NULL;
+                    DELETE FROM ld_merkle_trees a USING ld_merkle_trees b
+                        WHERE a.address = b.address AND a.ctid < b.ctid;
+                    UPDATE ld_merkle_trees SET should_index = TRUE WHERE should_index IS NULL;
+                    UPDATE ld_merkle_trees SET created_at = CURRENT_TIMESTAMP WHERE created_at IS NULL;
+                    UPDATE ld_merkle_trees SET updated_at = CURRENT_TIMESTAMP WHERE updated_at IS NULL;
+                    ALTER TABLE ld_merkle_trees
+                        ADD PRIMARY KEY (address),
+                        ALTER COLUMN should_index SET NOT NULL,
+                        ALTER COLUMN created_at SET NOT NULL,
+                        ALTER COLUMN updated_at SET NOT NULL,
+                        ADD COLUMN status ld_merkle_tree_status NOT NULL DEFAULT 'pending';",
+                )
+                .await?;
+
+            return Ok(());
+        }
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(LdMerkleTrees::Table)
+                    .col(
+                        ColumnDef::new(LdMerkleTrees::Address)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(LdMerkleTrees::Tag).string().null())
+                    .col(ColumnDef::new(LdMerkleTrees::Capacity).integer().null())
+                    .col(ColumnDef::new(LdMerkleTrees::MaxDepth).integer().null())
+                    .col(ColumnDef::new(LdMerkleTrees::CanopyDepth).integer().null())
+                    .col(
+                        ColumnDef::new(LdMerkleTrees::MaxBufferSize)
+                            .integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdMerkleTrees::ShouldIndex)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .col(
+                        ColumnDef::new(LdMerkleTrees::Status)
+                            .custom(LdMerkleTreeStatus::Table)
+                            .not_null()
+                            .default("pending"),
+                    )
+                    .col(
+                        ColumnDef::new(LdMerkleTrees::CreatedAt)
+                            .date_time()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdMerkleTrees::UpdatedAt)
+                            .date_time()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LdMerkleTrees::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(LdMerkleTreeStatus::Table).to_owned())
+            .await?;
+
+        // Dropped by `up`, the earlier migration creating it drops it when rolled back
+        manager
+            .create_table(
+                Table::create()
+                    .table(MerkleTrees::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MerkleTrees::Address)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(MerkleTrees::Capacity).integer().null())
+                    .col(ColumnDef::new(MerkleTrees::CanopyDepth).integer().null())
+                    .col(ColumnDef::new(MerkleTrees::Network).string().null())
+                    .col(ColumnDef::new(MerkleTrees::NumMinted).integer().null())
+                    .col(ColumnDef::new(MerkleTrees::Signature).string().null())
+                    .col(
+                        ColumnDef::new(MerkleTrees::CreatedAt)
+                            .date_time()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MerkleTrees::UpdatedAt)
+                            .date_time()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum MerkleTrees {
+    Table,
+    Address,
+    Capacity,
+    CanopyDepth,
+    Network,
+    NumMinted,
+    Signature,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum LdMerkleTrees {
+    Table,
+    Address,
+    Tag,
+    Capacity,
+    MaxDepth,
+    CanopyDepth,
+    MaxBufferSize,
+    ShouldIndex,
+    Status,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum LdMerkleTreeStatus {
+    #[sea_orm(iden = "ld_merkle_tree_status")]
+    Table,
+    Pending,
+    Backfilling,
+    Indexing,
+    Failed,
+}
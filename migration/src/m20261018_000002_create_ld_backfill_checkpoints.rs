@@ -0,0 +1,113 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LdBackfillCheckpoints::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::Tree)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::GapBefore)
+                            .binary()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::GapUntil)
+                            .binary()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::CrawlCursor)
+                            .binary()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::Crawled)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::CrawlComplete)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::Applied)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::LastAppliedSeq)
+                            .big_integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::CreatedAt)
+                            .date_time()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdBackfillCheckpoints::UpdatedAt)
+                            .date_time()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("ld_backfill_checkpoints_tree_idx")
+                    .table(LdBackfillCheckpoints::Table)
+                    .col(LdBackfillCheckpoints::Tree)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LdBackfillCheckpoints::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LdBackfillCheckpoints {
+    Table,
+    Id,
+    Tree,
+    GapBefore,
+    GapUntil,
+    CrawlCursor,
+    Crawled,
+    CrawlComplete,
+    Applied,
+    LastAppliedSeq,
+    CreatedAt,
+    UpdatedAt,
+}
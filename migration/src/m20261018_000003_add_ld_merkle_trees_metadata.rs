@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LdMerkleTrees::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::CreationSlot)
+                            .big_integer()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::Seq).big_integer().null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::NumMinted)
+                            .big_integer()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::TreeCreator).string().null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::TreeDelegate).string().null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LdMerkleTrees::Table)
+                    .drop_column(LdMerkleTrees::CreationSlot)
+                    .drop_column(LdMerkleTrees::Seq)
+                    .drop_column(LdMerkleTrees::NumMinted)
+                    .drop_column(LdMerkleTrees::TreeCreator)
+                    .drop_column(LdMerkleTrees::TreeDelegate)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LdMerkleTrees {
+    Table,
+    CreationSlot,
+    Seq,
+    NumMinted,
+    TreeCreator,
+    TreeDelegate,
+}
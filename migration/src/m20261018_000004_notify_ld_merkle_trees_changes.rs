@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+/// Channel notified with the address of every tree inserted, deleted, or whose `should_index`
+/// changed.
+pub const LD_MERKLE_TREES_CHANNEL: &str = "ld_merkle_trees_changed";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Metadata refreshes update every row periodically, only changes to the set of indexed
+        // trees are notified
+        manager
+            .get_connection()
+            .execute_unprepared(&format!(
+                r#"
+                CREATE OR REPLACE FUNCTION ld_merkle_trees_notify() RETURNS trigger AS $$
+                BEGIN
+                    IF TG_OP = 'DELETE' THEN
+                        PERFORM pg_notify('{channel}', OLD.address);
+                    ELSE
+                        PERFORM pg_notify('{channel}', NEW.address);
+                    END IF;
+                    RETURN NULL;
+                END;
+                $$ LANGUAGE plpgsql;
+
+                DROP TRIGGER IF EXISTS ld_merkle_trees_notify ON ld_merkle_trees;
+
+                CREATE TRIGGER ld_merkle_trees_notify
+                AFTER INSERT OR DELETE OR UPDATE OF address, should_index ON ld_merkle_trees
+                FOR EACH ROW EXECUTE FUNCTION ld_merkle_trees_notify();
+                "#,
+                channel = LD_MERKLE_TREES_CHANNEL
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                DROP TRIGGER IF EXISTS ld_merkle_trees_notify ON ld_merkle_trees;
+                DROP FUNCTION IF EXISTS ld_merkle_trees_notify();
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
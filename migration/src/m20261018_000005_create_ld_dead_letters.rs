@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LdDeadLetters::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(LdDeadLetters::Tree).binary().not_null())
+                    .col(ColumnDef::new(LdDeadLetters::Signature).binary().not_null())
+                    .col(ColumnDef::new(LdDeadLetters::Slot).big_integer().null())
+                    .col(ColumnDef::new(LdDeadLetters::ErrorKind).string().not_null())
+                    .col(ColumnDef::new(LdDeadLetters::Error).text().not_null())
+                    .col(
+                        ColumnDef::new(LdDeadLetters::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(1),
+                    )
+                    .col(
+                        ColumnDef::new(LdDeadLetters::CreatedAt)
+                            .date_time()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LdDeadLetters::UpdatedAt)
+                            .date_time()
+                            .default(SimpleExpr::Keyword(Keyword::CurrentTimestamp))
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(LdDeadLetters::Tree)
+                            .col(LdDeadLetters::Signature),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LdDeadLetters::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LdDeadLetters {
+    Table,
+    Tree,
+    Signature,
+    Slot,
+    ErrorKind,
+    Error,
+    Attempts,
+    CreatedAt,
+    UpdatedAt,
+}
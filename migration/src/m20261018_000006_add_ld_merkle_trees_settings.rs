@@ -0,0 +1,138 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Unset worker counts fall back to the ones LightDAS is configured with
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LdMerkleTrees::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::Backfill)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::BackfillFromSlot)
+                            .big_integer()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::BackfillAfterSignature)
+                            .string()
+                            .null(),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::SignatureWorkerCount)
+                            .integer()
+                            .null()
+                            .check(Expr::col(LdMerkleTrees::SignatureWorkerCount).gt(0)),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::GapWorkerCount)
+                            .integer()
+                            .null()
+                            .check(Expr::col(LdMerkleTrees::GapWorkerCount).gt(0)),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::MetadataJsonDownloadWorkerCount)
+                            .integer()
+                            .null()
+                            .check(Expr::col(LdMerkleTrees::MetadataJsonDownloadWorkerCount).gt(0)),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::Commitment)
+                            .string()
+                            .not_null()
+                            .default("confirmed")
+                            .check(
+                                Expr::col(LdMerkleTrees::Commitment)
+                                    .is_in(["confirmed", "finalized"]),
+                            ),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::DownloadMetadataJson)
+                            .boolean()
+                            .not_null()
+                            .default(true),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(LdMerkleTrees::Priority)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Trees whose settings change are restarted with them
+        notify_on_update_of(
+            manager,
+            "address, should_index, backfill, backfill_from_slot, backfill_after_signature, \
+             signature_worker_count, gap_worker_count, metadata_json_download_worker_count, \
+             commitment, download_metadata_json, priority",
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        notify_on_update_of(manager, "address, should_index").await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LdMerkleTrees::Table)
+                    .drop_column(LdMerkleTrees::Backfill)
+                    .drop_column(LdMerkleTrees::BackfillFromSlot)
+                    .drop_column(LdMerkleTrees::BackfillAfterSignature)
+                    .drop_column(LdMerkleTrees::SignatureWorkerCount)
+                    .drop_column(LdMerkleTrees::GapWorkerCount)
+                    .drop_column(LdMerkleTrees::MetadataJsonDownloadWorkerCount)
+                    .drop_column(LdMerkleTrees::Commitment)
+                    .drop_column(LdMerkleTrees::DownloadMetadataJson)
+                    .drop_column(LdMerkleTrees::Priority)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+/// Recreates the trigger notifying the changes of the tree registry so that it fires on updates of
+/// `columns`.
+async fn notify_on_update_of(manager: &SchemaManager<'_>, columns: &str) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute_unprepared(&format!(
+            r#"
+            DROP TRIGGER IF EXISTS ld_merkle_trees_notify ON ld_merkle_trees;
+
+            CREATE TRIGGER ld_merkle_trees_notify
+            AFTER INSERT OR DELETE OR UPDATE OF {columns} ON ld_merkle_trees
+            FOR EACH ROW EXECUTE FUNCTION ld_merkle_trees_notify();
+            "#,
+            columns = columns
+        ))
+        .await?;
+
+    Ok(())
+}
+
+#[derive(DeriveIden)]
+enum LdMerkleTrees {
+    Table,
+    Backfill,
+    BackfillFromSlot,
+    BackfillAfterSignature,
+    SignatureWorkerCount,
+    GapWorkerCount,
+    MetadataJsonDownloadWorkerCount,
+    Commitment,
+    DownloadMetadataJson,
+    Priority,
+}
@@ -1,8 +1,5 @@
 use {
-    crate::{
-        error::{ProgramTransformerError, ProgramTransformerResult},
-        DownloadMetadataNotifier,
-    },
+    crate::{error::ProgramTransformerResult, DownloadMetadataInfo},
     blockbuster::{
         instruction::InstructionBundle,
         programs::bubblegum::{
@@ -25,13 +22,15 @@ mod redeem;
 mod transfer;
 mod update_metadata;
 
+/// Writes the instruction with `txn` and returns the metadata JSON to download, if any.
+///
+/// The download is not requested here since `txn` may not be committed yet.
 pub async fn handle_bubblegum_instruction<'c, T>(
     parsing_result: &'c BubblegumInstruction,
     bundle: &'c InstructionBundle<'c>,
     txn: &T,
-    download_metadata_notifier: &DownloadMetadataNotifier,
     cl_audits: bool,
-) -> ProgramTransformerResult<()>
+) -> ProgramTransformerResult<Option<DownloadMetadataInfo>>
 where
     T: ConnectionTrait + TransactionTrait,
 {
@@ -61,6 +60,7 @@ where
     };
     println!("BGUM instruction txn={:?}: {:?}", ix_str, bundle.txn_id);
 
+    let mut download_metadata_info = None;
     match ix_type {
         InstructionName::Transfer => {
             transfer::transfer(parsing_result, bundle, txn, ix_str, cl_audits).await?;
@@ -72,13 +72,8 @@ where
             delegate::delegate(parsing_result, bundle, txn, ix_str, cl_audits).await?;
         }
         InstructionName::MintV1 | InstructionName::MintToCollectionV1 => {
-            if let Some(info) =
-                mint_v1::mint_v1(parsing_result, bundle, txn, ix_str, cl_audits).await?
-            {
-                download_metadata_notifier(info)
-                    .await
-                    .map_err(ProgramTransformerError::DownloadMetadataNotify)?;
-            }
+            download_metadata_info =
+                mint_v1::mint_v1(parsing_result, bundle, txn, ix_str, cl_audits).await?;
         }
         InstructionName::Redeem => {
             redeem::redeem(parsing_result, bundle, txn, ix_str, cl_audits).await?;
@@ -100,18 +95,13 @@ where
         }
         InstructionName::SetDecompressibleState => (), // Nothing to index.
         InstructionName::UpdateMetadata => {
-            if let Some(info) =
+            download_metadata_info =
                 update_metadata::update_metadata(parsing_result, bundle, txn, ix_str, cl_audits)
-                    .await?
-            {
-                download_metadata_notifier(info)
-                    .await
-                    .map_err(ProgramTransformerError::DownloadMetadataNotify)?;
-            }
+                    .await?;
         }
         _ => debug!("Bubblegum: Not Implemented Instruction"),
     }
-    Ok(())
+    Ok(download_metadata_info)
 }
 
 // PDA lookup requires an 8-byte array.
@@ -101,17 +101,58 @@ impl ProgramTransformer {
         )
     }
 
+    /// Returns the change log sequence numbers the bubblegum instructions of `tx_info` emitted for
+    /// `tree`, in instruction order. Instructions that fail to parse are skipped.
+    pub fn change_log_seqs(&self, tx_info: &TransactionInfo, tree: &Pubkey) -> Vec<u64> {
+        let txn_id = tx_info.signature.to_string();
+
+        self.break_transaction(tx_info)
+            .into_iter()
+            .filter(|((program, _), _)| *program == mpl_bubblegum::ID)
+            .filter_map(|((program, instruction), inner_ix)| {
+                let keys = instruction
+                    .accounts
+                    .iter()
+                    .filter_map(|a| tx_info.account_keys.get(*a as usize).copied())
+                    .collect::<Vec<_>>();
+                let ix = InstructionBundle {
+                    txn_id: &txn_id,
+                    program,
+                    instruction: Some(instruction),
+                    inner_ix: inner_ix.as_deref(),
+                    keys: keys.as_slice(),
+                    slot: tx_info.slot,
+                };
+
+                let result = self.match_program(&program)?.handle_instruction(&ix).ok()?;
+                match result.result_type() {
+                    ProgramParseResult::Bubblegum(parsing_result) => parsing_result
+                        .tree_update
+                        .as_ref()
+                        .filter(|cl| cl.id == *tree)
+                        .map(|cl| cl.seq),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
     #[allow(clippy::borrowed_box)]
     pub fn match_program(&self, key: &Pubkey) -> Option<&Box<dyn ProgramParser>> {
         self.parsers.get(key)
     }
 
+    /// Applies the instructions of `tx_info` in a single database transaction, so a failure or a
+    /// crash midway leaves none of them written.
     pub async fn handle_transaction(
         &self,
         tx_info: &TransactionInfo,
     ) -> ProgramTransformerResult<()> {
         println!("Handling Transaction: {:?}", tx_info.signature);
         let instructions = self.break_transaction(tx_info);
+        // Rolled back when dropped, i.e. when an instruction fails and its error is returned.
+        let txn = self.storage.begin().await?;
+        let mut download_metadata_infos = Vec::new();
         let mut not_impl = 0;
         let ixlen = instructions.len();
         debug!("Instructions: {}", ixlen);
@@ -154,21 +195,17 @@ impl ProgramTransformer {
                 let concrete = result.result_type();
                 match concrete {
                     ProgramParseResult::Bubblegum(parsing_result) => {
-                        handle_bubblegum_instruction(
-                            parsing_result,
-                            &ix,
-                            &self.storage,
-                            &self.download_metadata_notifier,
-                            self.cl_audits,
-                        )
-                        .await
-                        .map_err(|err| {
-                            error!(
-                                "Failed to handle bubblegum instruction for txn {:?}: {:?}",
-                                tx_info.signature, err
-                            );
-                            err
-                        })?;
+                        let download_metadata_info =
+                            handle_bubblegum_instruction(parsing_result, &ix, &txn, self.cl_audits)
+                                .await
+                                .map_err(|err| {
+                                    error!(
+                                        "Failed to handle bubblegum instruction for txn {:?}: {:?}",
+                                        tx_info.signature, err
+                                    );
+                                    err
+                                })?;
+                        download_metadata_infos.extend(download_metadata_info);
                     }
                     _ => {
                         not_impl += 1;
@@ -181,6 +218,14 @@ impl ProgramTransformer {
             debug!("Not imple");
             return Err(ProgramTransformerError::NotImplemented);
         }
+        txn.commit().await?;
+
+        // The downloaded metadata is written to the asset data committed above
+        for info in download_metadata_infos {
+            (self.download_metadata_notifier)(info)
+                .await
+                .map_err(ProgramTransformerError::DownloadMetadataNotify)?;
+        }
         Ok(())
     }
 
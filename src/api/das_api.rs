@@ -0,0 +1,472 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use das_bubblegum_backfill::TreeResponse;
+use das_core::Rpc;
+use digital_asset_types::dao::ld_merkle_trees;
+use digital_asset_types::dao::sea_orm_active_enums::{OwnerType, RoyaltyTargetType};
+use digital_asset_types::dao::{Cursor, PageOptions, SearchAssetsQuery};
+use digital_asset_types::dapi::{
+    check_asset_proof, get_asset, get_asset_proofs, get_asset_signatures, get_assets,
+    get_assets_by_authority, get_assets_by_creator, get_assets_by_group, get_assets_by_owner,
+    get_proof_for_asset, search_assets, truncate_canopy,
+};
+use digital_asset_types::rpc::filter::{AssetSortBy, AssetSorting, SearchConditionType};
+use digital_asset_types::rpc::response::{AssetList, TransactionSignatureList};
+use digital_asset_types::rpc::{Asset, AssetProof, OwnershipModel, RoyaltyModel};
+use sea_orm::sea_query::ConditionType;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, SqlxPostgresConnector};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::{Pool, Postgres};
+
+use super::error::{DasApiError, InvalidAssetProof};
+use super::params::{
+    GetAsset, GetAssetProof, GetAssetProofs, GetAssetSignatures, GetAssets, GetAssetsByAuthority,
+    GetAssetsByCreator, GetAssetsByGroup, GetAssetsByOwner, SearchAssets,
+};
+
+const DEFAULT_LIMIT: u64 = 1000;
+const MAX_BATCH_SIZE: usize = 1000;
+/// Trees fetched per `getMultipleAccounts` call for their canopy depth.
+const TREES_PER_REQUEST: usize = 100;
+
+pub struct DasApi {
+    db_connection: DatabaseConnection,
+    /// Fetches the canopy depth of the trees missing from `ld_merkle_trees`.
+    rpc: Rpc,
+}
+
+impl DasApi {
+    pub fn new(database_pool: Pool<Postgres>, rpc: Rpc) -> Self {
+        DasApi {
+            db_connection: SqlxPostgresConnector::from_sqlx_postgres_pool(database_pool),
+            rpc,
+        }
+    }
+
+    pub async fn get_asset(&self, payload: GetAsset) -> Result<Asset, DasApiError> {
+        let id = validate_pubkey(payload.id)?;
+        let options = payload.options.unwrap_or_default();
+
+        get_asset(&self.db_connection, id, &options)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn get_assets(&self, payload: GetAssets) -> Result<Vec<Option<Asset>>, DasApiError> {
+        validate_batch_size(payload.ids.len())?;
+
+        let ids = payload
+            .ids
+            .into_iter()
+            .map(validate_pubkey)
+            .collect::<Result<Vec<_>, _>>()?;
+        let options = payload.options.unwrap_or_default();
+
+        let mut assets =
+            get_assets(&self.db_connection, ids.clone(), ids.len() as u64, &options).await?;
+
+        Ok(ids
+            .into_iter()
+            .map(|id| assets.remove(&bs58::encode(id).into_string()))
+            .collect())
+    }
+
+    pub async fn get_asset_proof(&self, payload: GetAssetProof) -> Result<AssetProof, DasApiError> {
+        let id = validate_pubkey(payload.id.clone())?;
+
+        let mut proof = get_proof_for_asset(&self.db_connection, id).await?;
+
+        if payload.verify.unwrap_or_default() {
+            if let Some(invalid) = check_asset_proof(&proof) {
+                return Err(DasApiError::InvalidProofs(vec![InvalidAssetProof::new(
+                    payload.id, invalid,
+                )]));
+            }
+        }
+
+        if payload.truncate_canopy.unwrap_or_default() {
+            let canopy_depths = self
+                .canopy_depths(HashSet::from([proof.tree_id.clone()]))
+                .await?;
+            let canopy_depth = canopy_depths[&proof.tree_id];
+            truncate_canopy(&mut proof, canopy_depth);
+        }
+
+        Ok(proof)
+    }
+
+    pub async fn get_asset_proofs(
+        &self,
+        payload: GetAssetProofs,
+    ) -> Result<HashMap<String, Option<AssetProof>>, DasApiError> {
+        validate_batch_size(payload.ids.len())?;
+
+        let ids = payload
+            .ids
+            .iter()
+            .cloned()
+            .map(validate_pubkey)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut proofs = get_asset_proofs(&self.db_connection, ids).await?;
+
+        if payload.verify.unwrap_or_default() {
+            let invalid_proofs = proofs
+                .iter()
+                .filter_map(|(id, proof)| {
+                    check_asset_proof(proof)
+                        .map(|invalid| InvalidAssetProof::new(id.clone(), invalid))
+                })
+                .collect::<Vec<_>>();
+
+            if !invalid_proofs.is_empty() {
+                return Err(DasApiError::InvalidProofs(invalid_proofs));
+            }
+        }
+
+        if payload.truncate_canopy.unwrap_or_default() {
+            let canopy_depths = self
+                .canopy_depths(proofs.values().map(|proof| proof.tree_id.clone()).collect())
+                .await?;
+
+            for proof in proofs.values_mut() {
+                let canopy_depth = canopy_depths[&proof.tree_id];
+                truncate_canopy(proof, canopy_depth);
+            }
+        }
+
+        Ok(payload
+            .ids
+            .into_iter()
+            .map(|id| {
+                let proof = proofs.remove(&id);
+                (id, proof)
+            })
+            .collect())
+    }
+
+    pub async fn get_assets_by_owner(
+        &self,
+        payload: GetAssetsByOwner,
+    ) -> Result<AssetList, DasApiError> {
+        let owner_address = validate_pubkey(payload.owner_address)?;
+        let sorting = payload.sort_by.unwrap_or_default();
+        let page_options = build_page(
+            payload.limit,
+            payload.page,
+            payload.before,
+            payload.after,
+            payload.cursor,
+            &sorting,
+        )?;
+        let options = payload.options.unwrap_or_default();
+
+        get_assets_by_owner(
+            &self.db_connection,
+            owner_address,
+            sorting,
+            &page_options,
+            &options,
+        )
+        .await
+        .map_err(Into::into)
+    }
+
+    pub async fn get_assets_by_creator(
+        &self,
+        payload: GetAssetsByCreator,
+    ) -> Result<AssetList, DasApiError> {
+        let creator_address = validate_pubkey(payload.creator_address)?;
+        let sorting = payload.sort_by.unwrap_or_default();
+        let page_options = build_page(
+            payload.limit,
+            payload.page,
+            payload.before,
+            payload.after,
+            payload.cursor,
+            &sorting,
+        )?;
+        let options = payload.options.unwrap_or_default();
+
+        get_assets_by_creator(
+            &self.db_connection,
+            creator_address,
+            payload.only_verified.unwrap_or_default(),
+            sorting,
+            &page_options,
+            &options,
+        )
+        .await
+        .map_err(Into::into)
+    }
+
+    pub async fn get_assets_by_group(
+        &self,
+        payload: GetAssetsByGroup,
+    ) -> Result<AssetList, DasApiError> {
+        let sorting = payload.sort_by.unwrap_or_default();
+        let page_options = build_page(
+            payload.limit,
+            payload.page,
+            payload.before,
+            payload.after,
+            payload.cursor,
+            &sorting,
+        )?;
+        let options = payload.options.unwrap_or_default();
+
+        get_assets_by_group(
+            &self.db_connection,
+            payload.group_key,
+            payload.group_value,
+            sorting,
+            &page_options,
+            &options,
+        )
+        .await
+        .map_err(Into::into)
+    }
+
+    pub async fn get_assets_by_authority(
+        &self,
+        payload: GetAssetsByAuthority,
+    ) -> Result<AssetList, DasApiError> {
+        let authority_address = validate_pubkey(payload.authority_address)?;
+        let sorting = payload.sort_by.unwrap_or_default();
+        let page_options = build_page(
+            payload.limit,
+            payload.page,
+            payload.before,
+            payload.after,
+            payload.cursor,
+            &sorting,
+        )?;
+        let options = payload.options.unwrap_or_default();
+
+        get_assets_by_authority(
+            &self.db_connection,
+            authority_address,
+            sorting,
+            &page_options,
+            &options,
+        )
+        .await
+        .map_err(Into::into)
+    }
+
+    pub async fn get_asset_signatures(
+        &self,
+        payload: GetAssetSignatures,
+    ) -> Result<TransactionSignatureList, DasApiError> {
+        let id = validate_opt_pubkey(payload.id)?;
+        let tree = validate_opt_pubkey(payload.tree)?;
+
+        if id.is_none() && (tree.is_none() || payload.leaf_index.is_none()) {
+            return Err(DasApiError::Validation(
+                "Must provide either 'id' or both 'tree' and 'leafIndex'".to_string(),
+            ));
+        }
+
+        let page_options = build_page(
+            payload.limit,
+            payload.page,
+            payload.before,
+            payload.after,
+            payload.cursor,
+            &AssetSorting::default(),
+        )?;
+
+        get_asset_signatures(
+            &self.db_connection,
+            id,
+            tree,
+            payload.leaf_index,
+            page_options,
+            payload.sort_direction,
+        )
+        .await
+        .map_err(Into::into)
+    }
+
+    pub async fn search_assets(&self, payload: SearchAssets) -> Result<AssetList, DasApiError> {
+        let (specification_version, specification_asset_class) = payload
+            .interface
+            .map(|interface| {
+                let (version, class) = interface.into();
+                (Some(version), Some(class))
+            })
+            .unwrap_or_default();
+
+        let condition_type = payload.condition_type.map(|c| match c {
+            SearchConditionType::All => ConditionType::All,
+            SearchConditionType::Any => ConditionType::Any,
+        });
+
+        let owner_type = payload.owner_type.map(|o| match o {
+            OwnershipModel::Single => OwnerType::Single,
+            OwnershipModel::Token => OwnerType::Token,
+        });
+
+        let royalty_target_type = payload.royalty_target_type.map(|r| match r {
+            RoyaltyModel::Creators => RoyaltyTargetType::Creators,
+            RoyaltyModel::Fanout => RoyaltyTargetType::Fanout,
+            RoyaltyModel::Single => RoyaltyTargetType::Single,
+        });
+
+        let query = SearchAssetsQuery {
+            negate: payload.negate,
+            condition_type,
+            specification_version,
+            specification_asset_class,
+            owner_address: validate_opt_pubkey(payload.owner_address)?,
+            owner_type,
+            creator_address: validate_opt_pubkey(payload.creator_address)?,
+            creator_verified: payload.creator_verified,
+            authority_address: validate_opt_pubkey(payload.authority_address)?,
+            grouping: payload.grouping,
+            delegate: validate_opt_pubkey(payload.delegate)?,
+            frozen: payload.frozen,
+            supply: payload.supply,
+            supply_mint: validate_opt_pubkey(payload.supply_mint)?,
+            compressed: payload.compressed,
+            compressible: payload.compressible,
+            royalty_target_type,
+            royalty_target: validate_opt_pubkey(payload.royalty_target)?,
+            royalty_amount: payload.royalty_amount,
+            burnt: payload.burnt,
+            json_uri: payload.json_uri,
+            name: payload.name.map(String::into_bytes),
+        };
+
+        let sorting = payload.sort_by.unwrap_or_default();
+        let page_options = build_page(
+            payload.limit,
+            payload.page,
+            payload.before,
+            payload.after,
+            payload.cursor,
+            &sorting,
+        )?;
+        let options = payload.options.unwrap_or_default();
+
+        search_assets(&self.db_connection, query, sorting, &page_options, &options)
+            .await
+            .map_err(Into::into)
+    }
+}
+
+fn validate_pubkey(address: String) -> Result<Vec<u8>, DasApiError> {
+    Pubkey::from_str(&address)
+        .map(|pubkey| pubkey.to_bytes().to_vec())
+        .map_err(|_| DasApiError::PubkeyValidation(address))
+}
+
+fn validate_opt_pubkey(address: Option<String>) -> Result<Option<Vec<u8>>, DasApiError> {
+    address.map(validate_pubkey).transpose()
+}
+
+impl DasApi {
+    /// The canopy depth of each of `tree_ids`, read from `ld_merkle_trees` or from the account of
+    /// the trees it doesn't know it for.
+    async fn canopy_depths(
+        &self,
+        mut tree_ids: HashSet<String>,
+    ) -> Result<HashMap<String, u32>, DasApiError> {
+        let mut canopy_depths = ld_merkle_trees::Entity::find()
+            .filter(ld_merkle_trees::Column::Address.is_in(tree_ids.clone()))
+            .filter(ld_merkle_trees::Column::CanopyDepth.is_not_null())
+            .all(&self.db_connection)
+            .await?
+            .into_iter()
+            .filter_map(|tree| Some((tree.address, tree.canopy_depth? as u32)))
+            .collect::<HashMap<_, _>>();
+        tree_ids.retain(|tree_id| !canopy_depths.contains_key(tree_id));
+
+        let trees = tree_ids
+            .into_iter()
+            .map(|tree_id| {
+                Pubkey::from_str(&tree_id).map_err(|_| DasApiError::PubkeyValidation(tree_id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for trees in trees.chunks(TREES_PER_REQUEST) {
+            let accounts = self
+                .rpc
+                .get_multiple_accounts(trees)
+                .await
+                .map_err(|e| DasApiError::CanopyDepth(e.to_string()))?;
+
+            for (tree, account) in trees.iter().zip(accounts) {
+                let account = account
+                    .ok_or_else(|| DasApiError::CanopyDepth(format!("tree {} not found", tree)))?;
+                let tree_response = TreeResponse::try_from_rpc(*tree, account)
+                    .map_err(|e| DasApiError::CanopyDepth(format!("tree {}: {}", tree, e)))?;
+
+                canopy_depths.insert(tree.to_string(), tree_response.canopy_depth);
+            }
+        }
+
+        Ok(canopy_depths)
+    }
+}
+
+const fn validate_batch_size(size: usize) -> Result<(), DasApiError> {
+    if size > MAX_BATCH_SIZE {
+        return Err(DasApiError::BatchSizeExceeded(MAX_BATCH_SIZE));
+    }
+
+    Ok(())
+}
+
+fn build_page(
+    limit: Option<u32>,
+    page: Option<u32>,
+    before: Option<String>,
+    after: Option<String>,
+    cursor: Option<String>,
+    sorting: &AssetSorting,
+) -> Result<PageOptions, DasApiError> {
+    let limit = match limit {
+        Some(0) | None => DEFAULT_LIMIT,
+        Some(limit) => limit as u64,
+    };
+
+    if limit > MAX_BATCH_SIZE as u64 {
+        return Err(DasApiError::BatchSizeExceeded(MAX_BATCH_SIZE));
+    }
+
+    if page == Some(0) {
+        return Err(DasApiError::PaginationEmpty);
+    }
+
+    if let Some(cursor) = cursor {
+        if page.is_some() || before.is_some() || after.is_some() {
+            return Err(DasApiError::Pagination);
+        }
+
+        if sorting.sort_by != AssetSortBy::Id {
+            return Err(DasApiError::PaginationSorting);
+        }
+
+        return Ok(PageOptions {
+            limit,
+            cursor: Some(Cursor {
+                id: Some(validate_pubkey(cursor)?),
+            }),
+            ..PageOptions::default()
+        });
+    }
+
+    if page.is_some() && (before.is_some() || after.is_some()) {
+        return Err(DasApiError::Pagination);
+    }
+
+    Ok(PageOptions {
+        limit,
+        page: page.map(|p| p as u64),
+        before: validate_opt_pubkey(before)?,
+        after: validate_opt_pubkey(after)?,
+        cursor: None,
+    })
+}
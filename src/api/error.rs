@@ -0,0 +1,67 @@
+use digital_asset_types::dapi::InvalidProof;
+use jsonrpsee::core::Error as RpcError;
+use jsonrpsee::types::error::{CallError, ErrorObject, CALL_EXECUTION_FAILED_CODE};
+use serde::Serialize;
+use thiserror::Error;
+
+/// A proof whose leaf and nodes don't hash up to its root, returned as the data of the error.
+#[derive(Debug, Serialize)]
+pub struct InvalidAssetProof {
+    pub id: String,
+    pub tree_id: String,
+    pub node_index: i64,
+    pub leaf: String,
+    pub root: String,
+    /// `null` when the leaf or a node isn't 32 bytes long.
+    pub computed_root: Option<String>,
+}
+
+impl InvalidAssetProof {
+    pub fn new(id: String, invalid: InvalidProof) -> Self {
+        Self {
+            id,
+            tree_id: invalid.proof.tree_id,
+            node_index: invalid.proof.node_index,
+            leaf: invalid.proof.leaf,
+            root: invalid.proof.root,
+            computed_root: invalid.computed_root,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DasApiError {
+    #[error("Server failed to start: {0}")]
+    ServerStart(String),
+    #[error("Pubkey Validation Error: {0} is invalid")]
+    PubkeyValidation(String),
+    #[error("Validation Error: {0}")]
+    Validation(String),
+    #[error("Database Error: {0}")]
+    Database(#[from] sea_orm::DbErr),
+    #[error("Pagination Error. Only one pagination parameter supported per query.")]
+    Pagination,
+    #[error("Pagination Error. Page number must be greater than 0.")]
+    PaginationEmpty,
+    #[error(
+        "Pagination Sorting Error. Only sorting based on id is supported for cursor pagination"
+    )]
+    PaginationSorting,
+    #[error("Batch Size Error. Batch size should not be greater than {0}.")]
+    BatchSizeExceeded(usize),
+    #[error("Canopy Depth Error: {0}")]
+    CanopyDepth(String),
+    #[error("Proof Validation Error. {} proofs don't hash up to their root.", .0.len())]
+    InvalidProofs(Vec<InvalidAssetProof>),
+}
+
+impl From<DasApiError> for RpcError {
+    fn from(error: DasApiError) -> Self {
+        match error {
+            DasApiError::InvalidProofs(ref proofs) => RpcError::Call(CallError::Custom(
+                ErrorObject::owned(CALL_EXECUTION_FAILED_CODE, error.to_string(), Some(proofs)),
+            )),
+            error => RpcError::Call(CallError::from_std_error(error)),
+        }
+    }
+}
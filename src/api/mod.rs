@@ -0,0 +1,4 @@
+pub mod das_api;
+pub mod error;
+pub mod params;
+pub mod server;
@@ -0,0 +1,148 @@
+use digital_asset_types::rpc::{
+    filter::{AssetSortDirection, AssetSorting, SearchConditionType},
+    options::Options,
+    Interface, OwnershipModel, RoyaltyModel,
+};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAsset {
+    pub id: String,
+    #[serde(default, alias = "displayOptions")]
+    pub options: Option<Options>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAssets {
+    pub ids: Vec<String>,
+    #[serde(default, alias = "displayOptions")]
+    pub options: Option<Options>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAssetProof {
+    pub id: String,
+    /// Whether the proof is checked to hash up to its root before it is served.
+    pub verify: Option<bool>,
+    /// Whether the nodes held by the canopy of the tree are left out of the proof.
+    pub truncate_canopy: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAssetProofs {
+    pub ids: Vec<String>,
+    /// Whether the proofs are checked to hash up to their root before they are served.
+    pub verify: Option<bool>,
+    /// Whether the nodes held by the canopy of the trees are left out of the proofs.
+    pub truncate_canopy: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAssetsByOwner {
+    pub owner_address: String,
+    pub sort_by: Option<AssetSorting>,
+    pub limit: Option<u32>,
+    pub page: Option<u32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub cursor: Option<String>,
+    #[serde(default, alias = "displayOptions")]
+    pub options: Option<Options>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAssetsByCreator {
+    pub creator_address: String,
+    pub only_verified: Option<bool>,
+    pub sort_by: Option<AssetSorting>,
+    pub limit: Option<u32>,
+    pub page: Option<u32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub cursor: Option<String>,
+    #[serde(default, alias = "displayOptions")]
+    pub options: Option<Options>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAssetsByGroup {
+    pub group_key: String,
+    pub group_value: String,
+    pub sort_by: Option<AssetSorting>,
+    pub limit: Option<u32>,
+    pub page: Option<u32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub cursor: Option<String>,
+    #[serde(default, alias = "displayOptions")]
+    pub options: Option<Options>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAssetsByAuthority {
+    pub authority_address: String,
+    pub sort_by: Option<AssetSorting>,
+    pub limit: Option<u32>,
+    pub page: Option<u32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub cursor: Option<String>,
+    #[serde(default, alias = "displayOptions")]
+    pub options: Option<Options>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct GetAssetSignatures {
+    pub id: Option<String>,
+    pub tree: Option<String>,
+    pub leaf_index: Option<i64>,
+    pub limit: Option<u32>,
+    pub page: Option<u32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub cursor: Option<String>,
+    pub sort_direction: Option<AssetSortDirection>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct SearchAssets {
+    pub negate: Option<bool>,
+    pub condition_type: Option<SearchConditionType>,
+    pub interface: Option<Interface>,
+    pub owner_address: Option<String>,
+    pub owner_type: Option<OwnershipModel>,
+    pub creator_address: Option<String>,
+    pub creator_verified: Option<bool>,
+    pub authority_address: Option<String>,
+    pub grouping: Option<(String, String)>,
+    pub delegate: Option<String>,
+    pub frozen: Option<bool>,
+    pub supply: Option<u64>,
+    pub supply_mint: Option<String>,
+    pub compressed: Option<bool>,
+    pub compressible: Option<bool>,
+    pub royalty_target_type: Option<RoyaltyModel>,
+    pub royalty_target: Option<String>,
+    pub royalty_amount: Option<u32>,
+    pub burnt: Option<bool>,
+    pub json_uri: Option<String>,
+    pub name: Option<String>,
+    pub sort_by: Option<AssetSorting>,
+    pub limit: Option<u32>,
+    pub page: Option<u32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub cursor: Option<String>,
+    #[serde(default, alias = "displayOptions")]
+    pub options: Option<Options>,
+}
@@ -0,0 +1,104 @@
+use std::net::SocketAddr;
+
+use das_core::Rpc;
+use jsonrpsee::server::{ServerBuilder, ServerHandle};
+use jsonrpsee::RpcModule;
+use sqlx::{Pool, Postgres};
+
+use super::das_api::DasApi;
+use super::error::DasApiError;
+use super::params::{
+    GetAsset, GetAssetProof, GetAssetProofs, GetAssetSignatures, GetAssets, GetAssetsByAuthority,
+    GetAssetsByCreator, GetAssetsByGroup, GetAssetsByOwner, SearchAssets,
+};
+
+pub async fn start_api_server(
+    listen_address: &str,
+    database_pool: Pool<Postgres>,
+    rpc: Rpc,
+) -> Result<ServerHandle, DasApiError> {
+    let address: SocketAddr = listen_address
+        .parse()
+        .map_err(|e| DasApiError::ServerStart(format!("{listen_address}: {e}")))?;
+
+    let server = ServerBuilder::default()
+        .build(address)
+        .await
+        .map_err(|e| DasApiError::ServerStart(e.to_string()))?;
+
+    let module = build_rpc_module(DasApi::new(database_pool, rpc))
+        .map_err(|e| DasApiError::ServerStart(e.to_string()))?;
+
+    server
+        .start(module)
+        .map_err(|e| DasApiError::ServerStart(e.to_string()))
+}
+
+fn build_rpc_module(das_api: DasApi) -> Result<RpcModule<DasApi>, jsonrpsee::core::Error> {
+    let mut module = RpcModule::new(das_api);
+
+    module.register_async_method("getAsset", |params, api| async move {
+        let payload = params.parse::<GetAsset>()?;
+        api.get_asset(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("get_asset", "getAsset")?;
+
+    module.register_async_method("getAssets", |params, api| async move {
+        let payload = params.parse::<GetAssets>()?;
+        api.get_assets(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("get_assets", "getAssets")?;
+
+    module.register_async_method("getAssetProof", |params, api| async move {
+        let payload = params.parse::<GetAssetProof>()?;
+        api.get_asset_proof(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("get_asset_proof", "getAssetProof")?;
+
+    module.register_async_method("getAssetProofs", |params, api| async move {
+        let payload = params.parse::<GetAssetProofs>()?;
+        api.get_asset_proofs(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("get_asset_proofs", "getAssetProofs")?;
+
+    module.register_async_method("getAssetsByOwner", |params, api| async move {
+        let payload = params.parse::<GetAssetsByOwner>()?;
+        api.get_assets_by_owner(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("get_assets_by_owner", "getAssetsByOwner")?;
+
+    module.register_async_method("getAssetsByCreator", |params, api| async move {
+        let payload = params.parse::<GetAssetsByCreator>()?;
+        api.get_assets_by_creator(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("get_assets_by_creator", "getAssetsByCreator")?;
+
+    module.register_async_method("getAssetsByGroup", |params, api| async move {
+        let payload = params.parse::<GetAssetsByGroup>()?;
+        api.get_assets_by_group(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("get_assets_by_group", "getAssetsByGroup")?;
+
+    module.register_async_method("getAssetsByAuthority", |params, api| async move {
+        let payload = params.parse::<GetAssetsByAuthority>()?;
+        api.get_assets_by_authority(payload)
+            .await
+            .map_err(Into::into)
+    })?;
+    module.register_alias("get_assets_by_authority", "getAssetsByAuthority")?;
+
+    module.register_async_method("getAssetSignatures", |params, api| async move {
+        let payload = params.parse::<GetAssetSignatures>()?;
+        api.get_asset_signatures(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("get_asset_signatures", "getAssetSignatures")?;
+    module.register_alias("getSignaturesForAsset", "getAssetSignatures")?;
+
+    module.register_async_method("searchAssets", |params, api| async move {
+        let payload = params.parse::<SearchAssets>()?;
+        api.search_assets(payload).await.map_err(Into::into)
+    })?;
+    module.register_alias("search_assets", "searchAssets")?;
+
+    Ok(module)
+}
@@ -0,0 +1,351 @@
+use std::ops::Deref;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{value_parser, Args, Command};
+use das_bubblegum_backfill::BubblegumBackfillArgs;
+use das_core::{MetricsArgs, PoolArgs};
+use solana_sdk::pubkey::{ParsePubkeyError, Pubkey};
+
+/// Defaults LightDAS uses instead of the ones of the flattened backfill and metrics arguments,
+/// which are sized for crawling every tree at once rather than one tree per task.
+const INDEXER_DEFAULTS: &[(&str, &str)] = &[
+    ("tree_crawler_count", "4"),
+    ("metadata_json_download_worker_count", "100"),
+    ("metadata_json_download_worker_request_timeout", "200"),
+    ("signature_channel_size", "100"),
+    ("signature_worker_count", "100"),
+    ("gap_channel_size", "100"),
+    ("gap_worker_count", "100"),
+    ("gap_spill_threshold", "1000"),
+    ("metrics_prefix", "lightdas"),
+];
+
+/// Settings of the indexer, read from the command line, the environment and the config file.
+#[derive(Debug, Clone, Args)]
+pub struct IndexerArgs {
+    /// The Solana RPC URL.
+    #[arg(long, env)]
+    pub rpc_url: String,
+
+    /// The Solana RPC websocket URL, live transactions are subscribed to through it unless
+    /// `--grpc-url` or `--messenger-redis-url` is set.
+    #[arg(long, env)]
+    pub ws_url: String,
+
+    #[command(flatten)]
+    pub database: PoolArgs,
+
+    /// The address the DAS API is served on, e.g. `0.0.0.0:9090`. Only ingests when not set.
+    #[arg(long, env)]
+    pub api_listen_address: Option<String>,
+
+    /// The Yellowstone gRPC Geyser endpoint live transactions are streamed from.
+    #[arg(long, env, conflicts_with = "messenger_redis_url")]
+    pub grpc_url: Option<String>,
+
+    /// The `x-token` sent to the gRPC endpoint for authentication.
+    #[arg(long, env)]
+    pub grpc_x_token: Option<String>,
+
+    /// The Redis URL of a plerkle Geyser plugin deployment live transactions and account updates
+    /// are consumed from.
+    #[arg(long, env)]
+    pub messenger_redis_url: Option<String>,
+
+    /// How often the capacity, sequence number and mint count of the trees are refreshed.
+    #[arg(long, env, default_value = "300", value_parser = value_parser!(u64).range(1..))]
+    pub tree_metadata_refresh_interval_secs: u64,
+
+    /// Comma separated tree creators whose trees are registered and indexed automatically.
+    #[arg(long, env, default_value = "", hide_default_value = true)]
+    pub discovery_tree_creators: Pubkeys,
+
+    /// Comma separated collection mints whose trees are registered and indexed automatically.
+    #[arg(long, env, default_value = "", hide_default_value = true)]
+    pub discovery_collections: Pubkeys,
+
+    /// Comma separated MPL Core collections indexed along with their assets, on startup and
+    /// whenever a transaction touches them.
+    #[arg(long, env, default_value = "", hide_default_value = true)]
+    pub core_collections: Pubkeys,
+
+    /// How often all the trees of the discovery tree creators are scanned for.
+    #[arg(long, env, default_value = "3600", value_parser = value_parser!(u64).range(1..))]
+    pub tree_discovery_interval_secs: u64,
+
+    /// How long the trees are given to stop on SIGTERM or SIGINT before they are aborted.
+    #[arg(long, env, default_value = "30")]
+    pub shutdown_timeout_secs: u64,
+
+    /// How often the transactions that failed to index are retried.
+    #[arg(long, env, default_value = "300", value_parser = value_parser!(u64).range(1..))]
+    pub dead_letter_retry_interval_secs: u64,
+
+    /// The attempts after which a failed transaction is no longer retried automatically.
+    #[arg(long, env, default_value = "10")]
+    pub dead_letter_max_attempts: u32,
+
+    /// How many trees are backfilled at once, the ones with the highest priority in
+    /// `ld_merkle_trees` first. Unlimited when not set.
+    #[arg(long, env, value_parser = value_parser!(u64).range(1..))]
+    pub max_concurrent_backfills: Option<u64>,
+
+    /// How often the indexed trees are verified against the change logs buffered in their
+    /// account. Disabled when not set.
+    #[arg(long, env, value_parser = value_parser!(u64).range(1..))]
+    pub tree_verification_interval_secs: Option<u64>,
+
+    /// Backfills again from scratch the trees whose verification found nodes differing from
+    /// their account, the ones that are backfilled.
+    #[arg(long, env)]
+    pub rebackfill_on_mismatch: bool,
+
+    #[command(flatten)]
+    pub metrics: MetricsArgs,
+
+    #[command(flatten)]
+    pub backfill: BubblegumBackfillArgs,
+}
+
+impl IndexerArgs {
+    pub const fn tree_metadata_refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.tree_metadata_refresh_interval_secs)
+    }
+
+    pub const fn tree_discovery_interval(&self) -> Duration {
+        Duration::from_secs(self.tree_discovery_interval_secs)
+    }
+
+    pub const fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    pub const fn dead_letter_retry_interval(&self) -> Duration {
+        Duration::from_secs(self.dead_letter_retry_interval_secs)
+    }
+
+    pub fn tree_verification_interval(&self) -> Option<Duration> {
+        self.tree_verification_interval_secs
+            .map(Duration::from_secs)
+    }
+
+    /// Checks the settings clap cannot check on its own, the ones of the flattened arguments.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.backfill.only_trees.is_some() {
+            return Err(
+                "--only-trees is not supported, the trees to index are read from ld_merkle_trees"
+                    .to_string(),
+            );
+        }
+
+        if self.backfill.tree_worker.backfill_after_signature.is_some() {
+            return Err(
+                "--backfill-after-signature is not supported, it is set per tree in \
+                 ld_merkle_trees"
+                    .to_string(),
+            );
+        }
+
+        if self.database.database_min_connections > self.database.database_max_connections {
+            return Err(format!(
+                "--database-min-connections ({}) cannot exceed --database-max-connections ({})",
+                self.database.database_min_connections, self.database.database_max_connections
+            ));
+        }
+
+        let tree_worker = &self.backfill.tree_worker;
+        let counts = [
+            (
+                "--database-max-connections",
+                self.database.database_max_connections as usize,
+            ),
+            ("--tree-crawler-count", self.backfill.tree_crawler_count),
+            (
+                "--metadata-json-download-worker-count",
+                tree_worker
+                    .metadata_json_download_worker
+                    .metadata_json_download_worker_count,
+            ),
+            (
+                "--signature-channel-size",
+                tree_worker.signature_worker.signature_channel_size,
+            ),
+            (
+                "--signature-worker-count",
+                tree_worker.signature_worker.signature_worker_count,
+            ),
+            (
+                "--signature-window-size",
+                tree_worker.signature_worker.signature_window_size,
+            ),
+            (
+                "--gap-channel-size",
+                tree_worker.gap_worker.gap_channel_size,
+            ),
+            (
+                "--gap-worker-count",
+                tree_worker.gap_worker.gap_worker_count,
+            ),
+            (
+                "--program-transformer-channel-size",
+                tree_worker
+                    .program_transformer_worker
+                    .program_transformer_channel_size,
+            ),
+        ];
+
+        match counts.iter().find(|(_, count)| *count == 0) {
+            Some((name, _)) => Err(format!("{} must be greater than 0", name)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A comma separated list of pubkeys, empty when the value is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pubkeys(Vec<Pubkey>);
+
+impl FromStr for Pubkeys {
+    type Err = ParsePubkeyError;
+
+    fn from_str(pubkeys: &str) -> Result<Self, Self::Err> {
+        pubkeys
+            .split(',')
+            .map(str::trim)
+            .filter(|pubkey| !pubkey.is_empty())
+            .map(Pubkey::from_str)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl Deref for Pubkeys {
+    type Target = [Pubkey];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Applies the LightDAS defaults to the arguments of `command`, and hides `--only-trees` and
+/// `--backfill-after-signature` since the trees to index come from `ld_merkle_trees`.
+pub fn with_indexer_defaults(command: Command) -> Command {
+    INDEXER_DEFAULTS
+        .iter()
+        .fold(command, |command, (id, default)| {
+            command.mut_arg(*id, |arg| arg.default_value(*default))
+        })
+        .mut_arg("only_trees", |arg| arg.hide(true))
+        .mut_arg("backfill_after_signature", |arg| arg.hide(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::{CommandFactory, FromArgMatches, Parser};
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        indexer: IndexerArgs,
+    }
+
+    fn parse(args: &[&str]) -> Result<IndexerArgs, clap::Error> {
+        let required = [
+            "lightdas",
+            "--rpc-url=http://rpc",
+            "--ws-url=ws://rpc",
+            "--database-url=postgres://db",
+        ];
+        let matches = with_indexer_defaults(Cli::command())
+            .try_get_matches_from(required.iter().chain(args))?;
+
+        Cli::from_arg_matches(&matches).map(|cli| cli.indexer)
+    }
+
+    #[test]
+    fn uses_the_indexer_defaults_unless_overridden() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.backfill.tree_crawler_count, 4);
+        assert_eq!(
+            args.backfill
+                .tree_worker
+                .signature_worker
+                .signature_worker_count,
+            100
+        );
+        assert_eq!(
+            args.backfill.tree_worker.gap_worker.gap_spill_threshold,
+            1000
+        );
+        assert_eq!(
+            args.backfill.tree_worker.gap_worker.gap_spill_dir,
+            PathBuf::from("gap-spill")
+        );
+        assert_eq!(args.metrics.metrics_prefix, "lightdas");
+        assert_eq!(args.database.database_max_connections, 125);
+        assert_eq!(args.validate(), Ok(()));
+
+        let args = parse(&[
+            "--tree-crawler-count=8",
+            "--gap-spill-dir=/var/lib/lightdas",
+        ])
+        .unwrap();
+        assert_eq!(args.backfill.tree_crawler_count, 8);
+        assert_eq!(
+            args.backfill.tree_worker.gap_worker.gap_spill_dir,
+            PathBuf::from("/var/lib/lightdas")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(parse(&["--tree-metadata-refresh-interval-secs=0"]).is_err());
+        assert!(parse(&[
+            "--grpc-url=http://grpc",
+            "--messenger-redis-url=redis://redis"
+        ])
+        .is_err());
+
+        let creator = Pubkey::new_unique();
+        let args = parse(&[&format!(
+            "--discovery-tree-creators={}, {}",
+            creator, creator
+        )])
+        .unwrap();
+        assert_eq!(*args.discovery_tree_creators, [creator, creator]);
+        let args = parse(&["--discovery-collections="]).unwrap();
+        assert!(args.discovery_collections.is_empty());
+        assert!(parse(&["--discovery-collections=not-a-pubkey"]).is_err());
+        assert!(parse(&["--core-collections=not-a-pubkey"]).is_err());
+
+        let args = parse(&["--signature-worker-count=0"]).unwrap();
+        assert_eq!(
+            args.validate(),
+            Err("--signature-worker-count must be greater than 0".to_string())
+        );
+
+        let args = parse(&[
+            "--database-min-connections=10",
+            "--database-max-connections=5",
+        ])
+        .unwrap();
+        assert!(args.validate().is_err());
+
+        let args = parse(&["--only-trees=tree"]).unwrap();
+        assert!(args.validate().is_err());
+
+        let args = parse(&[&format!(
+            "--backfill-after-signature={}",
+            solana_sdk::signature::Signature::new_unique()
+        )])
+        .unwrap();
+        assert!(args.validate().is_err());
+        assert!(parse(&["--max-concurrent-backfills=0"]).is_err());
+        assert!(parse(&["--tree-verification-interval-secs=0"]).is_err());
+    }
+}
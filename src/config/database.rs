@@ -1,14 +1,11 @@
-use crate::config::env_config::EnvConfig;
+use das_core::PoolArgs;
 use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
     ConnectOptions, PgPool,
 };
 
-const BARE_MINIMUM_CONNECTIONS: u32 = 5;
-const DEFAULT_MAX: u32 = 125;
-pub async fn setup_database_config(config: &EnvConfig) -> PgPool {
-    let url = config.get_database_url();
-    let mut options: PgConnectOptions = url.parse().unwrap();
+pub async fn setup_database_config(config: &PoolArgs) -> PgPool {
+    let mut options: PgConnectOptions = config.database_url.parse().unwrap();
     options.log_statements(log::LevelFilter::Trace);
 
     options.log_slow_statements(
@@ -17,8 +14,8 @@ pub async fn setup_database_config(config: &EnvConfig) -> PgPool {
     );
 
     PgPoolOptions::new()
-        .min_connections(BARE_MINIMUM_CONNECTIONS)
-        .max_connections(DEFAULT_MAX)
+        .min_connections(config.database_min_connections)
+        .max_connections(config.database_max_connections)
         .connect_with(options)
         .await
         .expect("Failed to setup database pool")
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::Command;
+use figment::providers::{Format, Toml};
+use figment::Figment;
+use serde_json::Value;
+use thiserror::Error;
+
+/// The environment variable the config file can be passed with instead of `--config`.
+pub const CONFIG_FILE_ENV: &str = "LIGHTDAS_CONFIG";
+
+#[derive(Error, Debug)]
+pub enum ConfigFileError {
+    #[error(transparent)]
+    Read(#[from] Box<figment::Error>),
+    #[error("unknown setting `{0}`")]
+    UnknownSetting(String),
+    #[error("setting `{0}` must be a string, a number, a boolean or a list of them")]
+    InvalidValue(String),
+}
+
+/// The config file passed with `--config` in `args`, or with `LIGHTDAS_CONFIG`.
+///
+/// It is looked up before the arguments are parsed since its settings become their defaults.
+pub fn config_file_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    std::env::var_os(CONFIG_FILE_ENV).map(PathBuf::from)
+}
+
+/// Makes the settings of the TOML file at `path` the defaults of the matching arguments of
+/// `command`, so the command line and the environment still take precedence over them.
+///
+/// Settings are named after the arguments, `database_max_connections` and
+/// `database-max-connections` both set `--database-max-connections`.
+pub fn apply_config_file(mut command: Command, path: &Path) -> Result<Command, ConfigFileError> {
+    let settings: BTreeMap<String, Value> = Figment::from(Toml::file_exact(path))
+        .extract()
+        .map_err(Box::new)?;
+
+    for (key, value) in settings {
+        let id = key.replace('-', "_");
+        if id == "config"
+            || !command
+                .get_arguments()
+                .any(|arg| arg.get_id() == id.as_str())
+        {
+            return Err(ConfigFileError::UnknownSetting(key));
+        }
+
+        // Lists are passed like on the command line, comma separated
+        let value = match value {
+            Value::Array(values) => values
+                .into_iter()
+                .map(|value| setting_value(&key, value))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            value => setting_value(&key, value)?,
+        };
+
+        // A required argument set by the file can be omitted from the command line
+        command = command.mut_arg(id, |arg| arg.default_value(value).required(false));
+    }
+
+    Ok(command)
+}
+
+fn setting_value(key: &str, value: Value) -> Result<String, ConfigFileError> {
+    match value {
+        Value::String(value) => Ok(value),
+        Value::Bool(_) | Value::Number(_) => Ok(value.to_string()),
+        _ => Err(ConfigFileError::InvalidValue(key.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use clap::{Arg, ArgAction};
+
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("lightdas")
+            .arg(Arg::new("config").long("config"))
+            .arg(
+                Arg::new("rpc_url")
+                    .long("rpc-url")
+                    .env("LIGHTDAS_TEST_RPC_URL")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("worker_count")
+                    .long("worker-count")
+                    .default_value("1"),
+            )
+            .arg(
+                Arg::new("creators")
+                    .long("creators")
+                    .action(ArgAction::Append)
+                    .value_delimiter(','),
+            )
+    }
+
+    fn config_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn finds_the_config_file_on_the_command_line() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            config_file_path(&args(&["lightdas", "--config", "lightdas.toml"])),
+            Some(PathBuf::from("lightdas.toml"))
+        );
+        assert_eq!(
+            config_file_path(&args(&[
+                "lightdas",
+                "--rpc-url=x",
+                "--config=lightdas.toml"
+            ])),
+            Some(PathBuf::from("lightdas.toml"))
+        );
+        assert_eq!(
+            config_file_path(&args(&["lightdas", "--", "--config=lightdas.toml"])),
+            std::env::var_os(CONFIG_FILE_ENV).map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn config_file_settings_are_overridden_by_the_command_line() {
+        let file = config_file(
+            r#"
+            rpc-url = "http://file"
+            worker_count = 4
+            creators = ["a", "b"]
+            "#,
+        );
+        let command = apply_config_file(command(), file.path()).unwrap();
+
+        let matches = command.clone().try_get_matches_from(["lightdas"]).unwrap();
+        assert_eq!(matches.get_one::<String>("rpc_url").unwrap(), "http://file");
+        assert_eq!(matches.get_one::<String>("worker_count").unwrap(), "4");
+        assert_eq!(
+            matches
+                .get_many::<String>("creators")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+
+        let matches = command
+            .try_get_matches_from(["lightdas", "--rpc-url=http://cli", "--worker-count=8"])
+            .unwrap();
+        assert_eq!(matches.get_one::<String>("rpc_url").unwrap(), "http://cli");
+        assert_eq!(matches.get_one::<String>("worker_count").unwrap(), "8");
+    }
+
+    #[test]
+    fn rejects_invalid_config_files() {
+        let file = config_file("rpc_urll = \"http://file\"");
+        assert!(matches!(
+            apply_config_file(command(), file.path()),
+            Err(ConfigFileError::UnknownSetting(setting)) if setting == "rpc_urll"
+        ));
+
+        let file = config_file("[rpc_url]\nurl = \"http://file\"");
+        assert!(matches!(
+            apply_config_file(command(), file.path()),
+            Err(ConfigFileError::InvalidValue(_))
+        ));
+
+        let file = config_file("rpc_url = ");
+        assert!(matches!(
+            apply_config_file(command(), file.path()),
+            Err(ConfigFileError::Read(_))
+        ));
+
+        assert!(matches!(
+            apply_config_file(command(), Path::new("/nonexistent/lightdas.toml")),
+            Err(ConfigFileError::Read(_))
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod args;
 pub mod database;
-pub mod env_config;
+pub mod file;
 pub mod rpc_config;
@@ -1,23 +1,76 @@
-use crate::config::env_config::EnvConfig;
+use crate::config::args::IndexerArgs;
+use backon::{ExponentialBuilder, Retryable};
 use solana_client::nonblocking::pubsub_client::PubsubClient;
 use solana_client::nonblocking::rpc_client::RpcClient;
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
 
-pub static PUBSUB_CLIENT: OnceLock<PubsubClient> = OnceLock::new();
+pub static PUBSUB_CLIENT: OnceLock<Mutex<Arc<PubsubClient>>> = OnceLock::new();
 pub static RPC_CLIENT: OnceLock<RpcClient> = OnceLock::new();
+static WEBSOCKET_URL: OnceLock<String> = OnceLock::new();
+
+const PUBSUB_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+pub async fn setup_rpc_clients(config: &IndexerArgs) {
+    WEBSOCKET_URL
+        .set(config.ws_url.clone())
+        .unwrap_or_else(|_| panic!("websocket url already set"));
 
-pub async fn setup_rpc_clients(config: &EnvConfig) {
     PUBSUB_CLIENT
-        .set(PubsubClient::new(config.get_websocket_url()).await.unwrap())
+        .set(Mutex::new(Arc::new(
+            PubsubClient::new(&config.ws_url).await.unwrap(),
+        )))
         .unwrap_or_else(|_| panic!("pubsub client already set"));
 
     RPC_CLIENT
-        .set(RpcClient::new(config.get_rpc_url().clone().to_owned()))
+        .set(RpcClient::new(config.rpc_url.clone()))
         .unwrap_or_else(|_| panic!("rpc client already set"));
 }
 
-pub fn get_pubsub_client() -> &'static PubsubClient {
-    PUBSUB_CLIENT.get().expect("failed to get pubsub client")
+pub async fn get_pubsub_client() -> Arc<PubsubClient> {
+    let client = PUBSUB_CLIENT
+        .get()
+        .expect("failed to get pubsub client")
+        .lock()
+        .await;
+
+    Arc::clone(&client)
+}
+
+/// Replaces the shared pubsub client after its connection dropped.
+///
+/// Every tree subscribed through `stale` notices the drop at roughly the same time, so only the
+/// first caller rebuilds the client, the others get the already rebuilt one.
+pub async fn reconnect_pubsub_client(stale: &Arc<PubsubClient>) -> Arc<PubsubClient> {
+    let mut client = PUBSUB_CLIENT
+        .get()
+        .expect("failed to get pubsub client")
+        .lock()
+        .await;
+
+    if Arc::ptr_eq(&client, stale) {
+        let websocket_url = WEBSOCKET_URL.get().expect("failed to get websocket url");
+
+        let reconnected = (|| async { PubsubClient::new(websocket_url).await })
+            .retry(
+                &ExponentialBuilder::default()
+                    .with_jitter()
+                    .with_max_delay(PUBSUB_RECONNECT_MAX_DELAY)
+                    .with_max_times(usize::MAX),
+            )
+            .notify(|e, delay| {
+                eprintln!("Error reconnecting pubsub client, retrying in {delay:?}: {e:?}")
+            })
+            .await
+            .expect("pubsub reconnection retries are unbounded");
+
+        println!("Reconnected pubsub client");
+
+        *client = Arc::new(reconnected);
+    }
+
+    Arc::clone(&client)
 }
 
 pub fn get_rpc_client() -> &'static RpcClient {
@@ -0,0 +1,301 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Result;
+use blockbuster::instruction::order_instructions;
+use das_core::Rpc;
+use futures::FutureExt;
+use mpl_core::types::Key;
+use program_transformers::{AccountInfo, ProgramTransformer, TransactionInfo};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::{Pool, Postgres};
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::task;
+
+use crate::source::TransactionSource;
+
+/// Offset of the update authority of an asset account, after its key and its owner.
+const ASSET_UPDATE_AUTHORITY_OFFSET: usize = 33;
+/// Borsh tag of `UpdateAuthority::Collection`.
+const UPDATE_AUTHORITY_COLLECTION: u8 = 2;
+/// Accounts fetched per request.
+const ACCOUNTS_PER_REQUEST: usize = 100;
+
+/// Indexes the MPL Core collections of the config and their assets, through the same program
+/// transformer as the account updates of the other DAS ingesters.
+pub struct CoreCollectionWatcher {
+    rpc: Rpc,
+    program_transformer: ProgramTransformer,
+    collections: HashSet<Pubkey>,
+}
+
+impl CoreCollectionWatcher {
+    pub fn new(rpc: Rpc, database_pool: Pool<Postgres>, collections: &[Pubkey]) -> Self {
+        Self {
+            // The live transactions are not finalized yet, their changes must be read
+            rpc: rpc.with_commitment(CommitmentConfig::confirmed()),
+            program_transformer: ProgramTransformer::new(
+                database_pool,
+                Box::new(|_info| futures::future::ready(Ok(())).boxed()),
+                false,
+            ),
+            collections: collections.iter().copied().collect(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.collections.is_empty()
+    }
+
+    /// Indexes the collections and the assets they hold on startup, then the Core accounts of
+    /// the live transactions touching them.
+    pub async fn run(self, transaction_source: Arc<dyn TransactionSource>) {
+        let (sender, mut receiver) = unbounded_channel::<TransactionInfo>();
+
+        // Subscribed first so that no transaction landing during the scan is missed
+        let subscriptions = self
+            .collections
+            .iter()
+            .map(|address| {
+                let address = *address;
+                let transaction_source = Arc::clone(&transaction_source);
+                let sender = sender.clone();
+
+                task::spawn(async move { transaction_source.subscribe(address, sender).await })
+            })
+            .collect::<Vec<_>>();
+        drop(sender);
+
+        for collection in &self.collections {
+            if let Err(e) = self.scan_collection(collection).await {
+                eprintln!("Error indexing Core collection {:}: {:?}", collection, e);
+            }
+        }
+
+        while let Some(transaction) = receiver.recv().await {
+            let accounts = affected_core_accounts(&self.collections, &transaction);
+
+            if !accounts.is_empty() {
+                if let Err(e) = self.index_accounts(&accounts).await {
+                    eprintln!(
+                        "Error indexing the Core accounts of transaction {:}: {:?}",
+                        transaction.signature, e
+                    );
+                }
+            }
+
+            transaction_source.handled(&transaction.signature);
+        }
+
+        for subscription in subscriptions {
+            subscription.abort();
+        }
+    }
+
+    /// Indexes `collection` and the assets whose update authority it is.
+    async fn scan_collection(&self, collection: &Pubkey) -> Result<()> {
+        let assets = self
+            .rpc
+            .get_program_accounts(
+                &mpl_core::ID,
+                Some(vec![
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, vec![Key::AssetV1 as u8])),
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        ASSET_UPDATE_AUTHORITY_OFFSET,
+                        [&[UPDATE_AUTHORITY_COLLECTION], collection.as_ref()].concat(),
+                    )),
+                ]),
+            )
+            .await?;
+
+        let mut accounts = vec![*collection];
+        accounts.extend(assets.into_iter().map(|(asset, _)| asset));
+        self.index_accounts(&accounts).await?;
+
+        println!(
+            "Indexed Core collection {:} and its {} assets",
+            collection,
+            accounts.len() - 1
+        );
+
+        Ok(())
+    }
+
+    /// Fetches `accounts` and runs the ones owned by MPL Core through the program transformer,
+    /// as of the slot they were read at. Closed accounts are skipped.
+    async fn index_accounts(&self, accounts: &[Pubkey]) -> Result<()> {
+        for batch in accounts.chunks(ACCOUNTS_PER_REQUEST) {
+            let fetched = self.rpc.get_multiple_accounts_with_context(batch).await?;
+            let slot = fetched.context.slot;
+
+            for (pubkey, account) in batch.iter().zip(fetched.value) {
+                let Some(account) = account.filter(|account| account.owner == mpl_core::ID) else {
+                    continue;
+                };
+
+                let account_info = AccountInfo {
+                    slot,
+                    pubkey: *pubkey,
+                    owner: account.owner,
+                    data: account.data,
+                };
+                if let Err(e) = self
+                    .program_transformer
+                    .handle_account_update(&account_info)
+                    .await
+                {
+                    eprintln!("Error indexing Core account {:}: {:?}", pubkey, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The accounts of the MPL Core instructions of `transaction` that touch one of `collections`,
+/// the Core accounts among them are the assets and collections they changed.
+///
+/// The collections come first, the assets of a collection are indexed after it.
+fn affected_core_accounts(
+    collections: &HashSet<Pubkey>,
+    transaction: &TransactionInfo,
+) -> Vec<Pubkey> {
+    let programs = HashSet::from([mpl_core::ID]);
+    let mut accounts = Vec::new();
+
+    let instructions = order_instructions(
+        &programs,
+        &transaction.account_keys,
+        &transaction.message_instructions,
+        &transaction.meta_inner_instructions,
+    );
+
+    for ((_, instruction), _) in instructions {
+        let keys = instruction
+            .accounts
+            .iter()
+            .filter_map(|account| transaction.account_keys.get(*account as usize).copied())
+            .collect::<Vec<_>>();
+
+        if !keys.iter().any(|key| collections.contains(key)) {
+            continue;
+        }
+
+        for key in keys {
+            if key != mpl_core::ID && !accounts.contains(&key) {
+                accounts.push(key);
+            }
+        }
+    }
+
+    accounts.sort_by_key(|key| !collections.contains(key));
+    accounts
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+    use mpl_core::accounts::BaseAssetV1;
+    use mpl_core::instructions::{CreateV1Builder, TransferV1Builder};
+    use mpl_core::types::UpdateAuthority;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::Signature;
+
+    use super::*;
+
+    fn transaction(payer: Pubkey, instructions: &[Instruction]) -> TransactionInfo {
+        let message = Message::new(instructions, Some(&payer));
+
+        TransactionInfo {
+            slot: 1,
+            signature: Signature::default(),
+            account_keys: message.account_keys,
+            message_instructions: message.instructions,
+            meta_inner_instructions: vec![],
+        }
+    }
+
+    fn transfer(payer: Pubkey, asset: Pubkey, collection: Pubkey) -> Instruction {
+        TransferV1Builder::new()
+            .asset(asset)
+            .collection(Some(collection))
+            .payer(payer)
+            .new_owner(Pubkey::new_unique())
+            .instruction()
+    }
+
+    #[test]
+    fn finds_the_accounts_of_the_instructions_touching_watched_collections() {
+        let payer = Pubkey::new_unique();
+        let collection = Pubkey::new_unique();
+        let asset = Pubkey::new_unique();
+        let minted = Pubkey::new_unique();
+        let collections = HashSet::from([collection]);
+
+        let transaction = transaction(
+            payer,
+            &[
+                transfer(payer, Pubkey::new_unique(), Pubkey::new_unique()),
+                transfer(payer, asset, collection),
+                CreateV1Builder::new()
+                    .asset(minted)
+                    .collection(Some(collection))
+                    .payer(payer)
+                    .name(String::new())
+                    .uri(String::new())
+                    .instruction(),
+            ],
+        );
+
+        let accounts = affected_core_accounts(&collections, &transaction);
+        assert!(accounts.contains(&asset));
+        assert!(accounts.contains(&minted));
+        assert_eq!(
+            accounts
+                .iter()
+                .filter(|account| **account == collection)
+                .count(),
+            1
+        );
+        assert!(!accounts.contains(&mpl_core::ID));
+        assert_eq!(accounts[0], collection);
+    }
+
+    #[test]
+    fn matches_the_assets_of_a_collection_by_their_update_authority() {
+        let collection = Pubkey::new_unique();
+        let asset = BaseAssetV1 {
+            key: Key::AssetV1,
+            owner: Pubkey::new_unique(),
+            update_authority: UpdateAuthority::Collection(collection),
+            name: String::from("Asset"),
+            uri: String::new(),
+            seq: None,
+        }
+        .try_to_vec()
+        .unwrap();
+
+        assert_eq!(asset[0], Key::AssetV1 as u8);
+        assert_eq!(
+            asset[ASSET_UPDATE_AUTHORITY_OFFSET..][..33],
+            [&[UPDATE_AUTHORITY_COLLECTION], collection.as_ref()].concat()
+        );
+    }
+
+    #[test]
+    fn ignores_transactions_not_touching_watched_collections() {
+        let payer = Pubkey::new_unique();
+        let collections = HashSet::from([Pubkey::new_unique()]);
+
+        let transaction = transaction(
+            payer,
+            &[transfer(payer, Pubkey::new_unique(), Pubkey::new_unique())],
+        );
+
+        assert!(affected_core_accounts(&collections, &transaction).is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use das_bubblegum_backfill::{BubblegumBackfillContext, DeadLetter, DeadLetterErrorKind};
+use futures::FutureExt;
+use program_transformers::error::ProgramTransformerError;
+use program_transformers::ProgramTransformer;
+use sea_orm::{DatabaseConnection, SqlxPostgresConnector};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio_util::sync::CancellationToken;
+
+use crate::processor::transaction::parse_transaction;
+
+#[derive(Debug, Subcommand)]
+pub enum DeadLetterCommand {
+    /// Lists the transactions that failed to index.
+    List {
+        /// Only list the dead letters of this tree.
+        #[arg(long)]
+        tree: Option<Pubkey>,
+    },
+    /// Fetches and indexes the selected dead letters again, removing the ones that succeed.
+    Replay(DeadLetterSelection),
+    /// Removes the selected dead letters without indexing them.
+    Discard(DeadLetterSelection),
+}
+
+#[derive(Debug, Args)]
+#[group(required = true, multiple = true)]
+pub struct DeadLetterSelection {
+    /// Signatures of the dead letters.
+    signatures: Vec<Signature>,
+    /// Every dead letter of this tree.
+    #[arg(long)]
+    tree: Option<Pubkey>,
+    /// Every dead letter.
+    #[arg(long)]
+    all: bool,
+}
+
+/// Records that `signature` failed to index for `tree`.
+pub async fn record_dead_letter(
+    context: &BubblegumBackfillContext,
+    tree: Pubkey,
+    signature: Signature,
+    slot: Option<u64>,
+    error_kind: DeadLetterErrorKind,
+    error: &str,
+) {
+    let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+
+    if let Err(e) = DeadLetter::record(&conn, tree, signature, slot, error_kind, error).await {
+        eprintln!(
+            "Error recording dead letter {:} for tree {:}: {:?}",
+            signature, tree, e
+        );
+    }
+}
+
+/// Replays the dead letters attempted fewer than `max_attempts` times every `retry_interval`,
+/// until `shutdown` is cancelled.
+pub async fn retry_dead_letters(
+    context: BubblegumBackfillContext,
+    retry_interval: Duration,
+    max_attempts: u32,
+    shutdown: CancellationToken,
+) {
+    let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+    let program_transformer = program_transformer(&context);
+    let mut retry = tokio::time::interval(retry_interval);
+
+    loop {
+        tokio::select! {
+            _ = retry.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+
+        let dead_letters = match DeadLetter::find(&conn, None, Some(max_attempts)).await {
+            Ok(dead_letters) => dead_letters,
+            Err(e) => {
+                eprintln!("Error fetching dead letters: {:?}", e);
+                continue;
+            }
+        };
+
+        for dead_letter in dead_letters {
+            if shutdown.is_cancelled() {
+                return;
+            }
+
+            replay(&context, &conn, &program_transformer, &dead_letter).await;
+        }
+    }
+}
+
+pub async fn run_dead_letter_command(
+    command: DeadLetterCommand,
+    context: BubblegumBackfillContext,
+) -> Result<()> {
+    let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+
+    match command {
+        DeadLetterCommand::List { tree } => {
+            for dead_letter in DeadLetter::find(&conn, tree, None).await? {
+                println!(
+                    "{:} tree={:} slot={} kind={} attempts={} error={}",
+                    dead_letter.signature()?,
+                    dead_letter.tree()?,
+                    dead_letter
+                        .slot
+                        .map_or_else(|| "unknown".to_string(), |slot| slot.to_string()),
+                    dead_letter.error_kind,
+                    dead_letter.attempts,
+                    dead_letter.error
+                );
+            }
+        }
+        DeadLetterCommand::Replay(selection) => {
+            let program_transformer = program_transformer(&context);
+
+            for dead_letter in select(&conn, &selection).await? {
+                if replay(&context, &conn, &program_transformer, &dead_letter).await {
+                    println!("Replayed {:}", dead_letter.signature()?);
+                }
+            }
+        }
+        DeadLetterCommand::Discard(selection) => {
+            for dead_letter in select(&conn, &selection).await? {
+                dead_letter.delete(&conn).await?;
+                println!("Discarded {:}", dead_letter.signature()?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn select(
+    conn: &DatabaseConnection,
+    selection: &DeadLetterSelection,
+) -> Result<Vec<DeadLetter>> {
+    let dead_letters = DeadLetter::find(conn, selection.tree, None).await?;
+
+    if selection.all || selection.signatures.is_empty() {
+        return Ok(dead_letters);
+    }
+
+    Ok(dead_letters
+        .into_iter()
+        .filter(|dead_letter| {
+            dead_letter
+                .signature()
+                .is_ok_and(|signature| selection.signatures.contains(&signature))
+        })
+        .collect())
+}
+
+fn program_transformer(context: &BubblegumBackfillContext) -> ProgramTransformer {
+    // Audits record the last applied seq live processing resumes from
+    ProgramTransformer::new(
+        context.database_pool.clone(),
+        Box::new(|_info| futures::future::ready(Ok(())).boxed()),
+        true,
+    )
+}
+
+/// Fetches and indexes `dead_letter` again. Returns whether it succeeded and was removed, a
+/// failure is recorded as another attempt.
+async fn replay(
+    context: &BubblegumBackfillContext,
+    conn: &DatabaseConnection,
+    program_transformer: &ProgramTransformer,
+    dead_letter: &DeadLetter,
+) -> bool {
+    let (tree, signature) = match (dead_letter.tree(), dead_letter.signature()) {
+        (Ok(tree), Ok(signature)) => (tree, signature),
+        _ => {
+            eprintln!("Invalid dead letter {:?}", dead_letter);
+            return false;
+        }
+    };
+
+    match fetch_and_apply(context, program_transformer, signature).await {
+        Ok(()) => match dead_letter.delete(conn).await {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Error removing dead letter {:}: {:?}", signature, e);
+                false
+            }
+        },
+        Err((slot, error_kind, e)) => {
+            eprintln!(
+                "Error replaying dead letter {:} for tree {:}: {}",
+                signature, tree, e
+            );
+            record_dead_letter(context, tree, signature, slot, error_kind, &e).await;
+            false
+        }
+    }
+}
+
+async fn fetch_and_apply(
+    context: &BubblegumBackfillContext,
+    program_transformer: &ProgramTransformer,
+    signature: Signature,
+) -> Result<(), (Option<u64>, DeadLetterErrorKind, String)> {
+    let transaction = context
+        .solana_rpc
+        .with_commitment(CommitmentConfig::confirmed())
+        .get_transaction(&signature)
+        .await
+        .map_err(|e| (None, DeadLetterErrorKind::Fetch, e.to_string()))?;
+    let slot = transaction.slot;
+
+    let transaction = parse_transaction(transaction)
+        .map_err(|e| (Some(slot), DeadLetterErrorKind::Parse, e.to_string()))?;
+
+    match program_transformer.handle_transaction(&transaction).await {
+        Ok(()) | Err(ProgramTransformerError::NotImplemented) => Ok(()),
+        Err(e) => Err((Some(slot), DeadLetterErrorKind::Index, e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(subcommand)]
+        command: DeadLetterCommand,
+    }
+
+    #[test]
+    fn requires_a_selection_to_replay_or_discard() {
+        let signature = Signature::new_unique();
+        let tree = Pubkey::new_unique();
+
+        assert!(Cli::try_parse_from(["cli", "discard"]).is_err());
+        assert!(Cli::try_parse_from(["cli", "replay"]).is_err());
+
+        match Cli::try_parse_from(["cli", "discard", &signature.to_string()]) {
+            Ok(Cli {
+                command: DeadLetterCommand::Discard(selection),
+            }) => {
+                assert_eq!(selection.signatures, vec![signature]);
+                assert!(selection.tree.is_none() && !selection.all);
+            }
+            parsed => panic!("unexpected {parsed:?}"),
+        }
+
+        match Cli::try_parse_from(["cli", "replay", "--tree", &tree.to_string()]) {
+            Ok(Cli {
+                command: DeadLetterCommand::Replay(selection),
+            }) => {
+                assert!(selection.signatures.is_empty());
+                assert_eq!(selection.tree, Some(tree));
+            }
+            parsed => panic!("unexpected {parsed:?}"),
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use blockbuster::instruction::order_instructions;
+use das_bubblegum_backfill::TreeResponse;
+use das_core::Rpc;
+use digital_asset_types::dao::ld_merkle_trees;
+use mpl_bubblegum::accounts::TreeConfig;
+use mpl_bubblegum::{get_instruction_type, InstructionName};
+use program_transformers::TransactionInfo;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{EntityTrait, Set, SqlxPostgresConnector};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::{Pool, Postgres};
+use tokio::sync::mpsc::{unbounded_channel, Sender};
+use tokio::task;
+
+use crate::source::TransactionSource;
+
+/// Anchor discriminator of tree config accounts, the first bytes of `sha256("account:TreeConfig")`.
+const TREE_CONFIG_DISCRIMINATOR: [u8; 8] = [122, 245, 175, 248, 171, 34, 0, 207];
+/// Offset of the tree creator in a tree config account, after its discriminator.
+const TREE_CREATOR_OFFSET: usize = 8;
+
+// See Bubblegum for the account positions:
+// https://github.com/metaplex-foundation/mpl-bubblegum/blob/main/programs/bubblegum/README.md
+const CREATE_TREE_MERKLE_TREE_INDEX: usize = 1;
+const CREATE_TREE_TREE_CREATOR_INDEX: usize = 3;
+/// Shared by `mint_to_collection_v1`, `verify_collection` and `set_and_verify_collection`.
+const COLLECTION_MERKLE_TREE_INDEX: usize = 3;
+const COLLECTION_COLLECTION_MINT_INDEX: usize = 8;
+
+/// The configured value a discovered tree matched, stored as the tag of the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveredBy {
+    Creator(Pubkey),
+    Collection(Pubkey),
+}
+
+impl fmt::Display for DiscoveredBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Creator(creator) => write!(f, "creator:{}", creator),
+            Self::Collection(collection) => write!(f, "collection:{}", collection),
+        }
+    }
+}
+
+/// Registers the trees created by the configured tree creators, or minted into the configured
+/// collections, in `ld_merkle_trees`.
+pub struct TreeDiscovery {
+    rpc: Rpc,
+    database_pool: Pool<Postgres>,
+    creators: HashSet<Pubkey>,
+    collections: HashSet<Pubkey>,
+    /// Notified when new trees are registered, so they get indexed.
+    reload_sender: Sender<()>,
+}
+
+impl TreeDiscovery {
+    pub fn new(
+        rpc: Rpc,
+        database_pool: Pool<Postgres>,
+        creators: &[Pubkey],
+        collections: &[Pubkey],
+        reload_sender: Sender<()>,
+    ) -> Self {
+        Self {
+            rpc,
+            database_pool,
+            creators: creators.iter().copied().collect(),
+            collections: collections.iter().copied().collect(),
+            reload_sender,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.creators.is_empty() || !self.collections.is_empty()
+    }
+
+    /// Watches the live transactions of the creators and collections, and scans the trees of the
+    /// creators every `scan_interval`, starting immediately.
+    ///
+    /// Collections are only watched live, trees minted into them before are not discovered.
+    pub async fn run(
+        self,
+        transaction_source: Arc<dyn TransactionSource>,
+        scan_interval: Duration,
+    ) {
+        let (sender, mut receiver) = unbounded_channel::<TransactionInfo>();
+
+        let subscriptions = self
+            .creators
+            .iter()
+            .chain(&self.collections)
+            .map(|address| {
+                let address = *address;
+                let transaction_source = Arc::clone(&transaction_source);
+                let sender = sender.clone();
+
+                task::spawn(async move { transaction_source.subscribe(address, sender).await })
+            })
+            .collect::<Vec<_>>();
+        drop(sender);
+
+        let mut scan = tokio::time::interval(scan_interval);
+
+        loop {
+            tokio::select! {
+                _ = scan.tick() => {
+                    match self.scan_creators().await {
+                        Ok(trees) => self.register(trees).await,
+                        Err(e) => eprintln!("Error scanning the trees of the tree creators: {:?}", e),
+                    }
+                },
+                transaction = receiver.recv() => {
+                    let Some(transaction) = transaction else {
+                        break;
+                    };
+
+                    let trees =
+                        discover_trees(&self.creators, &self.collections, &transaction);
+
+                    if !trees.is_empty() {
+                        match self.existing_trees(trees).await {
+                            Ok(trees) => self.register(trees).await,
+                            Err(e) => eprintln!(
+                                "Error fetching the trees discovered in transaction {:}: {:?}",
+                                transaction.signature, e
+                            ),
+                        }
+                    }
+
+                    transaction_source.handled(&transaction.signature);
+                },
+            }
+        }
+
+        for subscription in subscriptions {
+            subscription.abort();
+        }
+    }
+
+    /// Finds the tree configs of every creator, then the trees they are the authority of.
+    async fn scan_creators(&self) -> Result<Vec<(Pubkey, DiscoveredBy)>> {
+        let mut trees = Vec::new();
+
+        for creator in &self.creators {
+            let tree_configs = self
+                .rpc
+                .get_program_accounts(
+                    &mpl_bubblegum::ID,
+                    Some(vec![
+                        RpcFilterType::DataSize(TreeConfig::LEN as u64),
+                        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                            0,
+                            TREE_CONFIG_DISCRIMINATOR.to_vec(),
+                        )),
+                        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                            TREE_CREATOR_OFFSET,
+                            creator.to_bytes().to_vec(),
+                        )),
+                    ]),
+                )
+                .await?;
+
+            for (tree_config, _) in tree_configs {
+                for tree in TreeResponse::all_by_authority(&self.rpc, &tree_config).await? {
+                    trees.push((tree.pubkey, DiscoveredBy::Creator(*creator)));
+                }
+            }
+        }
+
+        Ok(trees)
+    }
+
+    /// Drops the trees that are not Bubblegum trees on chain, e.g. from a failed transaction.
+    ///
+    /// Read at `confirmed`, the live transaction creating them is not finalized yet.
+    async fn existing_trees(
+        &self,
+        trees: Vec<(Pubkey, DiscoveredBy)>,
+    ) -> Result<Vec<(Pubkey, DiscoveredBy)>> {
+        let addresses = trees.iter().map(|(tree, _)| *tree).collect::<Vec<_>>();
+        let accounts = self
+            .rpc
+            .with_commitment(CommitmentConfig::confirmed())
+            .get_multiple_accounts(&addresses)
+            .await?;
+
+        Ok(trees
+            .into_iter()
+            .zip(accounts)
+            .filter_map(|(tree, account)| {
+                TreeResponse::try_from_rpc(tree.0, account?).ok()?;
+                Some(tree)
+            })
+            .collect())
+    }
+
+    /// Inserts the trees not registered yet and requests a reload if there are any.
+    async fn register(&self, trees: Vec<(Pubkey, DiscoveredBy)>) {
+        let database_connection =
+            SqlxPostgresConnector::from_sqlx_postgres_pool(self.database_pool.clone());
+        let mut registered = 0;
+
+        for (tree, discovered_by) in trees {
+            let res = ld_merkle_trees::Entity::insert(ld_merkle_trees::ActiveModel {
+                address: Set(tree.to_string()),
+                tag: Set(Some(discovered_by.to_string())),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::column(ld_merkle_trees::Column::Address)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(&database_connection)
+            .await;
+
+            match res {
+                Ok(0) => {}
+                Ok(_) => {
+                    println!("Discovered tree {:} by {:}", tree, discovered_by);
+                    registered += 1;
+                }
+                Err(e) => eprintln!("Error registering discovered tree {:}: {:?}", tree, e),
+            }
+        }
+
+        // A full channel means a reload is already pending
+        if registered > 0 {
+            let _ = self.reload_sender.try_send(());
+        }
+    }
+}
+
+/// The trees `transaction` created for one of `creators`, or minted or verified into one of
+/// `collections`, in instruction order.
+fn discover_trees(
+    creators: &HashSet<Pubkey>,
+    collections: &HashSet<Pubkey>,
+    transaction: &TransactionInfo,
+) -> Vec<(Pubkey, DiscoveredBy)> {
+    let programs = HashSet::from([mpl_bubblegum::ID]);
+
+    order_instructions(
+        &programs,
+        &transaction.account_keys,
+        &transaction.message_instructions,
+        &transaction.meta_inner_instructions,
+    )
+    .into_iter()
+    .filter(|((_, instruction), _)| instruction.data.len() >= 8)
+    .filter_map(|((_, instruction), _)| {
+        let key = |index: usize| {
+            instruction
+                .accounts
+                .get(index)
+                .and_then(|account| transaction.account_keys.get(*account as usize))
+                .copied()
+        };
+
+        match get_instruction_type(&instruction.data) {
+            InstructionName::CreateTree => {
+                let creator = key(CREATE_TREE_TREE_CREATOR_INDEX)?;
+                let tree = key(CREATE_TREE_MERKLE_TREE_INDEX)?;
+
+                creators
+                    .contains(&creator)
+                    .then_some((tree, DiscoveredBy::Creator(creator)))
+            }
+            InstructionName::MintToCollectionV1
+            | InstructionName::VerifyCollection
+            | InstructionName::SetAndVerifyCollection => {
+                let collection = key(COLLECTION_COLLECTION_MINT_INDEX)?;
+                let tree = key(COLLECTION_MERKLE_TREE_INDEX)?;
+
+                collections
+                    .contains(&collection)
+                    .then_some((tree, DiscoveredBy::Collection(collection)))
+            }
+            _ => None,
+        }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+
+    use borsh::BorshSerialize;
+    use das_core::SolanaRpcArgs;
+    use jsonrpsee::server::ServerBuilder;
+    use jsonrpsee::RpcModule;
+    use mpl_bubblegum::instructions::{CreateTreeConfigBuilder, MintToCollectionV1Builder};
+    use mpl_bubblegum::types::{DecompressibleState, MetadataArgs, TokenProgramVersion};
+    use serde_json::{json, Value};
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::Signature;
+    use sqlx::postgres::PgPoolOptions;
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+
+    fn transaction(instructions: &[Instruction]) -> TransactionInfo {
+        let message = Message::new(instructions, Some(&Pubkey::new_unique()));
+
+        TransactionInfo {
+            slot: 1,
+            signature: Signature::default(),
+            account_keys: message.account_keys,
+            message_instructions: message.instructions,
+            meta_inner_instructions: vec![],
+        }
+    }
+
+    fn create_tree(tree: Pubkey, creator: Pubkey) -> Instruction {
+        CreateTreeConfigBuilder::new()
+            .tree_config(TreeConfig::find_pda(&tree).0)
+            .merkle_tree(tree)
+            .payer(creator)
+            .tree_creator(creator)
+            .max_depth(14)
+            .max_buffer_size(64)
+            .instruction()
+    }
+
+    fn mint_to_collection(tree: Pubkey, collection: Pubkey) -> Instruction {
+        let authority = Pubkey::new_unique();
+
+        MintToCollectionV1Builder::new()
+            .tree_config(TreeConfig::find_pda(&tree).0)
+            .leaf_owner(authority)
+            .leaf_delegate(authority)
+            .merkle_tree(tree)
+            .payer(authority)
+            .tree_creator_or_delegate(authority)
+            .collection_authority(authority)
+            .collection_mint(collection)
+            .collection_metadata(Pubkey::new_unique())
+            .collection_edition(Pubkey::new_unique())
+            .metadata(MetadataArgs {
+                name: String::new(),
+                symbol: String::new(),
+                uri: String::new(),
+                seller_fee_basis_points: 0,
+                primary_sale_happened: false,
+                is_mutable: true,
+                edition_nonce: None,
+                token_standard: None,
+                collection: None,
+                uses: None,
+                token_program_version: TokenProgramVersion::Original,
+                creators: vec![],
+            })
+            .instruction()
+    }
+
+    #[test]
+    fn discovers_trees_created_by_watched_creators() {
+        let creator = Pubkey::new_unique();
+        let tree = Pubkey::new_unique();
+        let creators = HashSet::from([creator]);
+
+        let transaction = transaction(&[
+            create_tree(tree, creator),
+            create_tree(Pubkey::new_unique(), Pubkey::new_unique()),
+        ]);
+
+        assert_eq!(
+            discover_trees(&creators, &HashSet::new(), &transaction),
+            vec![(tree, DiscoveredBy::Creator(creator))]
+        );
+    }
+
+    #[test]
+    fn discovers_trees_minted_into_watched_collections() {
+        let collection = Pubkey::new_unique();
+        let tree = Pubkey::new_unique();
+        let collections = HashSet::from([collection]);
+
+        let transaction = transaction(&[
+            mint_to_collection(Pubkey::new_unique(), Pubkey::new_unique()),
+            mint_to_collection(tree, collection),
+        ]);
+
+        assert_eq!(
+            discover_trees(&HashSet::new(), &collections, &transaction),
+            vec![(tree, DiscoveredBy::Collection(collection))]
+        );
+    }
+
+    #[test]
+    fn matches_tree_configs_by_their_layout() {
+        let creator = Pubkey::new_unique();
+        let tree_config = TreeConfig {
+            discriminator: TREE_CONFIG_DISCRIMINATOR,
+            tree_creator: creator,
+            tree_delegate: creator,
+            total_mint_capacity: 1 << 14,
+            num_minted: 0,
+            is_public: false,
+            is_decompressible: DecompressibleState::Disabled,
+        }
+        .try_to_vec()
+        .unwrap();
+
+        assert_eq!(
+            TREE_CONFIG_DISCRIMINATOR[..],
+            solana_sdk::hash::hash(b"account:TreeConfig").to_bytes()[..8]
+        );
+        // Allocated with padding past the fields
+        assert!(tree_config.len() <= TreeConfig::LEN);
+        assert_eq!(tree_config[TREE_CREATOR_OFFSET..][..32], creator.to_bytes());
+    }
+
+    #[tokio::test]
+    async fn checks_discovered_trees_at_confirmed_commitment() {
+        let server = ServerBuilder::default()
+            .build("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+        let address = server.local_addr().unwrap();
+
+        let commitments = Arc::new(Mutex::new(Vec::<String>::new()));
+        let mut module = RpcModule::new(Arc::clone(&commitments));
+        // Queried by the client to pick how commitments are sent
+        module
+            .register_method("getVersion", |_params, _commitments| {
+                Ok(json!({ "solana-core": "1.17.14", "feature-set": 0 }))
+            })
+            .unwrap();
+        module
+            .register_method("getMultipleAccounts", |params, commitments| {
+                let (addresses, config) = params.parse::<(Vec<String>, Value)>()?;
+                commitments.lock().unwrap().push(
+                    config["commitment"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                );
+
+                Ok(json!({
+                    "context": { "slot": 1 },
+                    "value": vec![Value::Null; addresses.len()],
+                }))
+            })
+            .unwrap();
+        // Leaked so the server keeps running until the test ends
+        std::mem::forget(server.start(module).unwrap());
+
+        let (reload_sender, _reload_receiver) = channel(1);
+        let discovery = TreeDiscovery::new(
+            Rpc::from_config(&SolanaRpcArgs {
+                solana_rpc_url: format!("http://{address}"),
+            }),
+            // Never connected, nothing gets registered
+            PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/das")
+                .unwrap(),
+            &[],
+            &[],
+            reload_sender,
+        );
+
+        let creator = Pubkey::new_unique();
+        let trees = discovery
+            .existing_trees(vec![(Pubkey::new_unique(), DiscoveredBy::Creator(creator))])
+            .await
+            .unwrap();
+
+        assert!(trees.is_empty());
+        assert_eq!(*commitments.lock().unwrap(), vec!["confirmed".to_string()]);
+    }
+}
@@ -1,21 +1,26 @@
-use std::pin::Pin;
-
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use crate::api::server::start_api_server;
+use crate::config::args::{with_indexer_defaults, IndexerArgs};
 use crate::config::database::setup_database_config;
-use crate::config::env_config::{setup_env_config, EnvConfig};
+use crate::config::file::{apply_config_file, config_file_path};
 use anyhow::Result;
-use config::rpc_config::{get_pubsub_client, setup_rpc_clients};
-use das_bubblegum_backfill::worker::{
-    GapWorkerArgs, ProgramTransformerWorkerArgs, SignatureWorkerArgs,
-};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use config::rpc_config::setup_rpc_clients;
+use core_collections::CoreCollectionWatcher;
 use das_bubblegum_backfill::{
-    start_bubblegum_backfill, BubblegumBackfillArgs, BubblegumBackfillContext,
+    reset_tree_index, start_bubblegum_backfill, BubblegumBackfillContext,
 };
-use das_core::{MetadataJsonDownloadWorkerArgs, Rpc, SolanaRpcArgs};
+use das_core::{setup_metrics, Rpc, SolanaRpcArgs};
+use dead_letter::{retry_dead_letters, run_dead_letter_command, DeadLetterCommand};
+use discovery::TreeDiscovery;
 use dotenv::dotenv;
 
 use futures::prelude::*;
@@ -23,90 +28,315 @@ use futures::prelude::*;
 use log::info;
 use mpl_token_metadata::types::Data;
 use processor::transactions_channel_processor::process_transactions_channel;
-use program_transformers::ProgramTransformer;
+use program_transformers::{ProgramTransformer, TransactionInfo};
+use source::{setup_transaction_source, TransactionSource};
+use tree_metadata::refresh_tree_metadata;
+use tree_registry::listen_tree_registry_changes;
+use tree_settings::TreeSettings;
+use verifier::{
+    run_check_leaves_command, run_check_proofs_command, run_verify_command, verify_trees,
+    CheckLeavesCommand, CheckProofsCommand, VerifyCommand,
+};
 
-use sea_orm::{ConnectionTrait, DatabaseConnection, SqlxPostgresConnector, Statement};
-use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
-use solana_client::rpc_response::{Response, RpcLogsResponse};
-use solana_sdk::commitment_config::CommitmentConfig;
+use digital_asset_types::dao::ld_merkle_trees;
+use digital_asset_types::dao::sea_orm_active_enums::LdMerkleTreeStatus;
+use migration::{Migrator, MigratorTrait};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+    SqlxPostgresConnector,
+};
 
 use solana_sdk::pubkey::Pubkey;
 use sqlx::{Pool, Postgres};
 
+use tokio::sync::Semaphore;
 use tokio::task::{self};
+use tokio_util::sync::CancellationToken;
 
-use signal_hook::{consts::signal::SIGHUP, iterator::Signals};
+use signal_hook::{
+    consts::signal::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
 
+mod api;
 mod config;
+mod core_collections;
+mod dead_letter;
+mod discovery;
 mod processor;
 mod rpc;
+mod source;
+mod tree_metadata;
+mod tree_registry;
+mod tree_settings;
+mod verifier;
+
+/// Indexes the trees of `ld_merkle_trees` when no command is given.
+///
+/// Every setting is read from its flag, then its environment variable, then the config file,
+/// falling back to its default.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// A TOML file setting any of the options below, named like their flag without the leading
+    /// dashes.
+    #[arg(long, env = config::file::CONFIG_FILE_ENV)]
+    config: Option<PathBuf>,
+
+    #[command(flatten)]
+    indexer: IndexerArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Cli {
+    /// Parses `args` on top of the config file, exiting with the usage on invalid settings.
+    fn load(args: Vec<OsString>) -> Self {
+        let mut command = with_indexer_defaults(Self::command());
+
+        if let Some(path) = config_file_path(&args) {
+            command = apply_config_file(command, &path).unwrap_or_else(|e| {
+                Self::command()
+                    .error(
+                        ErrorKind::InvalidValue,
+                        format!("invalid config file {}: {}", path.display(), e),
+                    )
+                    .exit()
+            });
+        }
+
+        let cli = command
+            .try_get_matches_from_mut(args)
+            .and_then(|matches| Self::from_arg_matches(&matches))
+            .unwrap_or_else(|e| e.exit());
+
+        if let Err(e) = cli.indexer.validate() {
+            command.error(ErrorKind::ValueValidation, e).exit();
+        }
+
+        cli
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manages the transactions that failed to index.
+    #[command(subcommand)]
+    DeadLetters(DeadLetterCommand),
+    /// Verifies the indexed trees against the change logs buffered in their account.
+    Verify(VerifyCommand),
+    /// Checks that the proof of every indexed leaf hashes up to the indexed root.
+    CheckProofs(CheckProofsCommand),
+    /// Checks that the rows of every compressed asset still hash to its indexed leaf.
+    CheckLeaves(CheckLeavesCommand),
+}
 
 struct State {
-    tree_addresses: Vec<String>,
-    tasks: Vec<(String, task::JoinHandle<()>)>,
+    /// The trees to index and their settings, highest priority first.
+    trees: Vec<(String, TreeSettings)>,
+    tasks: Vec<TreeTask>,
+    /// Limits the trees backfilled at once, when set.
+    backfill_permits: Option<Arc<Semaphore>>,
+    /// Trees to backfill again from scratch on the next reload.
+    rebackfills: HashSet<String>,
+}
+
+/// The task indexing a tree, cancelling `shutdown` winds it down cooperatively.
+struct TreeTask {
+    address: String,
+    settings: TreeSettings,
+    shutdown: CancellationToken,
+    handle: task::JoinHandle<()>,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
 
-    let env_config = setup_env_config();
+    let cli = Cli::load(std::env::args_os().collect());
+    let config = cli.indexer;
 
-    setup_rpc_clients(&env_config).await;
+    if let Some(command) = cli.command {
+        let database_pool = setup_database_config(&config.database).await;
 
-    let database_pool = setup_database_config(&env_config).await;
+        if let Err(e) = configure_database(&config.database.database_url).await {
+            panic!("Error configuring database: {:?}", e);
+        }
 
-    if let Err(e) = configure_database(SqlxPostgresConnector::from_sqlx_postgres_pool(
-        database_pool.clone(),
-    ))
-    .await
-    {
+        let context = BubblegumBackfillContext::new(
+            database_pool,
+            Rpc::from_config(&SolanaRpcArgs {
+                solana_rpc_url: config.rpc_url.clone(),
+            }),
+        );
+
+        return match command {
+            Command::DeadLetters(command) => run_dead_letter_command(command, context).await,
+            Command::Verify(command) => run_verify_command(command, context).await,
+            Command::CheckProofs(command) => run_check_proofs_command(command, context).await,
+            Command::CheckLeaves(command) => run_check_leaves_command(command, context).await,
+        };
+    }
+
+    setup_metrics(&config.metrics)?;
+
+    setup_rpc_clients(&config).await;
+
+    let database_pool = setup_database_config(&config.database).await;
+
+    if let Err(e) = configure_database(&config.database.database_url).await {
         panic!("Error configuring database: {:?}", e);
     }
 
-    let tree_addresses = match get_trees(SqlxPostgresConnector::from_sqlx_postgres_pool(
+    let rpc = Rpc::from_config(&SolanaRpcArgs {
+        solana_rpc_url: config.rpc_url.clone(),
+    });
+
+    // Keep the handle alive for as long as the process runs, dropping it stops the server
+    let _api_server_handle = match &config.api_listen_address {
+        Some(listen_address) => {
+            let handle =
+                start_api_server(listen_address, database_pool.clone(), rpc.clone()).await?;
+            println!("DAS API server listening on {}", listen_address);
+            Some(handle)
+        }
+        None => None,
+    };
+
+    let transaction_source = setup_transaction_source(&config, database_pool.clone()).await?;
+
+    let (signal_tx, mut signal_rx) = tokio::sync::mpsc::channel(1);
+
+    let tree_discovery = TreeDiscovery::new(
+        rpc.clone(),
+        database_pool.clone(),
+        &config.discovery_tree_creators,
+        &config.discovery_collections,
+        signal_tx.clone(),
+    );
+    let tree_discovery_enabled = tree_discovery.is_enabled();
+    if tree_discovery_enabled {
+        task::spawn(tree_discovery.run(
+            Arc::clone(&transaction_source),
+            config.tree_discovery_interval(),
+        ));
+    }
+
+    let core_collection_watcher =
+        CoreCollectionWatcher::new(rpc.clone(), database_pool.clone(), &config.core_collections);
+    let core_collections_enabled = core_collection_watcher.is_enabled();
+    if core_collections_enabled {
+        task::spawn(core_collection_watcher.run(Arc::clone(&transaction_source)));
+    }
+
+    let trees = match get_trees(SqlxPostgresConnector::from_sqlx_postgres_pool(
         database_pool.clone(),
     ))
     .await
     {
-        Ok(tree_addresses) => tree_addresses,
+        Ok(trees) => trees,
         Err(e) => {
             eprintln!("Error getting trees: {:?}", e);
             return Err(e);
         }
     };
 
-    if tree_addresses.is_empty() {
-        eprintln!("No trees found. Exiting...");
+    // Discovered trees are picked up by a reload, Core collections are indexed without trees
+    if trees.is_empty() && !tree_discovery_enabled && !core_collections_enabled {
+        panic!("Trees to index not found in the database");
     }
 
     let state = Arc::new(std::sync::Mutex::new(State {
-        tree_addresses,
+        trees,
         tasks: vec![],
+        backfill_permits: config
+            .max_concurrent_backfills
+            .map(|permits| Arc::new(Semaphore::new(permits as usize))),
+        rebackfills: HashSet::new(),
     }));
 
     let state_clone = Arc::clone(&state);
 
-    let (signal_tx, mut signal_rx) = tokio::sync::mpsc::channel(1);
+    task::spawn(listen_tree_registry_changes(
+        database_pool.clone(),
+        signal_tx.clone(),
+    ));
+
+    let shutdown = CancellationToken::new();
+    let shutdown_timeout = config.shutdown_timeout();
+
+    task::spawn(retry_dead_letters(
+        BubblegumBackfillContext::new(database_pool.clone(), rpc.clone()),
+        config.dead_letter_retry_interval(),
+        config.dead_letter_max_attempts,
+        shutdown.clone(),
+    ));
+
+    let (rebackfill_tx, mut rebackfill_rx) = tokio::sync::mpsc::channel(1);
+    if let Some(interval) = config.tree_verification_interval() {
+        task::spawn(verify_trees(
+            BubblegumBackfillContext::new(database_pool.clone(), rpc.clone()),
+            interval,
+            config.rebackfill_on_mismatch.then_some(rebackfill_tx),
+            shutdown.clone(),
+        ));
+    }
 
-    // thread to handle SIGHUP
+    // thread to handle SIGHUP, kept as a fallback to the tree registry notifications, and the
+    // shutdown signals
+    let signal_shutdown = shutdown.clone();
     thread::spawn(move || {
-        let mut signals = Signals::new(&[SIGHUP]).unwrap();
-        for _ in signals.forever() {
-            let _ = signal_tx.blocking_send(());
+        let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM]).unwrap();
+        for signal in signals.forever() {
+            if signal == SIGHUP {
+                let _ = signal_tx.blocking_send(());
+            } else if signal_shutdown.is_cancelled() {
+                eprintln!("Received a second shutdown signal, exiting immediately");
+                std::process::exit(1);
+            } else {
+                signal_shutdown.cancel();
+            }
         }
     });
 
+    // The first tick completes immediately, filling in the metadata of the trees on startup
+    let mut tree_metadata_refresh = tokio::time::interval(config.tree_metadata_refresh_interval());
+
     let mut state = state_clone.lock().unwrap();
-    reload_tasks(&mut *state, database_pool.clone(), env_config);
+    reload_tasks(
+        &mut *state,
+        database_pool.clone(),
+        &config,
+        &transaction_source,
+        &shutdown,
+    );
 
     loop {
         tokio::select! {
             _ = tokio::time::sleep(Duration::from_secs(1)) => {
                 // publish metrics
             },
+            _ = tree_metadata_refresh.tick() => {
+                let rpc = rpc.clone();
+                let database_pool = database_pool.clone();
+                let tree_addresses = state
+                    .trees
+                    .iter()
+                    .map(|(address, _)| address.clone())
+                    .collect::<Vec<_>>();
+
+                task::spawn(async move {
+                    if let Err(e) =
+                        refresh_tree_metadata(&rpc, &database_pool, &tree_addresses).await
+                    {
+                        eprintln!("Error refreshing tree metadata: {:?}", e);
+                    }
+                });
+            },
             _ = signal_rx.recv() => {
-                println!("Received SIGHUP, reloading...");
+                println!("Tree registry changed or SIGHUP received, reloading...");
 
                 let trees = get_trees(SqlxPostgresConnector::from_sqlx_postgres_pool(
                     database_pool.clone(),
@@ -114,176 +344,347 @@ async fn main() -> Result<()> {
                 .await
                 .unwrap();
 
-                state.tree_addresses = trees;
+                state.trees = trees;
 
                 reload_tasks(
                     &mut state,
                     database_pool.clone(),
-                    setup_env_config(),
+                    &config,
+                    &transaction_source,
+                    &shutdown,
                 );
             }
-        }
-    }
-}
-
-async fn handle_stream(
-    mut stream: Pin<Box<dyn Stream<Item = Response<RpcLogsResponse>> + Send + 'static>>,
-    sender: tokio::sync::mpsc::UnboundedSender<RpcLogsResponse>,
-) {
-    loop {
-        if let Some(logs) = stream.next().await {
-            if let Err(e) = sender.send(logs.value) {
-                eprintln!("Error sending logs to transaction processing: {:?}", e);
+            Some(address) = rebackfill_rx.recv() => {
+                match state.trees.iter().find(|(tree, _)| *tree == address) {
+                    Some((_, settings)) if settings.backfill => {
+                        state.rebackfills.insert(address);
+
+                        reload_tasks(
+                            &mut state,
+                            database_pool.clone(),
+                            &config,
+                            &transaction_source,
+                            &shutdown,
+                        );
+                    }
+                    Some(_) => {
+                        println!("Tree {:} is not backfilled, leaving it as it is", address);
+                    }
+                    None => {}
+                }
+            }
+            _ = shutdown.cancelled() => {
+                break;
             }
         }
     }
+
+    println!(
+        "Shutting down, waiting up to {:?} for the trees to stop...",
+        shutdown_timeout
+    );
+
+    let tasks = std::mem::take(&mut state.tasks);
+    drop(state);
+
+    futures::future::join_all(
+        tasks
+            .into_iter()
+            .map(|tree_task| stop_tree_task(tree_task, shutdown_timeout)),
+    )
+    .await;
+
+    println!("Shutdown complete");
+
+    Ok(())
 }
 
-async fn get_trees(database_connection: DatabaseConnection) -> Result<Vec<String>> {
-    let res = database_connection
-        .query_all(Statement::from_string(
-            sea_orm::DatabaseBackend::Postgres,
-            String::from("SELECT * FROM ld_merkle_trees WHERE should_index IS TRUE;"),
-        ))
+/// The trees to index and their settings, highest priority first.
+async fn get_trees(database_connection: DatabaseConnection) -> Result<Vec<(String, TreeSettings)>> {
+    let res = ld_merkle_trees::Entity::find()
+        .filter(ld_merkle_trees::Column::ShouldIndex.eq(true))
+        .order_by_desc(ld_merkle_trees::Column::Priority)
+        .order_by_asc(ld_merkle_trees::Column::CreatedAt)
+        .all(&database_connection)
         .await;
 
-    let mut tree_addresses: Vec<String> = Vec::new();
+    let mut trees: Vec<(String, TreeSettings)> = Vec::new();
     match res {
-        Ok(rows) => {
-            if rows.len() == 0 {
-                panic!("Trees to index not found in the database");
-            }
+        Ok(models) => {
+            for tree in models {
+                if Pubkey::from_str(&tree.address).is_err() {
+                    eprintln!("Invalid tree address {:?}", tree.address);
+                    continue;
+                }
 
-            rows.iter().for_each(|row| {
-                let tree_address: Option<String> = row.try_get("", "address").unwrap();
-                match tree_address {
-                    Some(s) => {
-                        if let Ok(_) = Pubkey::from_str(s.as_str()) {
-                            tree_addresses.push(s);
-                        } else {
-                            eprintln!("Invalid tree address {:?}", s)
-                        }
-                    }
-                    None => {}
+                match TreeSettings::try_from(&tree) {
+                    Ok(settings) => trees.push((tree.address, settings)),
+                    Err(e) => eprintln!("Invalid settings for tree {:}: {}", tree.address, e),
                 }
-            });
+            }
         }
         Err(e) => {
             panic!("Error fetching trees to index {:?}", e)
         }
     }
 
-    Ok(tree_addresses)
+    Ok(trees)
 }
 
-fn reload_tasks(state: &mut State, database_pool: Pool<Postgres>, env_config: EnvConfig) {
-    state.tasks.retain(|(s, handle)| {
-        if !state.tree_addresses.contains(s) {
-            handle.abort();
-            false
-        } else {
-            true
-        }
-    });
+async fn set_tree_status(
+    database_pool: &Pool<Postgres>,
+    address: &str,
+    status: LdMerkleTreeStatus,
+) {
+    let database_connection = SqlxPostgresConnector::from_sqlx_postgres_pool(database_pool.clone());
+
+    let res = ld_merkle_trees::Entity::update_many()
+        .set(ld_merkle_trees::ActiveModel {
+            status: Set(status),
+            ..Default::default()
+        })
+        .col_expr(
+            ld_merkle_trees::Column::UpdatedAt,
+            Expr::cust("CURRENT_TIMESTAMP"),
+        )
+        .filter(ld_merkle_trees::Column::Address.eq(address))
+        .exec(&database_connection)
+        .await;
+
+    if let Err(e) = res {
+        eprintln!("Error updating status of tree {:?}: {:?}", address, e);
+    }
+}
+
+/// Cancels `tree_task` and waits for it to wind down, aborting it once `timeout` elapsed.
+async fn stop_tree_task(tree_task: TreeTask, timeout: Duration) {
+    let TreeTask {
+        address,
+        shutdown,
+        settings: _,
+        mut handle,
+    } = tree_task;
+
+    shutdown.cancel();
+
+    if tokio::time::timeout(timeout, &mut handle).await.is_err() {
+        eprintln!(
+            "Tree {:} did not stop within {:?}, aborting it",
+            address, timeout
+        );
+        handle.abort();
+    }
+}
+
+/// Starts a task for each tree of `state` not being indexed, in priority order, and stops the
+/// tasks of the trees removed from it.
+///
+/// Trees whose settings changed are restarted, their new task waits for the previous one to stop.
+/// So are the trees of `state.rebackfills`, whose index is reset before they are backfilled.
+fn reload_tasks(
+    state: &mut State,
+    database_pool: Pool<Postgres>,
+    config: &IndexerArgs,
+    transaction_source: &Arc<dyn TransactionSource>,
+    shutdown: &CancellationToken,
+) {
+    let (tasks, stopped_tasks): (Vec<_>, Vec<_>) = std::mem::take(&mut state.tasks)
+        .into_iter()
+        .partition(|tree_task| {
+            state
+                .trees
+                .iter()
+                .any(|(address, _)| *address == tree_task.address)
+        });
+
+    for tree_task in stopped_tasks {
+        println!("Stopping indexing for tree: {:}", tree_task.address);
+        task::spawn(stop_tree_task(tree_task, config.shutdown_timeout()));
+    }
+
+    // Trees whose live source closed are restarted below
+    let (tasks, mut restarted_tasks): (Vec<_>, Vec<_>) = tasks
+        .into_iter()
+        .filter(|tree_task| !tree_task.handle.is_finished())
+        .partition(|tree_task| {
+            !state.rebackfills.contains(&tree_task.address)
+                && state.trees.iter().any(|(address, settings)| {
+                    *address == tree_task.address && *settings == tree_task.settings
+                })
+        });
+    state.tasks = tasks;
 
     let context = BubblegumBackfillContext::new(
         database_pool.clone(),
         Rpc::from_config(&SolanaRpcArgs {
-            solana_rpc_url: env_config.get_rpc_url().to_string(),
+            solana_rpc_url: config.rpc_url.clone(),
         }),
     );
 
-    let tree_addresses = state.tree_addresses.clone();
-
-    for address in tree_addresses {
+    // Trees already being indexed with the same settings keep their task
+    let trees = state
+        .trees
+        .iter()
+        .filter(|(address, _)| {
+            !state
+                .tasks
+                .iter()
+                .any(|tree_task| tree_task.address == *address)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for (address, settings) in trees {
         let address_clone = address.clone();
 
+        // Audits record the last applied seq live processing resumes from
         let program_transformer = ProgramTransformer::new(
             database_pool.clone(),
             Box::new(|_info| futures::future::ready(Ok(())).boxed()),
-            false,
+            true,
         );
 
         let context = context.clone();
+        let transaction_source = Arc::clone(transaction_source);
+        let database_pool = database_pool.clone();
+        let tree_shutdown = shutdown.child_token();
+        let shutdown = tree_shutdown.clone();
+        let shutdown_timeout = config.shutdown_timeout();
+
+        let previous_task = restarted_tasks
+            .iter()
+            .position(|tree_task| tree_task.address == address)
+            .map(|index| restarted_tasks.swap_remove(index));
+        let rebackfill = state.rebackfills.remove(&address);
+        if rebackfill {
+            println!("Backfilling tree {:} again from scratch", address);
+        } else if previous_task.is_some() {
+            println!("Restarting tree {:} with its new settings", address);
+        }
 
-        let args = BubblegumBackfillArgs {
-            only_trees: Some(vec![address.clone().to_string()]),
-            tree_crawler_count: 4,
-            tree_worker: das_bubblegum_backfill::worker::TreeWorkerArgs {
-                metadata_json_download_worker: MetadataJsonDownloadWorkerArgs {
-                    metadata_json_download_worker_count: 100,
-                    metadata_json_download_worker_request_timeout: 200,
-                },
-                signature_worker: SignatureWorkerArgs {
-                    signature_channel_size: 100,
-                    signature_worker_count: 100,
-                },
-                gap_worker: GapWorkerArgs {
-                    gap_channel_size: 100,
-                    gap_worker_count: 100,
-                },
-                program_transformer_worker: ProgramTransformerWorkerArgs {
-                    program_transformer_channel_size: 100,
-                },
-            },
-        };
+        // Polled once so that the trees queue for a permit in priority order
+        let backfill_permit = settings.backfill.then(|| {
+            state.backfill_permits.as_ref().map(|permits| {
+                let mut permit = Arc::clone(permits).acquire_owned().boxed();
+
+                match (&mut permit).now_or_never() {
+                    Some(acquired) => future::ready(acquired).boxed(),
+                    None => permit,
+                }
+            })
+        });
+
+        let args = settings.backfill_args(&address, &config.backfill);
+        let lower_bound = settings.lower_bound(&config.backfill);
+        let spill_dir = args.tree_worker.gap_worker.gap_spill_dir.clone();
 
         let task_handle = task::spawn(async move {
             let address = address.clone();
-            let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<RpcLogsResponse>();
-
-            let stream: Pin<Box<dyn Stream<Item = Response<RpcLogsResponse>> + Send>> =
-                get_pubsub_client()
-                    .logs_subscribe(
-                        RpcTransactionLogsFilter::Mentions(vec![address.clone().to_string()]),
-                        RpcTransactionLogsConfig {
-                            commitment: Some(CommitmentConfig::processed()),
-                        },
-                    )
-                    .await
-                    .unwrap()
-                    .0;
 
-            task::spawn(async move {
-                handle_stream(stream, tx).await;
-            });
+            if let Some(previous_task) = previous_task {
+                stop_tree_task(previous_task, shutdown_timeout).await;
+            }
 
-            println!("Backfill started for tree: {:}", address);
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<TransactionInfo>();
 
-            if let Err(e) = start_bubblegum_backfill(context.clone(), args).await {
-                eprintln!("Error backfilling tree {:?}: {:?}", address.clone(), e);
+            let tree = match Pubkey::from_str(&address) {
+                Ok(tree) => tree,
+                Err(e) => {
+                    eprintln!("Invalid tree address {:?}: {:?}", address, e);
+                    set_tree_status(&database_pool, &address, LdMerkleTreeStatus::Failed).await;
+                    return;
+                }
+            };
+
+            let subscription_source = Arc::clone(&transaction_source);
+            task::spawn(async move { subscription_source.subscribe(tree, tx).await });
+
+            if let Some(backfill_permit) = backfill_permit {
+                // Held until the backfill finished
+                let _backfill_permit = match backfill_permit {
+                    Some(permit) => {
+                        set_tree_status(&database_pool, &address, LdMerkleTreeStatus::Pending)
+                            .await;
+
+                        tokio::select! {
+                            permit = permit => permit.ok(),
+                            _ = shutdown.cancelled() => {
+                                println!("Backfill interrupted for tree: {:}", address);
+                                return;
+                            }
+                        }
+                    }
+                    None => None,
+                };
+
+                if rebackfill {
+                    let conn =
+                        SqlxPostgresConnector::from_sqlx_postgres_pool(database_pool.clone());
+                    if let Err(e) = reset_tree_index(&conn, tree, &spill_dir).await {
+                        eprintln!("Error resetting the index of tree {:}: {:?}", address, e);
+                    }
+                }
+
+                println!("Backfill started for tree: {:}", address);
+                set_tree_status(&database_pool, &address, LdMerkleTreeStatus::Backfilling).await;
+
+                if let Err(e) =
+                    start_bubblegum_backfill(context.clone(), args, shutdown.clone()).await
+                {
+                    eprintln!("Error backfilling tree {:?}: {:?}", address.clone(), e);
+                }
+
+                // The backfill resumes from its checkpoints on the next start
+                if shutdown.is_cancelled() {
+                    println!("Backfill interrupted for tree: {:}", address);
+                    set_tree_status(&database_pool, &address, LdMerkleTreeStatus::Pending).await;
+                    return;
+                }
+
+                println!("Backfill finished and for tree: {:}", address);
             }
 
-            println!("Backfill finished and for tree: {:}", address);
             println!("Starting live indexing for tree: {:}", address);
-
-            process_transactions_channel(rx, &program_transformer).await;
+            set_tree_status(&database_pool, &address, LdMerkleTreeStatus::Indexing).await;
+
+            process_transactions_channel(
+                tree,
+                rx,
+                transaction_source.as_ref(),
+                &settings,
+                lower_bound,
+                &program_transformer,
+                &context,
+                &shutdown,
+            )
+            .await;
+
+            if shutdown.is_cancelled() {
+                println!("Live indexing stopped for tree: {:}", address);
+                set_tree_status(&database_pool, &address, LdMerkleTreeStatus::Pending).await;
+            } else {
+                // The live source closed, the tree is not indexed anymore
+                set_tree_status(&database_pool, &address, LdMerkleTreeStatus::Failed).await;
+            }
         });
 
-        state.tasks.push((address_clone, task_handle));
+        state.tasks.push(TreeTask {
+            address: address_clone,
+            settings,
+            shutdown: tree_shutdown,
+            handle: task_handle,
+        });
     }
+
+    // Trees removed from the registry meanwhile
+    state.rebackfills.clear();
 }
 
-async fn configure_database(
-    database_connection: DatabaseConnection,
-) -> Result<sea_orm::ExecResult, sea_orm::DbErr> {
-    // Refer https://github.com/WilfredAlmeida/LightDAS/issues/4 to understand why this is needed
-    database_connection
-        .execute(Statement::from_string(
-            sea_orm::DatabaseBackend::Postgres,
-            String::from(
-                "CREATE TABLE IF NOT EXISTS LD_MERKLE_TREES (
-                    ADDRESS VARCHAR(255),
-                    TAG VARCHAR(255) NULL,
-                    CAPACITY INT NULL,
-                    MAX_DEPTH INT NULL,
-                    CANOPY_DEPTH INT NULL,
-                    MAX_BUFFER_SIZE INT NULL,
-                    SHOULD_INDEX BOOLEAN DEFAULT TRUE,
-                    CREATED_AT TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                    UPDATED_AT TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-                );",
-            ),
-        ))
-        .await
+async fn configure_database(database_url: &str) -> Result<(), migration::DbErr> {
+    // The migrations use their own sea-orm version, so they run on a dedicated connection
+    let database_connection = migration::sea_orm::Database::connect(database_url).await?;
+
+    Migrator::up(&database_connection, None).await?;
+
+    database_connection.close().await
 }
@@ -1,2 +1,3 @@
+pub mod reorder_buffer;
 pub mod transaction;
-pub mod transactions_channel_processor;
\ No newline at end of file
+pub mod transactions_channel_processor;
@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use program_transformers::TransactionInfo;
+use solana_sdk::signature::Signature;
+
+struct PendingTransaction {
+    transaction: TransactionInfo,
+    last_seq: u64,
+    confirmed: bool,
+}
+
+/// Change log seqs missing between the last applied transaction and the first buffered one.
+pub struct Gap {
+    /// Seq the next applied transaction has to start at.
+    pub expected_seq: u64,
+    /// Seq the first buffered transaction starts at.
+    pub buffered_seq: u64,
+    /// Newest signature known to be applied, the gap lies between it and `before`.
+    pub until: Option<Signature>,
+    /// First buffered transaction past the gap.
+    pub before: Signature,
+    pub elapsed: Duration,
+}
+
+/// Holds the live transactions of a tree until they are confirmed and their change log sequence
+/// numbers line up with what was already applied.
+///
+/// Transactions are keyed on the first seq they emitted for the tree. Several transactions can
+/// claim the same seq when they landed on competing forks, only the confirmed one is released and
+/// the others are dropped. Transactions that emitted no change log for the tree don't affect its
+/// order and are released as soon as they are confirmed.
+pub struct ReorderBuffer {
+    next_seq: Option<u64>,
+    last_signature: Option<Signature>,
+    sequenced: BTreeMap<u64, Vec<PendingTransaction>>,
+    unsequenced: VecDeque<PendingTransaction>,
+    gap_detected_at: Option<Instant>,
+}
+
+impl ReorderBuffer {
+    /// `last_applied` is the seq and signature of the newest transaction already in the database,
+    /// if any. Without it the first confirmed transaction sets the starting seq.
+    pub fn new(last_applied: Option<(u64, Signature)>) -> Self {
+        Self {
+            next_seq: last_applied.map(|(seq, _)| seq + 1),
+            last_signature: last_applied.map(|(_, signature)| signature),
+            sequenced: BTreeMap::new(),
+            unsequenced: VecDeque::new(),
+            gap_detected_at: None,
+        }
+    }
+
+    /// Buffers `transaction` which emitted `seqs` for the tree.
+    ///
+    /// Returns `false` if it was dropped because it was already buffered or applied.
+    pub fn push(&mut self, transaction: TransactionInfo, seqs: &[u64], confirmed: bool) -> bool {
+        if self.contains(&transaction.signature) {
+            return false;
+        }
+
+        let (Some(first_seq), Some(last_seq)) =
+            (seqs.iter().min().copied(), seqs.iter().max().copied())
+        else {
+            self.unsequenced.push_back(PendingTransaction {
+                transaction,
+                last_seq: 0,
+                confirmed,
+            });
+            return true;
+        };
+
+        if self.next_seq.is_some_and(|next_seq| last_seq < next_seq) {
+            return false;
+        }
+
+        self.sequenced
+            .entry(first_seq)
+            .or_default()
+            .push(PendingTransaction {
+                transaction,
+                last_seq,
+                confirmed,
+            });
+
+        true
+    }
+
+    pub fn contains(&self, signature: &Signature) -> bool {
+        self.pending()
+            .any(|pending| pending.transaction.signature == *signature)
+    }
+
+    /// Signatures of the buffered transactions.
+    pub fn signatures(&self) -> HashSet<Signature> {
+        self.pending()
+            .map(|pending| pending.transaction.signature)
+            .collect()
+    }
+
+    fn pending(&self) -> impl Iterator<Item = &PendingTransaction> {
+        self.sequenced
+            .values()
+            .flatten()
+            .chain(self.unsequenced.iter())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequenced.is_empty() && self.unsequenced.is_empty()
+    }
+
+    /// Buffered transactions still waiting for confirmation, with the slot they were seen at.
+    pub fn unconfirmed(&self) -> Vec<(Signature, u64)> {
+        self.pending()
+            .filter(|pending| !pending.confirmed)
+            .map(|pending| (pending.transaction.signature, pending.transaction.slot))
+            .collect()
+    }
+
+    pub fn confirm(&mut self, signature: &Signature) {
+        self.sequenced
+            .values_mut()
+            .flatten()
+            .chain(self.unsequenced.iter_mut())
+            .filter(|pending| pending.transaction.signature == *signature)
+            .for_each(|pending| pending.confirmed = true);
+    }
+
+    /// Drops a transaction that will never be confirmed, e.g. because its fork was abandoned.
+    pub fn discard(&mut self, signature: &Signature) {
+        self.unsequenced
+            .retain(|pending| pending.transaction.signature != *signature);
+
+        self.sequenced.retain(|_, candidates| {
+            candidates.retain(|pending| pending.transaction.signature != *signature);
+            !candidates.is_empty()
+        });
+    }
+
+    /// Pops the next transaction to apply, if it is confirmed and in sequence.
+    pub fn pop(&mut self) -> Option<TransactionInfo> {
+        if let Some(index) = self
+            .unsequenced
+            .iter()
+            .position(|pending| pending.confirmed)
+        {
+            return self
+                .unsequenced
+                .remove(index)
+                .map(|pending| pending.transaction);
+        }
+
+        // Competing transactions for seqs already applied came from dead forks
+        if let Some(next_seq) = self.next_seq {
+            self.sequenced = self.sequenced.split_off(&next_seq);
+        }
+
+        let entry = self.sequenced.first_entry()?;
+
+        if self
+            .next_seq
+            .is_some_and(|next_seq| *entry.key() > next_seq)
+        {
+            self.gap_detected_at.get_or_insert_with(Instant::now);
+            return None;
+        }
+
+        let index = entry.get().iter().position(|pending| pending.confirmed)?;
+        let pending = entry.remove().swap_remove(index);
+
+        self.next_seq = Some(pending.last_seq + 1);
+        self.last_signature = Some(pending.transaction.signature);
+        self.gap_detected_at = None;
+
+        Some(pending.transaction)
+    }
+
+    /// The missing seqs blocking the buffer, found by the last call to `pop`.
+    pub fn gap(&self) -> Option<Gap> {
+        let detected_at = self.gap_detected_at?;
+        let (buffered_seq, candidates) = self.sequenced.first_key_value()?;
+
+        Some(Gap {
+            expected_seq: self.next_seq?,
+            buffered_seq: *buffered_seq,
+            until: self.last_signature,
+            before: candidates.first()?.transaction.signature,
+            elapsed: detected_at.elapsed(),
+        })
+    }
+
+    /// Gives up on the missing seqs and resumes from the first buffered transaction past them.
+    pub fn skip_gap(&mut self) {
+        self.next_seq = self.sequenced.first_key_value().map(|(seq, _)| *seq);
+        self.gap_detected_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(slot: u64) -> TransactionInfo {
+        TransactionInfo {
+            slot,
+            signature: Signature::new_unique(),
+            account_keys: vec![],
+            message_instructions: vec![],
+            meta_inner_instructions: vec![],
+        }
+    }
+
+    fn drain(buffer: &mut ReorderBuffer) -> Vec<Signature> {
+        std::iter::from_fn(|| buffer.pop())
+            .map(|transaction| transaction.signature)
+            .collect()
+    }
+
+    #[test]
+    fn applies_in_sequence_order() {
+        let mut buffer = ReorderBuffer::new(Some((4, Signature::new_unique())));
+
+        let third = transaction(12);
+        let first = transaction(10);
+        let second = transaction(10);
+        let expected = vec![first.signature, second.signature, third.signature];
+
+        assert!(buffer.push(third, &[7], true));
+        assert!(buffer.push(first, &[5], true));
+        assert!(buffer.push(second, &[6], true));
+
+        assert_eq!(drain(&mut buffer), expected);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn waits_for_confirmation() {
+        let mut buffer = ReorderBuffer::new(Some((4, Signature::new_unique())));
+
+        let first = transaction(10);
+        let second = transaction(11);
+        let (first_signature, second_signature) = (first.signature, second.signature);
+
+        buffer.push(first, &[5], false);
+        buffer.push(second, &[6], true);
+
+        assert!(buffer.pop().is_none());
+        assert_eq!(buffer.unconfirmed(), vec![(first_signature, 10)]);
+
+        buffer.confirm(&first_signature);
+
+        assert_eq!(drain(&mut buffer), vec![first_signature, second_signature]);
+    }
+
+    #[test]
+    fn drops_dead_fork_transactions() {
+        let mut buffer = ReorderBuffer::new(Some((4, Signature::new_unique())));
+
+        let forked = transaction(10);
+        let confirmed = transaction(11);
+        let abandoned = transaction(12);
+        let (forked_signature, confirmed_signature) = (forked.signature, confirmed.signature);
+        let abandoned_signature = abandoned.signature;
+
+        buffer.push(forked, &[5], false);
+        buffer.push(confirmed, &[5], true);
+        buffer.push(abandoned, &[9], false);
+
+        assert_eq!(drain(&mut buffer), vec![confirmed_signature]);
+        assert!(!buffer.unconfirmed().contains(&(forked_signature, 10)));
+
+        buffer.discard(&abandoned_signature);
+
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn drops_already_applied_transactions() {
+        let mut buffer = ReorderBuffer::new(Some((4, Signature::new_unique())));
+        let transaction = transaction(10);
+
+        assert!(!buffer.push(transaction.clone(), &[3, 4], true));
+        assert!(buffer.push(transaction.clone(), &[5], true));
+        assert!(!buffer.push(transaction, &[5], true));
+    }
+
+    #[test]
+    fn releases_unsequenced_transactions_once_confirmed() {
+        let mut buffer = ReorderBuffer::new(Some((4, Signature::new_unique())));
+
+        let blocked = transaction(10);
+        let unsequenced = transaction(11);
+        let unsequenced_signature = unsequenced.signature;
+
+        buffer.push(blocked, &[5], false);
+        buffer.push(unsequenced, &[], false);
+
+        assert!(buffer.pop().is_none());
+
+        buffer.confirm(&unsequenced_signature);
+
+        assert_eq!(drain(&mut buffer), vec![unsequenced_signature]);
+    }
+
+    #[test]
+    fn detects_and_skips_gaps() {
+        let applied = Signature::new_unique();
+        let mut buffer = ReorderBuffer::new(Some((4, applied)));
+
+        let after_gap = transaction(12);
+        let after_gap_signature = after_gap.signature;
+
+        buffer.push(after_gap, &[7, 8], true);
+
+        assert!(buffer.gap().is_none());
+        assert!(buffer.pop().is_none());
+
+        let gap = buffer.gap().unwrap();
+        assert_eq!(gap.expected_seq, 5);
+        assert_eq!(gap.buffered_seq, 7);
+        assert_eq!(gap.until, Some(applied));
+        assert_eq!(gap.before, after_gap_signature);
+
+        let filled = transaction(11);
+        let filled_signature = filled.signature;
+        buffer.push(filled, &[5, 6], true);
+
+        assert_eq!(
+            drain(&mut buffer),
+            vec![filled_signature, after_gap_signature]
+        );
+        assert!(buffer.gap().is_none());
+
+        let after_unfillable_gap = transaction(14);
+        let after_unfillable_gap_signature = after_unfillable_gap.signature;
+        buffer.push(after_unfillable_gap, &[11], true);
+
+        assert!(buffer.pop().is_none());
+        buffer.skip_gap();
+
+        assert_eq!(drain(&mut buffer), vec![after_unfillable_gap_signature]);
+    }
+
+    #[test]
+    fn starts_from_first_transaction_without_history() {
+        let mut buffer = ReorderBuffer::new(None);
+
+        let first = transaction(10);
+        let second = transaction(11);
+        let expected = vec![first.signature, second.signature];
+
+        buffer.push(second, &[43], true);
+        buffer.push(first, &[42], true);
+
+        assert_eq!(drain(&mut buffer), expected);
+    }
+}
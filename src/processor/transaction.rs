@@ -1,79 +1,290 @@
-use std::panic;
+use std::collections::HashMap;
 use std::str::FromStr;
 
-use anyhow::Error;
 use solana_sdk::{instruction::CompiledInstruction, pubkey::Pubkey};
 use solana_transaction_status::{
     EncodedConfirmedTransactionWithStatusMeta, InnerInstruction, InnerInstructions,
-    UiInnerInstructions, UiInstruction, UiLoadedAddresses,
+    UiInnerInstructions, UiInstruction, UiLoadedAddresses, UiParsedInstruction,
+    UiPartiallyDecodedInstruction,
 };
+use thiserror::Error;
 
-use program_transformers::{ProgramTransformer, TransactionInfo};
+use program_transformers::TransactionInfo;
 
-pub async fn process_transaction(
-    program_transformer: &ProgramTransformer,
+#[derive(Error, Debug)]
+pub enum TransactionParseError {
+    #[error("transaction does not have meta")]
+    MissingMeta,
+    #[error("transaction could not be decoded")]
+    UndecodableTransaction,
+    #[error("transaction does not have a signature")]
+    MissingSignature,
+    #[error("invalid address {0} in the address lookup tables")]
+    InvalidLoadedAddress(String),
+    #[error("invalid instruction data: {0}")]
+    InvalidInstructionData(#[from] bs58::decode::Error),
+    #[error("invalid account {0} in a parsed inner instruction")]
+    InvalidInstructionAccount(String),
+}
+
+pub fn parse_transaction(
     transaction: EncodedConfirmedTransactionWithStatusMeta,
-) -> Result<(), Error> {
+) -> Result<TransactionInfo, TransactionParseError> {
     let meta = transaction
         .transaction
         .meta
-        .to_owned()
-        .expect("transaction does not have meta");
+        .ok_or(TransactionParseError::MissingMeta)?;
     let inner_instructions: Option<Vec<UiInnerInstructions>> = meta.inner_instructions.into();
-    let tsx = transaction.transaction.transaction;
-    let unwrapped_transaction = tsx.decode().unwrap();
+    let unwrapped_transaction = transaction
+        .transaction
+        .transaction
+        .decode()
+        .ok_or(TransactionParseError::UndecodableTransaction)?;
+    let signature = *unwrapped_transaction
+        .signatures
+        .first()
+        .ok_or(TransactionParseError::MissingSignature)?;
     let message = unwrapped_transaction.message;
 
     let loaded_addresses =
-        Into::<Option<UiLoadedAddresses>>::into(meta.loaded_addresses).unwrap_or_default();
+        Option::<UiLoadedAddresses>::from(meta.loaded_addresses).unwrap_or_default();
     let mut account_keys = Vec::from(message.static_account_keys());
-    account_keys.extend(
-        loaded_addresses
-            .writable
-            .into_iter()
-            .chain(loaded_addresses.readonly.into_iter())
-            .map(|key| {
-                Pubkey::from_str(&key).map_err(|e| format!("could not parse pubkey: {key}: {e}"))
-            })
-            .collect::<Result<Vec<_>, _>>()
-            .expect("could not read additional addresses"),
-    );
-
-    let res = program_transformer
-        .handle_transaction(&TransactionInfo {
-            slot: transaction.slot,
-            signature: unwrapped_transaction.signatures[0],
-            account_keys: account_keys,
-            message_instructions: message.instructions().into(),
-            meta_inner_instructions: inner_instructions
-                .unwrap_or_default()
+    for address in loaded_addresses
+        .writable
+        .into_iter()
+        .chain(loaded_addresses.readonly)
+    {
+        let key = Pubkey::from_str(&address)
+            .map_err(|_| TransactionParseError::InvalidLoadedAddress(address))?;
+        account_keys.push(key);
+    }
+
+    let account_indexes = account_keys
+        .iter()
+        .enumerate()
+        .map(|(index, key)| (*key, index as u8))
+        .collect::<HashMap<_, _>>();
+
+    let meta_inner_instructions = inner_instructions
+        .unwrap_or_default()
+        .into_iter()
+        .map(|inner| {
+            let instructions = inner
+                .instructions
                 .into_iter()
-                .map(|i| InnerInstructions {
-                    index: i.index,
-                    instructions: i
-                        .instructions
-                        .into_iter()
-                        .map(|ix| match ix {
-                            UiInstruction::Compiled(instruction) => InnerInstruction {
-                                instruction: CompiledInstruction {
-                                    program_id_index: instruction.program_id_index,
-                                    accounts: instruction.accounts,
-                                    data: bs58::decode(&instruction.data).into_vec().unwrap(),
-                                },
-                                stack_height: instruction.stack_height,
-                            },
-                            _ => panic!("Not compiled"),
-                        })
-                        .collect(),
+                .filter_map(|instruction| {
+                    parse_inner_instruction(instruction, &account_indexes).transpose()
                 })
-                .collect::<Vec<_>>(),
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(InnerInstructions {
+                index: inner.index,
+                instructions,
+            })
         })
-        .await;
+        .collect::<Result<Vec<_>, TransactionParseError>>()?;
+
+    Ok(TransactionInfo {
+        slot: transaction.slot,
+        signature,
+        account_keys,
+        message_instructions: message.instructions().into(),
+        meta_inner_instructions,
+    })
+}
+
+/// Converts an inner instruction back to its compiled form, `account_indexes` maps the accounts of
+/// the transaction to their index.
+///
+/// Instructions the RPC fully parsed to JSON no longer carry their data, they are skipped. The RPC
+/// only parses the instructions of native and SPL programs, which do not emit the change logs
+/// LightDAS indexes.
+fn parse_inner_instruction(
+    instruction: UiInstruction,
+    account_indexes: &HashMap<Pubkey, u8>,
+) -> Result<Option<InnerInstruction>, TransactionParseError> {
+    match instruction {
+        UiInstruction::Compiled(instruction) => Ok(Some(InnerInstruction {
+            instruction: CompiledInstruction {
+                program_id_index: instruction.program_id_index,
+                accounts: instruction.accounts,
+                data: bs58::decode(&instruction.data).into_vec()?,
+            },
+            stack_height: instruction.stack_height,
+        })),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(
+            UiPartiallyDecodedInstruction {
+                program_id,
+                accounts,
+                data,
+                stack_height,
+            },
+        )) => {
+            let index = |account: String| {
+                Pubkey::from_str(&account)
+                    .ok()
+                    .and_then(|key| account_indexes.get(&key).copied())
+                    .ok_or(TransactionParseError::InvalidInstructionAccount(account))
+            };
+
+            Ok(Some(InnerInstruction {
+                instruction: CompiledInstruction {
+                    program_id_index: index(program_id)?,
+                    accounts: accounts.into_iter().map(index).collect::<Result<_, _>>()?,
+                    data: bs58::decode(&data).into_vec()?,
+                },
+                stack_height,
+            }))
+        }
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::Transaction;
+    use solana_transaction_status::option_serializer::OptionSerializer;
+    use solana_transaction_status::{
+        Encodable, EncodedTransaction, EncodedTransactionWithStatusMeta, TransactionStatusMeta,
+        UiCompiledInstruction, UiTransactionEncoding, UiTransactionStatusMeta,
+    };
+
+    use super::*;
+
+    fn transaction(
+        inner_instructions: Vec<UiInstruction>,
+    ) -> (Message, EncodedConfirmedTransactionWithStatusMeta) {
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[1, 2, 3],
+            vec![AccountMeta::new(Pubkey::new_unique(), false)],
+        );
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::new_unique(),
+        );
+        let mut meta = UiTransactionStatusMeta::from(TransactionStatusMeta::default());
+        meta.inner_instructions = OptionSerializer::Some(vec![UiInnerInstructions {
+            index: 0,
+            instructions: inner_instructions,
+        }]);
+
+        (
+            transaction.message.clone(),
+            EncodedConfirmedTransactionWithStatusMeta {
+                slot: 1,
+                transaction: EncodedTransactionWithStatusMeta {
+                    transaction: transaction.encode(UiTransactionEncoding::Base58),
+                    meta: Some(meta),
+                    version: None,
+                },
+                block_time: None,
+            },
+        )
+    }
 
-    if let Err(e) = res {
-        eprintln!("tx handling error: {:?}", e);
-        return Ok(());
+    #[test]
+    fn parses_partially_decoded_inner_instructions_like_compiled_ones() {
+        let (message, compiled) =
+            transaction(vec![UiInstruction::Compiled(UiCompiledInstruction {
+                program_id_index: 2,
+                accounts: vec![1, 0],
+                data: bs58::encode([4, 5]).into_string(),
+                stack_height: Some(2),
+            })]);
+        let (_, mut partially_decoded) = transaction(vec![
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(
+                UiPartiallyDecodedInstruction {
+                    program_id: message.account_keys[2].to_string(),
+                    accounts: vec![
+                        message.account_keys[1].to_string(),
+                        message.account_keys[0].to_string(),
+                    ],
+                    data: bs58::encode([4, 5]).into_string(),
+                    stack_height: Some(2),
+                },
+            )),
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(
+                solana_transaction_status::parse_instruction::ParsedInstruction {
+                    program: "spl-memo".to_string(),
+                    program_id: Pubkey::new_unique().to_string(),
+                    parsed: serde_json::Value::Null,
+                    stack_height: Some(2),
+                },
+            )),
+        ]);
+        partially_decoded.transaction.transaction = compiled.transaction.transaction.clone();
+
+        let compiled = parse_transaction(compiled).unwrap();
+        let partially_decoded = parse_transaction(partially_decoded).unwrap();
+
+        assert_eq!(compiled, partially_decoded);
+        assert_eq!(
+            compiled.meta_inner_instructions[0].instructions[0]
+                .instruction
+                .data,
+            vec![4, 5]
+        );
     }
 
-    Ok(())
+    #[test]
+    fn fails_on_odd_transactions_instead_of_panicking() {
+        let (_, mut missing_meta) = transaction(vec![]);
+        missing_meta.transaction.meta = None;
+        assert!(matches!(
+            parse_transaction(missing_meta),
+            Err(TransactionParseError::MissingMeta)
+        ));
+
+        let (_, mut undecodable) = transaction(vec![]);
+        undecodable.transaction.transaction =
+            EncodedTransaction::LegacyBinary("not base58".to_string());
+        assert!(matches!(
+            parse_transaction(undecodable),
+            Err(TransactionParseError::UndecodableTransaction)
+        ));
+
+        let (_, invalid_data) = transaction(vec![UiInstruction::Compiled(UiCompiledInstruction {
+            program_id_index: 2,
+            accounts: vec![],
+            data: "0OIl".to_string(),
+            stack_height: None,
+        })]);
+        assert!(matches!(
+            parse_transaction(invalid_data),
+            Err(TransactionParseError::InvalidInstructionData(_))
+        ));
+
+        let (_, unknown_account) = transaction(vec![UiInstruction::Parsed(
+            UiParsedInstruction::PartiallyDecoded(UiPartiallyDecodedInstruction {
+                program_id: Pubkey::new_unique().to_string(),
+                accounts: vec![],
+                data: String::new(),
+                stack_height: None,
+            }),
+        )]);
+        assert!(matches!(
+            parse_transaction(unknown_account),
+            Err(TransactionParseError::InvalidInstructionAccount(_))
+        ));
+
+        let (_, mut invalid_loaded_address) = transaction(vec![]);
+        if let Some(meta) = invalid_loaded_address.transaction.meta.as_mut() {
+            meta.loaded_addresses = OptionSerializer::Some(UiLoadedAddresses {
+                writable: vec!["not a pubkey".to_string()],
+                readonly: vec![],
+            });
+        }
+        assert!(matches!(
+            parse_transaction(invalid_loaded_address),
+            Err(TransactionParseError::InvalidLoadedAddress(_))
+        ));
+    }
 }
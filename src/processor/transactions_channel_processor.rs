@@ -1,23 +1,862 @@
-use program_transformers::ProgramTransformer;
-use solana_client::rpc_response::RpcLogsResponse;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
 
-use crate::rpc::rpc::get_transaction_with_retries;
+use das_bubblegum_backfill::{
+    BubblegumBackfillContext, CrawlLowerBound, DeadLetterErrorKind, TreeGapFill,
+};
+use digital_asset_types::dao::cl_audits_v2;
+use program_transformers::error::ProgramTransformerError;
+use program_transformers::{ProgramTransformer, TransactionInfo};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, SqlxPostgresConnector};
+use solana_client::client_error::ClientError;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc::{channel, UnboundedReceiver};
+use tokio_util::sync::CancellationToken;
 
-use super::transaction::process_transaction;
+use crate::config::rpc_config::get_rpc_client;
+use crate::dead_letter::record_dead_letter;
+use crate::processor::reorder_buffer::{Gap, ReorderBuffer};
+use crate::processor::transaction::parse_transaction;
+use crate::source::TransactionSource;
+use crate::tree_settings::TreeSettings;
 
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(400);
+/// How long a skipped seq may stay missing before it is crawled, transactions at `processed`
+/// commitment routinely arrive a few slots out of order.
+const GAP_FILL_DELAY: Duration = Duration::from_secs(10);
+const GAP_SIGNATURES_CHANNEL_SIZE: usize = 1000;
+const MAX_SIGNATURE_STATUSES: usize = 256;
+const GET_SIGNATURES_FOR_ADDRESS_LIMIT: usize = 1000;
+
+/// Applies the live transactions of `tree` in change log order.
+///
+/// Received transactions are buffered until they reach the commitment of `settings`, transactions
+/// from abandoned forks are dropped and seqs that never arrive are crawled from the RPC.
+/// Processing starts with a handoff from the backfill, see [`handoff`].
+///
+/// Once `shutdown` is cancelled, `receiver` is closed so the subscription stops, and the
+/// transactions already received are applied as far as they are confirmed and in order. The rest
+/// is crawled by the backfill on the next start.
+///
+/// `transaction_source` is told about every received transaction once it left the buffer, applied,
+/// recorded as a dead letter or dropped.
+///
+/// Without an applied transaction, the handoff crawls back to `lower_bound`, where the tree is
+/// backfilled from.
+#[allow(clippy::too_many_arguments)]
 pub async fn process_transactions_channel(
-    mut receiver: tokio::sync::mpsc::UnboundedReceiver<RpcLogsResponse>,
+    tree: Pubkey,
+    mut receiver: UnboundedReceiver<TransactionInfo>,
+    transaction_source: &dyn TransactionSource,
+    settings: &TreeSettings,
+    lower_bound: CrawlLowerBound,
     program_transformer: &ProgramTransformer,
+    context: &BubblegumBackfillContext,
+    shutdown: &CancellationToken,
 ) {
+    let commitment = settings.commitment;
+    let last_applied = get_last_audit(tree, context).await.and_then(|audit| {
+        Some((
+            audit.seq as u64,
+            Signature::try_from(audit.tx.as_slice()).ok()?,
+        ))
+    });
+    let mut buffer = ReorderBuffer::new(last_applied);
+    let mut interval = tokio::time::interval(CONFIRMATION_POLL_INTERVAL);
+
+    let first_live = tokio::select! {
+        first_live = receiver.recv() => first_live,
+        _ = shutdown.cancelled() => None,
+    };
+    let Some(first_live) = first_live else {
+        return;
+    };
+    // Received transactions still buffered
+    let mut received = HashSet::from([first_live.signature]);
+    println!(
+        "First live transaction for tree {:} at slot {}",
+        tree, first_live.slot
+    );
+
+    // Without a backfill nor an applied transaction, indexing starts with the first live one
+    let handed_off = if last_applied.is_none() && !settings.backfill {
+        let seqs = program_transformer.change_log_seqs(&first_live, &tree);
+        buffer.push(first_live, &seqs, false);
+
+        Ok(HashSet::new())
+    } else {
+        let lower_bound = CrawlLowerBound {
+            signature: last_applied
+                .map(|(_, signature)| signature)
+                .or(lower_bound.signature),
+            ..lower_bound
+        };
+
+        handoff(
+            tree,
+            first_live,
+            lower_bound,
+            commitment,
+            &mut buffer,
+            program_transformer,
+            context,
+        )
+        .await
+    };
+    let handed_off = match handed_off {
+        Ok(handed_off) => handed_off,
+        Err(e) => {
+            // The seqs left missing are crawled as a gap
+            eprintln!(
+                "Error handing off tree {:} to live processing: {:?}",
+                tree, e
+            );
+            HashSet::new()
+        }
+    };
+
+    let mut closing = false;
+
     loop {
-        if let Some(logs) = receiver.recv().await {
-            let transaction_signature = logs.signature;
+        tokio::select! {
+            _ = shutdown.cancelled(), if !closing => {
+                // Drains what was already received, then `recv` returns `None`
+                receiver.close();
+                closing = true;
+            }
+            transaction = receiver.recv() => {
+                let Some(transaction) = transaction else {
+                    break;
+                };
+                let signature = transaction.signature;
+
+                // Handed off transactions were buffered by the crawl already
+                let buffered = if handed_off.contains(&signature) {
+                    buffer.contains(&signature)
+                } else {
+                    let seqs = program_transformer.change_log_seqs(&transaction, &tree);
 
-            if let Ok(transaction) = get_transaction_with_retries(&transaction_signature).await {
-                if let Err(e) = process_transaction(&program_transformer, transaction).await {
-                    eprintln!("Transaction processing error: {:?}", e);
+                    buffer.push(transaction, &seqs, false) || buffer.contains(&signature)
+                };
+
+                if buffered {
+                    received.insert(signature);
+                } else {
+                    transaction_source.handled(&signature);
+                }
+            }
+            _ = interval.tick(), if !buffer.is_empty() => {
+                if let Err(e) =
+                    confirm_transactions(tree, commitment, &mut buffer, program_transformer, context)
+                        .await
+                {
+                    eprintln!("Error confirming transactions for tree {:}: {:?}", tree, e);
+                }
+
+                apply_transactions(tree, &mut buffer, program_transformer, context).await;
+
+                if let Some(gap) = buffer.gap().filter(|gap| gap.elapsed >= GAP_FILL_DELAY) {
+                    fill_gap(tree, &gap, commitment, &mut buffer, program_transformer, context).await;
+                    apply_transactions(tree, &mut buffer, program_transformer, context).await;
+
+                    if buffer
+                        .gap()
+                        .is_some_and(|unfilled| unfilled.expected_seq == gap.expected_seq)
+                    {
+                        eprintln!(
+                            "Could not fill seqs {}..{} for tree {:}, skipping them",
+                            gap.expected_seq, gap.buffered_seq, tree
+                        );
+
+                        buffer.skip_gap();
+                        apply_transactions(tree, &mut buffer, program_transformer, context).await;
+                    }
                 }
+
+                release_handled(&mut received, &buffer, transaction_source);
+            }
+        }
+    }
+
+    if !buffer.is_empty() {
+        if let Err(e) =
+            confirm_transactions(tree, commitment, &mut buffer, program_transformer, context).await
+        {
+            eprintln!("Error confirming transactions for tree {:}: {:?}", tree, e);
+        }
+
+        apply_transactions(tree, &mut buffer, program_transformer, context).await;
+        release_handled(&mut received, &buffer, transaction_source);
+    }
+}
+
+/// Tells `transaction_source` about the `received` transactions that left `buffer`.
+fn release_handled(
+    received: &mut HashSet<Signature>,
+    buffer: &ReorderBuffer,
+    transaction_source: &dyn TransactionSource,
+) {
+    let buffered = buffer.signatures();
+
+    received.retain(|signature| {
+        if buffered.contains(signature) {
+            return true;
+        }
+
+        transaction_source.handled(signature);
+        false
+    });
+}
+
+/// Hands `tree` over from the backfill to live processing.
+///
+/// The backfill stops at the finalized tip while live transactions are received from the moment
+/// the subscription was opened, at `processed` commitment. The transactions landed in between are
+/// crawled at `confirmed` commitment, from the newest one down to `lower_bound`, the last applied
+/// one or where the tree is indexed from, and buffered oldest first along with `first_live`.
+/// Transactions landed after the slot of `first_live` are left to the live subscription.
+///
+/// Returns the signatures of the crawled transactions, the live subscription may deliver them
+/// again.
+async fn handoff(
+    tree: Pubkey,
+    first_live: TransactionInfo,
+    lower_bound: CrawlLowerBound,
+    commitment: CommitmentConfig,
+    buffer: &mut ReorderBuffer,
+    program_transformer: &ProgramTransformer,
+    context: &BubblegumBackfillContext,
+) -> Result<HashSet<Signature>, anyhow::Error> {
+    let client = context
+        .solana_rpc
+        .with_commitment(CommitmentConfig::confirmed());
+    let first_live_slot = first_live.slot;
+    let mut signatures = Vec::new();
+    let mut before = None;
+
+    'crawl: loop {
+        let page = client
+            .get_signatures_for_address(&tree, before, lower_bound.signature)
+            .await?;
+        let page_len = page.len();
+
+        for status in page {
+            let signature = Signature::from_str(&status.signature)?;
+
+            if lower_bound.reached(&signature, status.slot) {
+                break 'crawl;
+            }
+            before = Some(signature);
+
+            if status.err.is_none() && status.slot <= first_live_slot {
+                signatures.push(signature);
             }
         }
+
+        if page_len < GET_SIGNATURES_FOR_ADDRESS_LIMIT {
+            break;
+        }
+    }
+
+    println!(
+        "Handing off tree {:} to live processing with {} transactions up to slot {}",
+        tree,
+        signatures.len(),
+        first_live_slot
+    );
+
+    for signature in signatures.iter().rev() {
+        fetch_transaction(
+            tree,
+            *signature,
+            commitment,
+            buffer,
+            program_transformer,
+            context,
+        )
+        .await;
+    }
+
+    let seqs = program_transformer.change_log_seqs(&first_live, &tree);
+    buffer.push(first_live, &seqs, false);
+
+    Ok(signatures.into_iter().collect())
+}
+
+/// Applies the transactions released by `buffer`, the ones that fail are recorded as dead letters.
+async fn apply_transactions(
+    tree: Pubkey,
+    buffer: &mut ReorderBuffer,
+    program_transformer: &ProgramTransformer,
+    context: &BubblegumBackfillContext,
+) {
+    while let Some(transaction) = buffer.pop() {
+        match program_transformer.handle_transaction(&transaction).await {
+            Ok(()) | Err(ProgramTransformerError::NotImplemented) => {}
+            Err(e) => {
+                eprintln!("Transaction processing error: {:?}", e);
+                record_dead_letter(
+                    context,
+                    tree,
+                    transaction.signature,
+                    Some(transaction.slot),
+                    DeadLetterErrorKind::Index,
+                    &e.to_string(),
+                )
+                .await;
+            }
+        }
+    }
+}
+
+/// Checks the status of the buffered transactions that did not reach `commitment` yet.
+///
+/// Failed transactions and the ones absent from the chain once their slot is finalized are
+/// dropped. Transactions confirmed at another slot than they were received at landed again on
+/// another fork, possibly with other seqs, so they are fetched again.
+async fn confirm_transactions(
+    tree: Pubkey,
+    commitment: CommitmentConfig,
+    buffer: &mut ReorderBuffer,
+    program_transformer: &ProgramTransformer,
+    context: &BubblegumBackfillContext,
+) -> Result<(), ClientError> {
+    let rpc_client = get_rpc_client();
+    let mut finalized_slot = None;
+    let mut refetch = Vec::new();
+
+    for unconfirmed in buffer.unconfirmed().chunks(MAX_SIGNATURE_STATUSES) {
+        let signatures = unconfirmed
+            .iter()
+            .map(|(signature, _)| *signature)
+            .collect::<Vec<_>>();
+
+        let statuses = rpc_client
+            .get_signature_statuses_with_history(&signatures)
+            .await?
+            .value;
+
+        for ((signature, slot), status) in unconfirmed.iter().zip(statuses) {
+            match status {
+                Some(status) if status.err.is_some() => buffer.discard(signature),
+                Some(status) if status.satisfies_commitment(commitment) => {
+                    if status.slot == *slot {
+                        buffer.confirm(signature);
+                    } else {
+                        buffer.discard(signature);
+                        refetch.push(*signature);
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    let finalized_slot = match finalized_slot {
+                        Some(finalized_slot) => finalized_slot,
+                        None => *finalized_slot.insert(
+                            rpc_client
+                                .get_slot_with_commitment(CommitmentConfig::finalized())
+                                .await?,
+                        ),
+                    };
+
+                    if *slot <= finalized_slot {
+                        println!("Dropping transaction {:} from an abandoned fork", signature);
+                        buffer.discard(signature);
+                    }
+                }
+            }
+        }
+    }
+
+    for signature in refetch {
+        fetch_transaction(
+            tree,
+            signature,
+            commitment,
+            buffer,
+            program_transformer,
+            context,
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Crawls the transactions between the last applied one and the first buffered one past `gap`.
+async fn fill_gap(
+    tree: Pubkey,
+    gap: &Gap,
+    commitment: CommitmentConfig,
+    buffer: &mut ReorderBuffer,
+    program_transformer: &ProgramTransformer,
+    context: &BubblegumBackfillContext,
+) {
+    println!(
+        "Filling seqs {}..{} for tree: {:}",
+        gap.expected_seq, gap.buffered_seq, tree
+    );
+
+    let (signature_sender, mut signature_receiver) =
+        channel::<Signature>(GAP_SIGNATURES_CHANNEL_SIZE);
+
+    let gap_fill = TreeGapFill::new(tree, Some(gap.before), gap.until);
+    let client = context
+        .solana_rpc
+        .with_commitment(CommitmentConfig::confirmed());
+
+    let crawl = tokio::spawn(async move { gap_fill.crawl(client, signature_sender).await });
+
+    while let Some(signature) = signature_receiver.recv().await {
+        fetch_transaction(
+            tree,
+            signature,
+            commitment,
+            buffer,
+            program_transformer,
+            context,
+        )
+        .await;
+    }
+
+    match crawl.await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("Error crawling seq gap for tree {:}: {:?}", tree, e),
+        Err(e) => eprintln!("Error joining seq gap crawl for tree {:}: {:?}", tree, e),
+    }
+}
+
+/// Fetches `signature` at `confirmed` commitment and buffers it, as confirmed unless `commitment`
+/// is stricter, or records it as a dead letter.
+async fn fetch_transaction(
+    tree: Pubkey,
+    signature: Signature,
+    commitment: CommitmentConfig,
+    buffer: &mut ReorderBuffer,
+    program_transformer: &ProgramTransformer,
+    context: &BubblegumBackfillContext,
+) {
+    let transaction = context
+        .solana_rpc
+        .with_commitment(CommitmentConfig::confirmed())
+        .get_transaction(&signature)
+        .await
+        .map_err(|e| (None, DeadLetterErrorKind::Fetch, anyhow::Error::from(e)))
+        .and_then(|transaction| {
+            let slot = transaction.slot;
+
+            parse_transaction(transaction)
+                .map_err(|e| (Some(slot), DeadLetterErrorKind::Parse, e.into()))
+        });
+
+    match transaction {
+        Ok(transaction) => {
+            let seqs = program_transformer.change_log_seqs(&transaction, &tree);
+
+            buffer.push(transaction, &seqs, !commitment.is_finalized());
+        }
+        Err((slot, error_kind, e)) => {
+            eprintln!("Error fetching transaction {:}: {:?}", signature, e);
+            record_dead_letter(context, tree, signature, slot, error_kind, &e.to_string()).await;
+        }
+    }
+}
+
+/// The audit of the newest change log applied to `tree`.
+pub async fn get_last_audit(
+    tree: Pubkey,
+    context: &BubblegumBackfillContext,
+) -> Option<cl_audits_v2::Model> {
+    let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+
+    let audit = cl_audits_v2::Entity::find()
+        .filter(cl_audits_v2::Column::Tree.eq(tree.as_ref().to_vec()))
+        .order_by_desc(cl_audits_v2::Column::Seq)
+        .one(&conn)
+        .await;
+
+    match audit {
+        Ok(audit) => audit,
+        Err(e) => {
+            eprintln!(
+                "Error fetching last audited transaction for tree {:}: {:?}",
+                tree, e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use das_core::{Rpc, SolanaRpcArgs};
+    use futures::FutureExt;
+    use jsonrpsee::server::ServerBuilder;
+    use jsonrpsee::RpcModule;
+    use serde_json::{json, Value};
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::Transaction;
+    use solana_transaction_status::{
+        Encodable, EncodedConfirmedTransactionWithStatusMeta, EncodedTransactionWithStatusMeta,
+        TransactionStatusMeta, UiTransactionEncoding,
+    };
+    use sqlx::postgres::PgPoolOptions;
+    use tokio::sync::mpsc::UnboundedSender;
+
+    use crate::config::rpc_config::RPC_CLIENT;
+
+    use super::*;
+
+    /// Transactions of the mocked chain, newest first.
+    struct MockChain {
+        transactions: Vec<EncodedConfirmedTransactionWithStatusMeta>,
+        commitments: Mutex<Vec<String>>,
+    }
+
+    impl MockChain {
+        fn signature(transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Signature {
+            transaction
+                .transaction
+                .transaction
+                .decode()
+                .unwrap()
+                .signatures[0]
+        }
+
+        fn signatures_for_address(&self, config: &Value) -> Value {
+            self.record_commitment(config);
+
+            let signature = |key: &str| {
+                config[key]
+                    .as_str()
+                    .map(|signature| Signature::from_str(signature).unwrap())
+            };
+            let (before, until) = (signature("before"), signature("until"));
+
+            let statuses = self
+                .transactions
+                .iter()
+                .skip_while(|transaction| {
+                    before.is_some_and(|before| Self::signature(transaction) != before)
+                })
+                .skip(usize::from(before.is_some()))
+                .take_while(|transaction| Some(Self::signature(transaction)) != until)
+                .map(|transaction| {
+                    json!({
+                        "signature": Self::signature(transaction).to_string(),
+                        "slot": transaction.slot,
+                        "err": null,
+                        "memo": null,
+                        "blockTime": null,
+                        "confirmationStatus": "confirmed",
+                    })
+                })
+                .collect();
+
+            Value::Array(statuses)
+        }
+
+        fn transaction(&self, signature: &str, config: &Value) -> Value {
+            self.record_commitment(config);
+
+            let transaction = self
+                .transactions
+                .iter()
+                .find(|transaction| Self::signature(transaction).to_string() == signature);
+
+            serde_json::to_value(transaction).unwrap()
+        }
+
+        fn record_commitment(&self, config: &Value) {
+            self.commitments.lock().unwrap().push(
+                config["commitment"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+        }
+    }
+
+    async fn start_mock_rpc(chain: Arc<MockChain>) -> SocketAddr {
+        let server = ServerBuilder::default()
+            .build("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+        let address = server.local_addr().unwrap();
+
+        let mut module = RpcModule::new(chain);
+        // Queried by the client to pick how commitments are sent
+        module
+            .register_method("getVersion", |_params, _chain| {
+                Ok(json!({ "solana-core": "1.17.14", "feature-set": 0 }))
+            })
+            .unwrap();
+        module
+            .register_method("getSignaturesForAddress", |params, chain| {
+                let (_address, config) = params.parse::<(String, Value)>()?;
+                Ok(chain.signatures_for_address(&config))
+            })
+            .unwrap();
+        module
+            .register_method("getTransaction", |params, chain| {
+                let (signature, config) = params.parse::<(String, Value)>()?;
+                Ok(chain.transaction(&signature, &config))
+            })
+            .unwrap();
+
+        // Leaked so the server keeps running until the test ends
+        std::mem::forget(server.start(module).unwrap());
+
+        address
+    }
+
+    fn transaction(tree: Pubkey, slot: u64) -> EncodedConfirmedTransactionWithStatusMeta {
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[],
+            vec![AccountMeta::new(tree, false)],
+        );
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[&payer],
+            solana_sdk::hash::Hash::new_unique(),
+        );
+
+        EncodedConfirmedTransactionWithStatusMeta {
+            slot,
+            transaction: EncodedTransactionWithStatusMeta {
+                transaction: transaction.encode(UiTransactionEncoding::Base58),
+                meta: Some(TransactionStatusMeta::default().into()),
+                version: None,
+            },
+            block_time: None,
+        }
+    }
+
+    fn context(address: SocketAddr) -> BubblegumBackfillContext {
+        // There is no database, fail fast when reading the last audit
+        let database_pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_secs(1))
+            .connect_lazy("postgres://localhost/das")
+            .unwrap();
+
+        BubblegumBackfillContext::new(
+            database_pool.clone(),
+            Rpc::from_config(&SolanaRpcArgs {
+                solana_rpc_url: format!("http://{address}"),
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn hands_off_between_the_last_applied_and_the_first_live_transaction() {
+        let tree = Pubkey::new_unique();
+
+        // Newest first, the backfill applied slot 100 and the subscription started at slot 104
+        let after_first_live = transaction(tree, 105);
+        let same_slot_as_first_live = transaction(tree, 104);
+        let first_live = transaction(tree, 104);
+        let blind = transaction(tree, 102);
+        let unfinalized = transaction(tree, 101);
+        let last_applied = transaction(tree, 100);
+        let backfilled = transaction(tree, 99);
+
+        let signature = MockChain::signature;
+        let last_applied_signature = signature(&last_applied);
+        let first_live_signature = signature(&first_live);
+        let first_live_info =
+            parse_transaction(serde_json::from_value(json!(first_live)).unwrap()).unwrap();
+        let expected = vec![
+            signature(&unfinalized),
+            signature(&blind),
+            first_live_signature,
+            signature(&same_slot_as_first_live),
+        ];
+        let skipped = [signature(&after_first_live), signature(&backfilled)];
+
+        let chain = Arc::new(MockChain {
+            transactions: vec![
+                after_first_live,
+                same_slot_as_first_live,
+                first_live,
+                blind,
+                unfinalized,
+                last_applied,
+                backfilled,
+            ],
+            commitments: Mutex::new(Vec::new()),
+        });
+        let context = context(start_mock_rpc(Arc::clone(&chain)).await);
+        let program_transformer = ProgramTransformer::new(
+            context.database_pool.clone(),
+            Box::new(|_info| futures::future::ready(Ok(())).boxed()),
+            false,
+        );
+        let mut buffer = ReorderBuffer::new(None);
+
+        let handed_off = handoff(
+            tree,
+            first_live_info,
+            CrawlLowerBound {
+                slot: None,
+                signature: Some(last_applied_signature),
+            },
+            CommitmentConfig::confirmed(),
+            &mut buffer,
+            &program_transformer,
+            &context,
+        )
+        .await
+        .unwrap();
+
+        // Transactions of the slot of the first live one may have landed before the subscription
+        assert_eq!(handed_off, expected.iter().copied().collect::<HashSet<_>>());
+        assert!(skipped
+            .iter()
+            .all(|signature| !handed_off.contains(signature)));
+
+        // Crawled transactions are confirmed and released oldest first, the first live one was
+        // crawled too and is only buffered once
+        let released = std::iter::from_fn(|| buffer.pop())
+            .map(|transaction| transaction.signature)
+            .collect::<Vec<_>>();
+        assert_eq!(released, expected);
+        assert!(buffer.is_empty());
+
+        assert!(chain
+            .commitments
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|commitment| commitment == "confirmed"));
+    }
+
+    /// Records the transactions processing is done with.
+    #[derive(Default)]
+    struct RecordingSource {
+        handled: Mutex<Vec<Signature>>,
+    }
+
+    #[async_trait]
+    impl TransactionSource for RecordingSource {
+        async fn subscribe(&self, _tree: Pubkey, _sender: UnboundedSender<TransactionInfo>) {}
+
+        fn handled(&self, signature: &Signature) {
+            self.handled.lock().unwrap().push(*signature);
+        }
+    }
+
+    /// Serves the statuses of the live transactions, confirmed from the second poll on. The first
+    /// poll cancels `shutdown`, so it happens while transactions are still buffered.
+    async fn start_mock_statuses(shutdown: CancellationToken) -> SocketAddr {
+        let server = ServerBuilder::default()
+            .build("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+        let address = server.local_addr().unwrap();
+
+        let mut module = RpcModule::new(shutdown);
+        module
+            .register_method("getVersion", |_params, _shutdown| {
+                Ok(json!({ "solana-core": "1.17.14", "feature-set": 0 }))
+            })
+            .unwrap();
+        module
+            .register_method("getSignatureStatuses", |params, shutdown| {
+                let (signatures, _config) = params.parse::<(Vec<String>, Value)>()?;
+                let confirmation_status = if shutdown.is_cancelled() {
+                    "confirmed"
+                } else {
+                    shutdown.cancel();
+                    "processed"
+                };
+                let statuses = signatures
+                    .iter()
+                    .map(|_| {
+                        json!({
+                            "slot": 100,
+                            "confirmations": null,
+                            "status": { "Ok": null },
+                            "err": null,
+                            "confirmationStatus": confirmation_status,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(json!({ "context": { "slot": 100 }, "value": statuses }))
+            })
+            .unwrap();
+
+        // Leaked so the server keeps running until the test ends
+        std::mem::forget(server.start(module).unwrap());
+
+        address
+    }
+
+    #[tokio::test]
+    async fn stops_the_subscription_on_shutdown() {
+        let tree = Pubkey::new_unique();
+        let shutdown = CancellationToken::new();
+        let address = start_mock_statuses(shutdown.clone()).await;
+        // No other test polls the statuses of live transactions
+        RPC_CLIENT.get_or_init(|| RpcClient::new(format!("http://{address}")));
+
+        let context = context(address);
+        let program_transformer = ProgramTransformer::new(
+            context.database_pool.clone(),
+            Box::new(|_info| futures::future::ready(Ok(())).boxed()),
+            false,
+        );
+        let transaction_source = RecordingSource::default();
+        let settings = TreeSettings {
+            backfill: false,
+            ..Default::default()
+        };
+
+        // The second one is still in the channel when shutdown is cancelled
+        let received = [transaction(tree, 100), transaction(tree, 100)].map(|transaction| {
+            parse_transaction(serde_json::from_value(json!(transaction)).unwrap()).unwrap()
+        });
+        let signatures = received
+            .iter()
+            .map(|transaction| transaction.signature)
+            .collect::<HashSet<_>>();
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        for transaction in received {
+            sender.send(transaction).unwrap();
+        }
+
+        tokio::time::timeout(
+            Duration::from_secs(30),
+            process_transactions_channel(
+                tree,
+                receiver,
+                &transaction_source,
+                &settings,
+                CrawlLowerBound::default(),
+                &program_transformer,
+                &context,
+                &shutdown,
+            ),
+        )
+        .await
+        .unwrap();
+
+        // The subscription stopped and what was received was drained and processed
+        assert!(shutdown.is_cancelled());
+        assert!(sender.is_closed());
+        let handled = transaction_source.handled.lock().unwrap();
+        assert_eq!(handled.len(), signatures.len());
+        assert_eq!(handled.iter().copied().collect::<HashSet<_>>(), signatures);
     }
 }
@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+
+use das_bubblegum_backfill::{BubblegumBackfillContext, CrawlLowerBound, TreeGapFill};
+use futures::StreamExt;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_client::rpc_response::RpcLogsResponse;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc::{channel, UnboundedSender};
+
+use crate::config::rpc_config::{get_pubsub_client, get_rpc_client, reconnect_pubsub_client};
+
+const MISSED_SIGNATURES_CHANNEL_SIZE: usize = 1000;
+
+/// How far back the transactions missed while disconnected are crawled.
+#[derive(Default)]
+struct MissedWindow {
+    /// Newest signature known to be confirmed, live messages are only `processed` and may come
+    /// from an abandoned fork.
+    until: Option<Signature>,
+    /// Newest slot the subscription was known to be live at.
+    slot: Option<u64>,
+    /// Live signatures already sent for `slot`, the crawl starts at that slot.
+    slot_signatures: HashSet<String>,
+}
+
+impl MissedWindow {
+    /// Records that the subscription was live at `slot`, received `signature` when set.
+    fn live_at(&mut self, slot: u64, signature: Option<&str>) {
+        if self.slot.is_none_or(|live_slot| slot > live_slot) {
+            self.slot = Some(slot);
+            self.slot_signatures.clear();
+        }
+
+        if let Some(signature) = signature.filter(|_| Some(slot) == self.slot) {
+            self.slot_signatures.insert(signature.to_string());
+        }
+    }
+
+    /// Where the crawl stops, at the newest confirmed signature or the slot the subscription was
+    /// last live at, whichever comes first.
+    const fn lower_bound(&self) -> CrawlLowerBound {
+        CrawlLowerBound {
+            slot: self.slot,
+            signature: self.until,
+        }
+    }
+}
+
+/// Streams the logs of every transaction mentioning `tree` into `sender`.
+///
+/// When the websocket stream ends, the pubsub client is rebuilt, the tree is resubscribed and the
+/// transactions that landed while disconnected are crawled and queued ahead of the new live
+/// messages. Returns once `sender` is closed.
+pub async fn subscribe_tree_logs(
+    tree: Pubkey,
+    context: BubblegumBackfillContext,
+    sender: UnboundedSender<RpcLogsResponse>,
+) {
+    let mut client = get_pubsub_client().await;
+    let mut missed_window = MissedWindow::default();
+    let mut reconnected = false;
+
+    loop {
+        match client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![tree.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(CommitmentConfig::processed()),
+                },
+            )
+            .await
+        {
+            Ok((mut stream, _unsubscribe)) => {
+                let mut filled_signatures = HashSet::new();
+                // Behind the tip so that the slots the websocket has yet to deliver are crawled
+                let subscribed_slot = get_rpc_client()
+                    .get_slot_with_commitment(CommitmentConfig::confirmed())
+                    .await;
+
+                if reconnected {
+                    match fill_missed_window(tree, &mut missed_window, &context, &sender).await {
+                        Ok(signatures) => filled_signatures = signatures,
+                        Err(_) => return,
+                    }
+                }
+
+                match subscribed_slot {
+                    Ok(slot) => missed_window.live_at(slot, None),
+                    Err(e) => eprintln!(
+                        "Error fetching the slot logs of tree {:} are subscribed at: {:?}",
+                        tree, e
+                    ),
+                }
+
+                while let Some(logs) = stream.next().await {
+                    let slot = logs.context.slot;
+                    let logs = logs.value;
+
+                    if filled_signatures.remove(&logs.signature) {
+                        continue;
+                    }
+
+                    missed_window.live_at(slot, Some(&logs.signature));
+
+                    if sender.send(logs).is_err() {
+                        return;
+                    }
+                }
+
+                eprintln!("Logs subscription ended for tree: {:}", tree);
+            }
+            Err(e) => {
+                eprintln!("Error subscribing to logs for tree {:}: {:?}", tree, e);
+            }
+        }
+
+        if sender.is_closed() {
+            return;
+        }
+
+        client = reconnect_pubsub_client(&client).await;
+        reconnected = true;
+
+        println!("Resubscribing to logs for tree: {:}", tree);
+    }
+}
+
+/// Crawls the signatures of `tree` within `missed_window` and queues them oldest first, except
+/// the ones already sent live. The newest crawled signature, confirmed, bounds the next crawl.
+///
+/// Returns the queued signatures so their live duplicates can be skipped, or an error if `sender`
+/// is closed.
+async fn fill_missed_window(
+    tree: Pubkey,
+    missed_window: &mut MissedWindow,
+    context: &BubblegumBackfillContext,
+    sender: &UnboundedSender<RpcLogsResponse>,
+) -> Result<HashSet<String>, ()> {
+    let lower_bound = missed_window.lower_bound();
+    // Never live, the transactions before the first live one are crawled by the handoff
+    if lower_bound.slot.is_none() {
+        return Ok(HashSet::new());
+    }
+
+    println!("Filling missed transactions for tree: {:}", tree);
+
+    let (signature_sender, mut signature_receiver) =
+        channel::<Signature>(MISSED_SIGNATURES_CHANNEL_SIZE);
+
+    let gap = TreeGapFill::new(tree, None, lower_bound.signature).with_lower_bound(lower_bound);
+    // Live messages are at `processed`, so crawl closer to the tip than the default `finalized`
+    let client = context
+        .solana_rpc
+        .with_commitment(CommitmentConfig::confirmed());
+
+    let crawl = tokio::spawn(async move { gap.crawl(client, signature_sender).await });
+
+    let mut signatures = Vec::new();
+    while let Some(signature) = signature_receiver.recv().await {
+        signatures.push(signature);
+    }
+
+    match crawl.await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!(
+            "Error crawling missed transactions for tree {:}: {:?}",
+            tree, e
+        ),
+        Err(e) => eprintln!(
+            "Error joining missed transactions crawl for tree {:}: {:?}",
+            tree, e
+        ),
+    }
+
+    if let Some(newest) = signatures.first() {
+        missed_window.until = Some(*newest);
+    }
+
+    // Signatures are crawled newest first
+    signatures.reverse();
+
+    let mut filled_signatures = HashSet::with_capacity(signatures.len());
+
+    for signature in signatures {
+        let signature = signature.to_string();
+        if missed_window.slot_signatures.contains(&signature) {
+            continue;
+        }
+
+        sender
+            .send(RpcLogsResponse {
+                signature: signature.clone(),
+                err: None,
+                logs: vec![],
+            })
+            .map_err(|_| ())?;
+
+        filled_signatures.insert(signature);
+    }
+
+    println!(
+        "Queued {} missed transactions for tree: {:}",
+        filled_signatures.len(),
+        tree
+    );
+
+    Ok(filled_signatures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_the_missed_window_by_the_last_live_slot() {
+        let mut missed_window = MissedWindow::default();
+        assert_eq!(missed_window.lower_bound().slot, None);
+
+        // Subscribed, no message received yet
+        missed_window.live_at(10, None);
+        assert_eq!(missed_window.lower_bound().slot, Some(10));
+
+        missed_window.live_at(12, Some("a"));
+        missed_window.live_at(12, Some("b"));
+        // Messages of older slots arrive late
+        missed_window.live_at(11, Some("c"));
+        assert_eq!(missed_window.lower_bound().slot, Some(12));
+        assert_eq!(
+            missed_window.slot_signatures,
+            HashSet::from(["a".to_string(), "b".to_string()])
+        );
+
+        missed_window.live_at(13, Some("d"));
+        assert_eq!(
+            missed_window.slot_signatures,
+            HashSet::from(["d".to_string()])
+        );
+
+        // Only crawled signatures, confirmed, bound it by signature
+        assert_eq!(missed_window.lower_bound().signature, None);
+        let until = Signature::new_unique();
+        missed_window.until = Some(until);
+        assert_eq!(missed_window.lower_bound().signature, Some(until));
+    }
+}
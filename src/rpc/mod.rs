@@ -1 +1,2 @@
+pub mod logs_subscription;
 pub mod rpc;
@@ -1,4 +1,3 @@
-use std::str::FromStr;
 use std::time::Duration;
 
 use crate::config::rpc_config::get_rpc_client;
@@ -9,7 +8,7 @@ use solana_sdk::signature::Signature;
 use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
 
 pub async fn get_transaction_with_retries(
-    signature: &str,
+    signature: &Signature,
 ) -> Result<EncodedConfirmedTransactionWithStatusMeta, ClientError> {
     let rpc_client = get_rpc_client();
 
@@ -19,7 +18,7 @@ pub async fn get_transaction_with_retries(
     for _ in 0..MAX_RETRIES {
         let transaction = rpc_client
             .get_transaction_with_config(
-                &Signature::from_str(signature).expect("Invalid transaction signature"),
+                signature,
                 RpcTransactionConfig {
                     max_supported_transaction_version: Some(0),
                     encoding: Some(UiTransactionEncoding::Base58),
@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use das_bubblegum_backfill::{BubblegumBackfillContext, DeadLetterErrorKind};
+use program_transformers::TransactionInfo;
+use solana_client::rpc_response::RpcLogsResponse;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task;
+
+use crate::dead_letter::record_dead_letter;
+use crate::processor::transaction::parse_transaction;
+use crate::rpc::logs_subscription::subscribe_tree_logs;
+use crate::rpc::rpc::get_transaction_with_retries;
+
+use super::TransactionSource;
+
+/// Subscribes to `logsSubscribe` over the websocket and fetches every notified transaction with
+/// `getTransaction`.
+pub struct LogsSource {
+    context: BubblegumBackfillContext,
+}
+
+impl LogsSource {
+    pub const fn new(context: BubblegumBackfillContext) -> Self {
+        Self { context }
+    }
+}
+
+#[async_trait]
+impl TransactionSource for LogsSource {
+    async fn subscribe(&self, tree: Pubkey, sender: UnboundedSender<TransactionInfo>) {
+        let (logs_sender, mut logs_receiver) = unbounded_channel::<RpcLogsResponse>();
+
+        let subscription =
+            task::spawn(subscribe_tree_logs(tree, self.context.clone(), logs_sender));
+
+        while let Some(logs) = logs_receiver.recv().await {
+            let Ok(signature) = logs.signature.parse::<Signature>() else {
+                eprintln!("Invalid transaction signature {:}", logs.signature);
+                continue;
+            };
+
+            let transaction = match get_transaction_with_retries(&signature).await {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    eprintln!("Error fetching transaction {:}: {:?}", logs.signature, e);
+                    record_dead_letter(
+                        &self.context,
+                        tree,
+                        signature,
+                        None,
+                        DeadLetterErrorKind::Fetch,
+                        &e.to_string(),
+                    )
+                    .await;
+                    continue;
+                }
+            };
+            let slot = transaction.slot;
+
+            match parse_transaction(transaction) {
+                Ok(transaction) => {
+                    if sender.send(transaction).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error parsing transaction {:}: {:?}", logs.signature, e);
+                    record_dead_letter(
+                        &self.context,
+                        tree,
+                        signature,
+                        Some(slot),
+                        DeadLetterErrorKind::Parse,
+                        &e.to_string(),
+                    )
+                    .await;
+                }
+            }
+        }
+
+        subscription.abort();
+    }
+}
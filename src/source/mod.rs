@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use das_bubblegum_backfill::BubblegumBackfillContext;
+use das_core::{QueueArgs, QueuePool, Rpc, SolanaRpcArgs};
+use futures::FutureExt;
+use program_transformers::{ProgramTransformer, TransactionInfo};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use sqlx::{Pool, Postgres};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::args::IndexerArgs;
+
+pub mod logs;
+pub mod plerkle;
+pub mod yellowstone;
+
+pub use logs::LogsSource;
+pub use plerkle::PlerkleSource;
+pub use yellowstone::YellowstoneSource;
+
+/// A live feed of the transactions touching a tree.
+#[async_trait]
+pub trait TransactionSource: Send + Sync {
+    /// Streams the transactions mentioning `tree` into `sender` in the order they landed.
+    ///
+    /// Implementations are expected to recover from dropped connections on their own and only
+    /// return once `sender` is closed.
+    async fn subscribe(&self, tree: Pubkey, sender: UnboundedSender<TransactionInfo>);
+
+    /// Tells the source that a subscriber is done with a transaction it received, it was applied,
+    /// recorded as a dead letter or dropped.
+    ///
+    /// Sources that acknowledge what they consume only do so once every subscriber the transaction
+    /// was sent to is done with it, the others have nothing to do.
+    fn handled(&self, _signature: &Signature) {}
+}
+
+/// Consumer group LightDAS reads the plerkle streams with, so it gets its own copy of every
+/// message instead of splitting them with other ingesters.
+const PLERKLE_CONSUMER_GROUP: &str = "lightdas";
+const PLERKLE_BATCH_SIZE: &str = "100";
+/// One connection per stream consumed, plus one to acknowledge the applied transactions.
+const PLERKLE_QUEUE_CONNECTIONS: u64 = 3;
+/// How long a message may stay unacknowledged before it is delivered again. Transactions are
+/// acknowledged once applied, which takes up to their finalization and the filling of a gap.
+const PLERKLE_IDLE_TIMEOUT_MS: u64 = 60_000;
+
+/// Picks the live transaction source from the config, the websocket is the default.
+pub async fn setup_transaction_source(
+    config: &IndexerArgs,
+    database_pool: Pool<Postgres>,
+) -> Result<Arc<dyn TransactionSource>> {
+    match (&config.grpc_url, &config.messenger_redis_url) {
+        (Some(_), Some(_)) => bail!("GRPC_URL and MESSENGER_REDIS_URL cannot both be set"),
+        (Some(grpc_url), None) => Ok(Arc::new(YellowstoneSource::new(
+            grpc_url,
+            config.grpc_x_token.as_deref(),
+        )?)),
+        (None, Some(messenger_redis_url)) => {
+            let queue = QueuePool::try_from_config(&QueueArgs {
+                messenger_redis_url: messenger_redis_url.clone(),
+                messenger_redis_batch_size: PLERKLE_BATCH_SIZE.to_string(),
+                messenger_queue_connections: PLERKLE_QUEUE_CONNECTIONS,
+                messenger_consumer_group_name: Some(PLERKLE_CONSUMER_GROUP.to_string()),
+                messenger_consumer_id: None,
+                messenger_idle_timeout_ms: Some(PLERKLE_IDLE_TIMEOUT_MS),
+            })
+            .await?;
+
+            let program_transformer = ProgramTransformer::new(
+                database_pool,
+                Box::new(|_info| futures::future::ready(Ok(())).boxed()),
+                false,
+            );
+
+            Ok(Arc::new(PlerkleSource::new(queue, program_transformer)))
+        }
+        (None, None) => Ok(Arc::new(LogsSource::new(BubblegumBackfillContext::new(
+            database_pool,
+            Rpc::from_config(&SolanaRpcArgs {
+                solana_rpc_url: config.rpc_url.clone(),
+            }),
+        )))),
+    }
+}
@@ -0,0 +1,694 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Error};
+use async_trait::async_trait;
+use blockbuster::program_handler::ProgramParser;
+use blockbuster::programs::mpl_core_program::{MplCoreAccountData, MplCoreParser};
+use blockbuster::programs::token_metadata::{TokenMetadataAccountData, TokenMetadataParser};
+use blockbuster::programs::ProgramParseResult;
+use das_core::{QueuePool, QueuePoolError};
+use mpl_core::types::UpdateAuthority;
+use plerkle_messenger::RecvData;
+use plerkle_serialization::deserializer::{
+    PlerkleCompiledInnerInstructionVector, PlerkleCompiledInstructionVector,
+    PlerkleInnerInstructionsVector, PlerkleOptionalPubkeyVector, PlerkleOptionalStr,
+    PlerkleOptionalU8Vector,
+};
+use plerkle_serialization::{root_as_account_info, root_as_transaction_info};
+use program_transformers::{AccountInfo, ProgramTransformer, TransactionInfo};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task;
+
+use super::TransactionSource;
+
+/// How long to wait before polling again when a stream had no new messages.
+const EMPTY_STREAM_POLL_DELAY: Duration = Duration::from_millis(100);
+const STREAM_ERROR_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Handled transactions acknowledged per request.
+const ACK_BATCH_SIZE: usize = 100;
+
+type Subscriptions = Arc<Mutex<HashMap<Pubkey, Vec<UnboundedSender<TransactionInfo>>>>>;
+type InFlightTransactions = Arc<Mutex<HashMap<Signature, InFlightTransaction>>>;
+
+/// A transaction sent to subscribers that are not all done with it yet.
+struct InFlightTransaction {
+    /// Ids of the messages it was delivered in, it is delivered again when left pending too long.
+    ids: Vec<String>,
+    senders: Vec<UnboundedSender<TransactionInfo>>,
+    /// Subscribers still holding it.
+    remaining: usize,
+}
+
+/// Consumes the plerkle transaction and account streams that a Geyser plugin publishes to Redis.
+///
+/// A single consumer reads each stream for every tree. Transactions are sent once to every
+/// subscriber of any of their account keys, account updates are written directly when they
+/// belong to a subscribed address.
+///
+/// Messages are only acknowledged once the transactions were handled by every subscriber, or the
+/// account updates written. Until then they stay pending in the consumer group, which delivers
+/// them again after a restart.
+pub struct PlerkleSource {
+    subscriptions: Subscriptions,
+    in_flight: InFlightTransactions,
+    handled: UnboundedSender<Vec<String>>,
+}
+
+impl PlerkleSource {
+    /// Starts consuming the streams of `queue` in the background.
+    pub fn new(queue: QueuePool, program_transformer: ProgramTransformer) -> Self {
+        let subscriptions = Subscriptions::default();
+        let in_flight = InFlightTransactions::default();
+        let (handled, handled_receiver) = unbounded_channel();
+
+        task::spawn(consume_transactions(
+            queue.clone(),
+            Arc::clone(&subscriptions),
+            Arc::clone(&in_flight),
+        ));
+        task::spawn(ack_handled_transactions(queue.clone(), handled_receiver));
+        task::spawn(consume_accounts(
+            queue,
+            program_transformer,
+            Arc::clone(&subscriptions),
+        ));
+
+        Self {
+            subscriptions,
+            in_flight,
+            handled,
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionSource for PlerkleSource {
+    async fn subscribe(&self, tree: Pubkey, sender: UnboundedSender<TransactionInfo>) {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .entry(tree)
+            .or_default()
+            .push(sender.clone());
+
+        sender.closed().await;
+
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        // Other subscribers of the same address, e.g. a reloaded tree, keep their subscription
+        if let Some(senders) = subscriptions.get_mut(&tree) {
+            senders.retain(|current| !current.same_channel(&sender));
+
+            if senders.is_empty() {
+                subscriptions.remove(&tree);
+            }
+        }
+    }
+
+    fn handled(&self, signature: &Signature) {
+        if let Some(ids) = handle_transaction(&self.in_flight, signature) {
+            // The acknowledging task only stops with the source
+            let _ = self.handled.send(ids);
+        }
+    }
+}
+
+async fn consume_transactions(
+    queue: QueuePool,
+    subscriptions: Subscriptions,
+    in_flight: InFlightTransactions,
+) {
+    loop {
+        let Some(messages) = recv_messages(queue.recv_transactions().await).await else {
+            continue;
+        };
+
+        // Messages no subscriber has anything to do with
+        let mut ids = Vec::new();
+
+        for message in messages {
+            match parse_transaction(&message.data) {
+                Ok(transaction) => ids.extend(track_transaction(
+                    &subscriptions,
+                    &in_flight,
+                    transaction,
+                    message.id,
+                )),
+                Err(e) => {
+                    eprintln!("Error parsing plerkle transaction {}: {:?}", message.id, e);
+                    ids.push(message.id);
+                }
+            }
+        }
+
+        if let Err(e) = queue.ack_transactions(&ids).await {
+            eprintln!("Error acknowledging plerkle transactions: {:?}", e);
+        }
+    }
+}
+
+/// Acknowledges the messages of the transactions every subscriber is done with.
+async fn ack_handled_transactions(queue: QueuePool, mut handled: UnboundedReceiver<Vec<String>>) {
+    let mut batch = Vec::with_capacity(ACK_BATCH_SIZE);
+
+    while handled.recv_many(&mut batch, ACK_BATCH_SIZE).await > 0 {
+        let ids = batch.drain(..).flatten().collect::<Vec<_>>();
+
+        // Left pending, they are delivered again and acknowledged as already applied
+        if let Err(e) = queue.ack_transactions(&ids).await {
+            eprintln!("Error acknowledging plerkle transactions: {:?}", e);
+        }
+    }
+}
+
+async fn consume_accounts(
+    queue: QueuePool,
+    program_transformer: ProgramTransformer,
+    subscriptions: Subscriptions,
+) {
+    loop {
+        let Some(messages) = recv_messages(queue.recv_accounts().await).await else {
+            continue;
+        };
+
+        let watched: HashSet<Pubkey> = subscriptions.lock().unwrap().keys().copied().collect();
+        let mut ids = Vec::with_capacity(messages.len());
+
+        for message in messages {
+            match parse_account(&message.data) {
+                Ok(account) if is_watched_account(&watched, &account) => {
+                    if let Err(e) = program_transformer.handle_account_update(&account).await {
+                        eprintln!("Error handling account {:}: {:?}", account.pubkey, e);
+                        // Left pending to be delivered again
+                        continue;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Error parsing plerkle account {}: {:?}", message.id, e),
+            }
+
+            ids.push(message.id);
+        }
+
+        if let Err(e) = queue.ack_accounts(&ids).await {
+            eprintln!("Error acknowledging plerkle accounts: {:?}", e);
+        }
+    }
+}
+
+/// Unwraps a read from a stream, backing off when it failed or came back empty.
+async fn recv_messages(messages: Result<Vec<RecvData>, QueuePoolError>) -> Option<Vec<RecvData>> {
+    match messages {
+        Ok(messages) if messages.is_empty() => {
+            tokio::time::sleep(EMPTY_STREAM_POLL_DELAY).await;
+            None
+        }
+        Ok(messages) => Some(messages),
+        Err(e) => {
+            eprintln!("Error receiving plerkle messages: {:?}", e);
+            tokio::time::sleep(STREAM_ERROR_RETRY_DELAY).await;
+            None
+        }
+    }
+}
+
+/// Routes `transaction`, delivered in message `id`, and tracks it until its subscribers are done
+/// with it. Returns the ids of the messages there is nothing to wait for anymore.
+///
+/// A transaction delivered again while its subscribers still hold it is not routed twice. When
+/// one of them unsubscribed meanwhile, e.g. a restarted tree, it is only routed to the current
+/// subscribers that don't hold it.
+fn track_transaction(
+    subscriptions: &Subscriptions,
+    in_flight: &InFlightTransactions,
+    transaction: TransactionInfo,
+    id: String,
+) -> Vec<String> {
+    // Held while routing so that subscribers can't be done with it before it is tracked
+    let mut in_flight = in_flight.lock().unwrap();
+    let signature = transaction.signature;
+
+    if let Some(tracked) = in_flight.get_mut(&signature) {
+        if !tracked.ids.contains(&id) {
+            tracked.ids.push(id);
+        }
+        if tracked.senders.iter().all(|sender| !sender.is_closed()) {
+            return vec![];
+        }
+
+        // Unsubscribed ones are not waited for anymore. Which subscribers were done with it is not
+        // known, so the ones left may all still hold it
+        tracked.senders.retain(|sender| !sender.is_closed());
+        tracked.remaining = tracked.remaining.min(tracked.senders.len());
+
+        let routed = route_transaction(subscriptions, transaction, &tracked.senders);
+        tracked.remaining += routed.len();
+        tracked.senders.extend(routed);
+
+        if tracked.remaining > 0 {
+            return vec![];
+        }
+        return in_flight
+            .remove(&signature)
+            .map(|tracked| tracked.ids)
+            .unwrap_or_default();
+    }
+
+    let senders = route_transaction(subscriptions, transaction, &[]);
+    if senders.is_empty() {
+        return vec![id];
+    }
+
+    in_flight.insert(
+        signature,
+        InFlightTransaction {
+            ids: vec![id],
+            remaining: senders.len(),
+            senders,
+        },
+    );
+
+    vec![]
+}
+
+/// Records that a subscriber is done with `signature`, returning the ids of its messages once
+/// every subscriber is.
+fn handle_transaction(
+    in_flight: &InFlightTransactions,
+    signature: &Signature,
+) -> Option<Vec<String>> {
+    let mut in_flight = in_flight.lock().unwrap();
+
+    let tracked = in_flight.get_mut(signature)?;
+    tracked.remaining = tracked.remaining.saturating_sub(1);
+
+    if tracked.remaining > 0 {
+        return None;
+    }
+
+    in_flight.remove(signature).map(|tracked| tracked.ids)
+}
+
+/// Sends `transaction` to the subscribers of its account keys, once per subscriber even when it
+/// subscribed several of them, returning the ones it was sent to. Subscribers in `holding` already
+/// received it and are skipped.
+fn route_transaction(
+    subscriptions: &Subscriptions,
+    transaction: TransactionInfo,
+    holding: &[UnboundedSender<TransactionInfo>],
+) -> Vec<UnboundedSender<TransactionInfo>> {
+    let subscriptions = subscriptions.lock().unwrap();
+    let mut senders: Vec<&UnboundedSender<TransactionInfo>> = Vec::new();
+
+    for sender in transaction
+        .account_keys
+        .iter()
+        .filter_map(|key| subscriptions.get(key))
+        .flatten()
+    {
+        if !senders
+            .iter()
+            .copied()
+            .chain(holding)
+            .any(|routed| routed.same_channel(sender))
+        {
+            senders.push(sender);
+        }
+    }
+
+    senders
+        .into_iter()
+        // A closed channel means the subscriber is being unsubscribed, nothing left to deliver to
+        .filter(|sender| sender.send(transaction.clone()).is_ok())
+        .cloned()
+        .collect()
+}
+
+/// Whether an account update belongs to a watched address, either directly or as an asset of a
+/// watched collection.
+fn is_watched_account(watched: &HashSet<Pubkey>, account: &AccountInfo) -> bool {
+    if watched.contains(&account.pubkey) {
+        return true;
+    }
+
+    let collection = if account.owner == MplCoreParser.key() {
+        MplCoreParser
+            .handle_account(&account.data)
+            .ok()
+            .and_then(|parsed| match parsed.result_type() {
+                ProgramParseResult::MplCore(state) => match &state.data {
+                    MplCoreAccountData::Asset(asset) => match asset.update_authority {
+                        UpdateAuthority::Collection(collection) => Some(collection),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            })
+    } else if account.owner == TokenMetadataParser.key() {
+        TokenMetadataParser
+            .handle_account(&account.data)
+            .ok()
+            .and_then(|parsed| match parsed.result_type() {
+                ProgramParseResult::TokenMetadata(state) => match &state.data {
+                    TokenMetadataAccountData::MetadataV1(metadata) => metadata
+                        .collection
+                        .as_ref()
+                        .map(|collection| collection.key),
+                    _ => None,
+                },
+                _ => None,
+            })
+    } else {
+        None
+    };
+
+    collection.is_some_and(|collection| watched.contains(&collection))
+}
+
+fn parse_transaction(bytes: &[u8]) -> Result<TransactionInfo, Error> {
+    let transaction = root_as_transaction_info(bytes)?;
+
+    let message_instructions = transaction
+        .outer_instructions()
+        .ok_or_else(|| anyhow!("transaction does not have instructions"))?;
+
+    // Older plugin versions only publish inner instructions without stack heights
+    let meta_inner_instructions = match transaction.compiled_inner_instructions() {
+        Some(instructions) => PlerkleCompiledInnerInstructionVector(instructions).try_into()?,
+        None => match transaction.inner_instructions() {
+            Some(instructions) => PlerkleInnerInstructionsVector(instructions).try_into()?,
+            None => vec![],
+        },
+    };
+
+    Ok(TransactionInfo {
+        slot: transaction.slot(),
+        signature: Signature::try_from(PlerkleOptionalStr(transaction.signature()))?,
+        account_keys: PlerkleOptionalPubkeyVector(transaction.account_keys()).try_into()?,
+        message_instructions: PlerkleCompiledInstructionVector(message_instructions).try_into()?,
+        meta_inner_instructions,
+    })
+}
+
+fn parse_account(bytes: &[u8]) -> Result<AccountInfo, Error> {
+    let account = root_as_account_info(bytes)?;
+
+    let pubkey = account
+        .pubkey()
+        .ok_or_else(|| anyhow!("account does not have a pubkey"))?;
+    let owner = account
+        .owner()
+        .ok_or_else(|| anyhow!("account does not have an owner"))?;
+
+    Ok(AccountInfo {
+        slot: account.slot(),
+        pubkey: Pubkey::try_from(pubkey)?,
+        owner: Pubkey::try_from(owner)?,
+        data: PlerkleOptionalU8Vector(account.data()).try_into()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+    use flatbuffers::FlatBufferBuilder;
+    use mpl_core::accounts::BaseAssetV1;
+    use mpl_core::types::Key;
+    use plerkle_serialization::serializer::{
+        seralize_encoded_transaction_with_status, serialize_account,
+    };
+    use plerkle_serialization::solana_geyser_plugin_interface_shims::ReplicaAccountInfoV2;
+    use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use crate::processor::transaction::parse_transaction as parse_rpc_transaction;
+
+    use super::*;
+
+    fn load_fixture(name: &str) -> EncodedConfirmedTransactionWithStatusMeta {
+        let path = format!(
+            "{}/blockbuster/tests/fixtures/{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn serialize_fixture_account(pubkey: Pubkey, owner: Pubkey, data: &[u8]) -> Vec<u8> {
+        let account = ReplicaAccountInfoV2 {
+            pubkey: pubkey.as_ref(),
+            lamports: 1,
+            owner: owner.as_ref(),
+            executable: false,
+            rent_epoch: 0,
+            data,
+            write_version: 1,
+            txn_signature: None,
+        };
+
+        serialize_account(FlatBufferBuilder::new(), &account, 42, false)
+            .finished_data()
+            .to_vec()
+    }
+
+    /// The fixtures predate stack heights, which the plerkle serializer then writes as `0`.
+    fn without_stack_heights(mut transaction: TransactionInfo) -> TransactionInfo {
+        for inner in &mut transaction.meta_inner_instructions {
+            for instruction in &mut inner.instructions {
+                instruction.stack_height = None;
+            }
+        }
+
+        transaction
+    }
+
+    #[test]
+    fn parses_transaction_like_rpc() {
+        for name in ["double_bubblegum_mint", "helium_nested"] {
+            let builder = seralize_encoded_transaction_with_status(
+                FlatBufferBuilder::new(),
+                load_fixture(name),
+            )
+            .unwrap();
+
+            let transaction = parse_transaction(builder.finished_data()).unwrap();
+            let expected = parse_rpc_transaction(load_fixture(name)).unwrap();
+
+            assert!(
+                without_stack_heights(transaction) == without_stack_heights(expected),
+                "{name} parsed differently from plerkle than from RPC"
+            );
+        }
+    }
+
+    #[test]
+    fn routes_transaction_to_subscribed_tree() {
+        let builder = seralize_encoded_transaction_with_status(
+            FlatBufferBuilder::new(),
+            load_fixture("double_bubblegum_mint"),
+        )
+        .unwrap();
+        let transaction = parse_transaction(builder.finished_data()).unwrap();
+
+        let subscriptions = Subscriptions::default();
+        let (other_sender, mut other_receiver) = unbounded_channel();
+        subscriptions
+            .lock()
+            .unwrap()
+            .insert(Pubkey::new_unique(), vec![other_sender]);
+
+        // Not touching any subscribed tree, dropped
+        route_transaction(&subscriptions, transaction.clone(), &[]);
+        assert!(other_receiver.try_recv().is_err());
+
+        let tree = transaction.account_keys[1];
+        let (sender, mut receiver) = unbounded_channel();
+        subscriptions.lock().unwrap().insert(tree, vec![sender]);
+
+        route_transaction(&subscriptions, transaction.clone(), &[]);
+        assert_eq!(receiver.try_recv().unwrap(), transaction);
+        assert!(other_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn routes_transaction_to_every_subscriber() {
+        let builder = seralize_encoded_transaction_with_status(
+            FlatBufferBuilder::new(),
+            load_fixture("double_bubblegum_mint"),
+        )
+        .unwrap();
+        let transaction = parse_transaction(builder.finished_data()).unwrap();
+
+        // The signer comes first among the account keys
+        let creator = transaction.account_keys[0];
+        let tree = transaction.account_keys[1];
+
+        let (tree_sender, mut tree_receiver) = unbounded_channel();
+        let (reloaded_sender, mut reloaded_receiver) = unbounded_channel();
+        let (discovery_sender, mut discovery_receiver) = unbounded_channel();
+        let subscriptions = Subscriptions::default();
+        subscriptions.lock().unwrap().extend([
+            (tree, vec![tree_sender, reloaded_sender]),
+            (creator, vec![discovery_sender.clone()]),
+            (transaction.account_keys[2], vec![discovery_sender]),
+        ]);
+
+        route_transaction(&subscriptions, transaction.clone(), &[]);
+
+        assert_eq!(tree_receiver.try_recv().unwrap(), transaction);
+        assert_eq!(reloaded_receiver.try_recv().unwrap(), transaction);
+        assert_eq!(discovery_receiver.try_recv().unwrap(), transaction);
+        // Subscribed to two of its keys, still received once
+        assert!(discovery_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn acknowledges_transactions_once_every_subscriber_handled_them() {
+        let builder = seralize_encoded_transaction_with_status(
+            FlatBufferBuilder::new(),
+            load_fixture("double_bubblegum_mint"),
+        )
+        .unwrap();
+        let transaction = parse_transaction(builder.finished_data()).unwrap();
+        let signature = transaction.signature;
+
+        let subscriptions = Subscriptions::default();
+        let in_flight = InFlightTransactions::default();
+
+        // Nothing to wait for without subscribers
+        assert_eq!(
+            track_transaction(
+                &subscriptions,
+                &in_flight,
+                transaction.clone(),
+                "1-0".into()
+            ),
+            vec!["1-0".to_string()]
+        );
+
+        let (tree_sender, mut tree_receiver) = unbounded_channel();
+        let (discovery_sender, mut discovery_receiver) = unbounded_channel();
+        subscriptions.lock().unwrap().extend([
+            (transaction.account_keys[1], vec![tree_sender]),
+            (transaction.account_keys[0], vec![discovery_sender]),
+        ]);
+
+        assert!(track_transaction(
+            &subscriptions,
+            &in_flight,
+            transaction.clone(),
+            "2-0".into()
+        )
+        .is_empty());
+        // Delivered again while both subscribers hold it, not routed twice
+        assert!(track_transaction(
+            &subscriptions,
+            &in_flight,
+            transaction.clone(),
+            "2-0".into()
+        )
+        .is_empty());
+        assert_eq!(tree_receiver.try_recv().unwrap(), transaction);
+        assert_eq!(discovery_receiver.try_recv().unwrap(), transaction);
+        assert!(tree_receiver.try_recv().is_err());
+
+        assert_eq!(handle_transaction(&in_flight, &signature), None);
+        assert_eq!(
+            handle_transaction(&in_flight, &signature),
+            Some(vec!["2-0".to_string()])
+        );
+        assert_eq!(handle_transaction(&in_flight, &signature), None);
+
+        // A subscriber restarted while holding it, only routed again to the restarted one
+        assert!(track_transaction(
+            &subscriptions,
+            &in_flight,
+            transaction.clone(),
+            "3-0".into()
+        )
+        .is_empty());
+        drop(tree_receiver);
+        let (restarted_sender, mut restarted_receiver) = unbounded_channel();
+        subscriptions
+            .lock()
+            .unwrap()
+            .insert(transaction.account_keys[1], vec![restarted_sender]);
+
+        assert!(track_transaction(
+            &subscriptions,
+            &in_flight,
+            transaction.clone(),
+            "3-0".into()
+        )
+        .is_empty());
+        assert_eq!(restarted_receiver.try_recv().unwrap(), transaction);
+        assert_eq!(discovery_receiver.try_recv().unwrap(), transaction);
+        assert!(discovery_receiver.try_recv().is_err());
+        assert_eq!(handle_transaction(&in_flight, &signature), None);
+        assert_eq!(
+            handle_transaction(&in_flight, &signature),
+            Some(vec!["3-0".to_string()])
+        );
+    }
+
+    #[test]
+    fn parses_account() {
+        let pubkey = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        let account = parse_account(&serialize_fixture_account(pubkey, owner, &[1, 2, 3])).unwrap();
+
+        assert_eq!(account.slot, 42);
+        assert_eq!(account.pubkey, pubkey);
+        assert_eq!(account.owner, owner);
+        assert_eq!(account.data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn watches_accounts_of_watched_collections() {
+        let collection = Pubkey::new_unique();
+        let watched = HashSet::from([collection]);
+
+        let asset = |update_authority| AccountInfo {
+            slot: 1,
+            pubkey: Pubkey::new_unique(),
+            owner: MplCoreParser.key(),
+            data: BaseAssetV1 {
+                key: Key::AssetV1,
+                owner: Pubkey::new_unique(),
+                update_authority,
+                name: "Asset".to_string(),
+                uri: "https://example.com/asset.json".to_string(),
+                seq: None,
+            }
+            .try_to_vec()
+            .unwrap(),
+        };
+
+        assert!(is_watched_account(
+            &watched,
+            &asset(UpdateAuthority::Collection(collection))
+        ));
+        assert!(!is_watched_account(
+            &watched,
+            &asset(UpdateAuthority::Collection(Pubkey::new_unique()))
+        ));
+        assert!(!is_watched_account(
+            &watched,
+            &asset(UpdateAuthority::Address(collection))
+        ));
+
+        let collection_account = AccountInfo {
+            slot: 1,
+            pubkey: collection,
+            owner: MplCoreParser.key(),
+            data: vec![],
+        };
+        assert!(is_watched_account(&watched, &collection_account));
+    }
+}
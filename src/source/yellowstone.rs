@@ -0,0 +1,580 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Error};
+use async_trait::async_trait;
+use backon::{BackoffBuilder, ExponentialBuilder};
+use futures::channel::mpsc::{unbounded, UnboundedSender as RequestSender};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::connect::HttpConnector;
+use program_transformers::TransactionInfo;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{InnerInstruction, InnerInstructions};
+use tokio::sync::mpsc::UnboundedSender;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Status};
+use yellowstone_grpc_proto::geyser::geyser_client::GeyserClient;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{
+    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterTransactions, SubscribeRequestPing,
+    SubscribeUpdateTransaction,
+};
+
+use super::TransactionSource;
+
+const TRANSACTIONS_FILTER: &str = "lightdas";
+const PING_ID: i32 = 1;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Streams full transactions from a Yellowstone gRPC Geyser endpoint, so no `getTransaction` call
+/// is needed per signature.
+pub struct YellowstoneSource {
+    channel: Channel,
+    x_token: Option<AsciiMetadataValue>,
+    reconnect_backoff: ExponentialBuilder,
+}
+
+impl YellowstoneSource {
+    /// Prepares a lazily connected channel to `endpoint`, TLS is used for `https` endpoints.
+    pub fn new(endpoint: &str, x_token: Option<&str>) -> Result<Self, Error> {
+        let endpoint =
+            Endpoint::from_shared(endpoint.to_string())?.connect_timeout(CONNECT_TIMEOUT);
+
+        // tonic's own TLS needs a newer rustls than the solana crates allow, so TLS goes through
+        // native-tls instead. gRPC needs HTTP/2, which TLS servers only pick when offered in ALPN.
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        let tls = native_tls::TlsConnector::builder()
+            .request_alpns(&["h2"])
+            .build()?;
+        let channel =
+            endpoint.connect_with_connector_lazy(HttpsConnector::from((http, tls.into())));
+
+        let x_token = x_token.map(AsciiMetadataValue::try_from).transpose()?;
+
+        Ok(Self {
+            channel,
+            x_token,
+            reconnect_backoff: ExponentialBuilder::default()
+                .with_jitter()
+                .with_max_delay(RECONNECT_MAX_DELAY)
+                .with_max_times(usize::MAX),
+        })
+    }
+
+    /// Runs a single subscription until the stream fails, returns `Ok` once `sender` is closed.
+    async fn stream_transactions(
+        &self,
+        tree: Pubkey,
+        resume: &mut ResumePoint,
+        sender: &UnboundedSender<TransactionInfo>,
+    ) -> Result<(), Error> {
+        let x_token = self.x_token.clone();
+        let mut client =
+            GeyserClient::with_interceptor(self.channel.clone(), move |mut request: Request<()>| {
+                if let Some(x_token) = &x_token {
+                    request.metadata_mut().insert("x-token", x_token.clone());
+                }
+                Ok(request)
+            });
+
+        // The request stream must stay open for the subscription to stay alive
+        let (requests, request_stream) = unbounded();
+        requests.unbounded_send(subscribe_request(tree, resume.slot))?;
+
+        let mut updates = client.subscribe(request_stream).await?.into_inner();
+
+        while let Some(update) = updates.message().await? {
+            match update.update_oneof {
+                Some(UpdateOneof::Transaction(update)) => {
+                    let transaction = match parse_transaction_update(update) {
+                        Ok(transaction) => transaction,
+                        Err(e) => {
+                            eprintln!("Error parsing gRPC transaction for tree {:}: {:?}", tree, e);
+                            continue;
+                        }
+                    };
+
+                    if !resume.advance(transaction.slot, transaction.signature) {
+                        continue;
+                    }
+
+                    if sender.send(transaction).is_err() {
+                        return Ok(());
+                    }
+                }
+                Some(UpdateOneof::Ping(_)) => send_ping(&requests),
+                _ => {}
+            }
+        }
+
+        bail!("gRPC transaction stream ended")
+    }
+}
+
+#[async_trait]
+impl TransactionSource for YellowstoneSource {
+    async fn subscribe(&self, tree: Pubkey, sender: UnboundedSender<TransactionInfo>) {
+        let mut resume = ResumePoint::default();
+        let mut backoff = self.reconnect_backoff.build();
+
+        loop {
+            let resumed_slot = resume.slot;
+
+            let error = match self.stream_transactions(tree, &mut resume, &sender).await {
+                Ok(()) => return,
+                Err(e) => e,
+            };
+
+            eprintln!("gRPC subscription error for tree {:}: {:?}", tree, error);
+
+            if sender.is_closed() {
+                return;
+            }
+
+            // Not every endpoint keeps enough history to replay from the last seen slot
+            let replay_rejected = error
+                .downcast_ref::<Status>()
+                .is_some_and(|status| status.code() == Code::InvalidArgument);
+
+            if replay_rejected && resumed_slot.is_some() {
+                eprintln!(
+                    "gRPC endpoint cannot replay from slot {:?} for tree {:}, transactions missed while disconnected are skipped",
+                    resumed_slot, tree
+                );
+                resume = ResumePoint::default();
+            }
+
+            // Anything received means the connection was healthy, start backing off from scratch
+            if resume.slot != resumed_slot {
+                backoff = self.reconnect_backoff.build();
+            }
+
+            if let Some(delay) = backoff.next() {
+                tokio::time::sleep(delay).await;
+            }
+
+            println!("Resubscribing to gRPC transactions for tree: {:}", tree);
+        }
+    }
+}
+
+/// The newest slot delivered and the signatures delivered in it.
+///
+/// Resubscriptions replay from that slot so nothing landing while disconnected is lost, the
+/// signatures filter out what was already delivered before the disconnect.
+#[derive(Default)]
+struct ResumePoint {
+    slot: Option<u64>,
+    signatures: HashSet<Signature>,
+}
+
+impl ResumePoint {
+    /// Records a transaction, returns `false` if it was already delivered.
+    fn advance(&mut self, slot: u64, signature: Signature) -> bool {
+        match self.slot {
+            Some(current) if slot < current => true,
+            Some(current) if slot == current => self.signatures.insert(signature),
+            _ => {
+                self.slot = Some(slot);
+                self.signatures.clear();
+                self.signatures.insert(signature);
+                true
+            }
+        }
+    }
+}
+
+fn subscribe_request(tree: Pubkey, from_slot: Option<u64>) -> SubscribeRequest {
+    SubscribeRequest {
+        transactions: HashMap::from([(
+            TRANSACTIONS_FILTER.to_string(),
+            SubscribeRequestFilterTransactions {
+                vote: Some(false),
+                failed: Some(false),
+                account_include: vec![tree.to_string()],
+                ..Default::default()
+            },
+        )]),
+        commitment: Some(CommitmentLevel::Confirmed as i32),
+        from_slot,
+        ..Default::default()
+    }
+}
+
+/// Answers the server's keepalive pings, some load balancers drop idle streams otherwise.
+fn send_ping(requests: &RequestSender<SubscribeRequest>) {
+    let _ = requests.unbounded_send(SubscribeRequest {
+        ping: Some(SubscribeRequestPing { id: PING_ID }),
+        ..Default::default()
+    });
+}
+
+fn parse_transaction_update(update: SubscribeUpdateTransaction) -> Result<TransactionInfo, Error> {
+    let transaction = update
+        .transaction
+        .ok_or_else(|| anyhow!("update does not have a transaction"))?;
+    let signature = Signature::try_from(transaction.signature.as_slice())?;
+    let message = transaction
+        .transaction
+        .and_then(|transaction| transaction.message)
+        .ok_or_else(|| anyhow!("transaction {signature} does not have a message"))?;
+    let meta = transaction
+        .meta
+        .ok_or_else(|| anyhow!("transaction {signature} does not have meta"))?;
+
+    let account_keys = message
+        .account_keys
+        .iter()
+        .chain(meta.loaded_writable_addresses.iter())
+        .chain(meta.loaded_readonly_addresses.iter())
+        .map(|key| Pubkey::try_from(key.as_slice()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let message_instructions = message
+        .instructions
+        .into_iter()
+        .map(|instruction| {
+            Ok(CompiledInstruction {
+                program_id_index: u8::try_from(instruction.program_id_index)?,
+                accounts: instruction.accounts,
+                data: instruction.data,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let meta_inner_instructions = meta
+        .inner_instructions
+        .into_iter()
+        .map(|inner| {
+            Ok(InnerInstructions {
+                index: u8::try_from(inner.index)?,
+                instructions: inner
+                    .instructions
+                    .into_iter()
+                    .map(|instruction| {
+                        Ok(InnerInstruction {
+                            instruction: CompiledInstruction {
+                                program_id_index: u8::try_from(instruction.program_id_index)?,
+                                accounts: instruction.accounts,
+                                data: instruction.data,
+                            },
+                            stack_height: instruction.stack_height,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(TransactionInfo {
+        slot: update.slot,
+        signature,
+        account_keys,
+        message_instructions,
+        meta_inner_instructions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+
+    use futures::stream::{self, BoxStream, StreamExt};
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+    use tonic::{Response, Streaming};
+    use yellowstone_grpc_proto::geyser::geyser_server::{Geyser, GeyserServer};
+    use yellowstone_grpc_proto::geyser::{
+        GetBlockHeightRequest, GetBlockHeightResponse, GetLatestBlockhashRequest,
+        GetLatestBlockhashResponse, GetSlotRequest, GetSlotResponse, GetVersionRequest,
+        GetVersionResponse, IsBlockhashValidRequest, IsBlockhashValidResponse, PingRequest,
+        PongResponse, SubscribeDeshredRequest, SubscribeGossipRequest, SubscribeReplayInfoRequest,
+        SubscribeReplayInfoResponse, SubscribeUpdate, SubscribeUpdateDeshred,
+        SubscribeUpdateGossip, SubscribeUpdateTransactionInfo,
+    };
+    use yellowstone_grpc_proto::solana::storage::confirmed_block::{
+        self, InnerInstructions as ProtoInnerInstructions, Message, Transaction,
+        TransactionStatusMeta,
+    };
+
+    use super::*;
+
+    /// Serves one scripted session per subscription, finished sessions end their stream like a
+    /// dropped connection would. Once the script runs out subscriptions stay open and idle.
+    struct MockGeyser {
+        sessions: Mutex<VecDeque<Vec<SubscribeUpdate>>>,
+        subscriptions: UnboundedSender<(Option<String>, SubscribeRequest)>,
+    }
+
+    #[tonic::async_trait]
+    impl Geyser for MockGeyser {
+        type SubscribeStream = BoxStream<'static, Result<SubscribeUpdate, Status>>;
+        type SubscribeDeshredStream = BoxStream<'static, Result<SubscribeUpdateDeshred, Status>>;
+        type SubscribeGossipStream = BoxStream<'static, Result<SubscribeUpdateGossip, Status>>;
+
+        async fn subscribe(
+            &self,
+            request: Request<Streaming<SubscribeRequest>>,
+        ) -> Result<Response<Self::SubscribeStream>, Status> {
+            let x_token = request
+                .metadata()
+                .get("x-token")
+                .and_then(|x_token| x_token.to_str().ok())
+                .map(String::from);
+
+            let subscription = request
+                .into_inner()
+                .message()
+                .await?
+                .ok_or_else(|| Status::invalid_argument("missing subscribe request"))?;
+            let _ = self.subscriptions.send((x_token, subscription));
+
+            let session = self.sessions.lock().unwrap().pop_front();
+
+            Ok(Response::new(match session {
+                Some(updates) => stream::iter(updates.into_iter().map(Ok)).boxed(),
+                None => stream::pending().boxed(),
+            }))
+        }
+
+        async fn subscribe_deshred(
+            &self,
+            _request: Request<Streaming<SubscribeDeshredRequest>>,
+        ) -> Result<Response<Self::SubscribeDeshredStream>, Status> {
+            Err(Status::unimplemented("subscribe_deshred"))
+        }
+
+        async fn subscribe_gossip(
+            &self,
+            _request: Request<SubscribeGossipRequest>,
+        ) -> Result<Response<Self::SubscribeGossipStream>, Status> {
+            Err(Status::unimplemented("subscribe_gossip"))
+        }
+
+        async fn subscribe_replay_info(
+            &self,
+            _request: Request<SubscribeReplayInfoRequest>,
+        ) -> Result<Response<SubscribeReplayInfoResponse>, Status> {
+            Err(Status::unimplemented("subscribe_replay_info"))
+        }
+
+        async fn ping(
+            &self,
+            _request: Request<PingRequest>,
+        ) -> Result<Response<PongResponse>, Status> {
+            Err(Status::unimplemented("ping"))
+        }
+
+        async fn get_latest_blockhash(
+            &self,
+            _request: Request<GetLatestBlockhashRequest>,
+        ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+            Err(Status::unimplemented("get_latest_blockhash"))
+        }
+
+        async fn get_block_height(
+            &self,
+            _request: Request<GetBlockHeightRequest>,
+        ) -> Result<Response<GetBlockHeightResponse>, Status> {
+            Err(Status::unimplemented("get_block_height"))
+        }
+
+        async fn get_slot(
+            &self,
+            _request: Request<GetSlotRequest>,
+        ) -> Result<Response<GetSlotResponse>, Status> {
+            Err(Status::unimplemented("get_slot"))
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _request: Request<IsBlockhashValidRequest>,
+        ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+            Err(Status::unimplemented("is_blockhash_valid"))
+        }
+
+        async fn get_version(
+            &self,
+            _request: Request<GetVersionRequest>,
+        ) -> Result<Response<GetVersionResponse>, Status> {
+            Err(Status::unimplemented("get_version"))
+        }
+    }
+
+    async fn start_mock_geyser(
+        sessions: Vec<Vec<SubscribeUpdate>>,
+    ) -> (
+        SocketAddr,
+        UnboundedReceiver<(Option<String>, SubscribeRequest)>,
+    ) {
+        let (subscriptions, subscriptions_receiver) = unbounded_channel();
+        let geyser = MockGeyser {
+            sessions: Mutex::new(sessions.into()),
+            subscriptions,
+        };
+
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = incoming.local_addr().unwrap();
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(GeyserServer::new(geyser))
+                .serve_with_incoming(incoming),
+        );
+
+        (address, subscriptions_receiver)
+    }
+
+    fn transaction_update(slot: u64, signature: Signature, tree: Pubkey) -> SubscribeUpdate {
+        let key = |pubkey: Pubkey| pubkey.to_bytes().to_vec();
+
+        SubscribeUpdate {
+            filters: vec![TRANSACTIONS_FILTER.to_string()],
+            update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+                transaction: Some(SubscribeUpdateTransactionInfo {
+                    signature: signature.as_ref().to_vec(),
+                    transaction: Some(Transaction {
+                        signatures: vec![signature.as_ref().to_vec()],
+                        message: Some(Message {
+                            account_keys: vec![
+                                key(Pubkey::new_unique()),
+                                key(tree),
+                                key(mpl_bubblegum::ID),
+                            ],
+                            instructions: vec![confirmed_block::CompiledInstruction {
+                                program_id_index: 2,
+                                accounts: vec![0, 1],
+                                data: vec![1, 2, 3],
+                            }],
+                            ..Default::default()
+                        }),
+                    }),
+                    meta: Some(TransactionStatusMeta {
+                        inner_instructions: vec![ProtoInnerInstructions {
+                            index: 0,
+                            instructions: vec![confirmed_block::InnerInstruction {
+                                program_id_index: 3,
+                                accounts: vec![1],
+                                data: vec![4, 5],
+                                stack_height: Some(2),
+                            }],
+                        }],
+                        loaded_writable_addresses: vec![key(spl_noop::ID)],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                slot,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_transaction_update() {
+        let tree = Pubkey::new_unique();
+        let signature = Signature::new_unique();
+
+        let Some(UpdateOneof::Transaction(update)) =
+            transaction_update(42, signature, tree).update_oneof
+        else {
+            unreachable!()
+        };
+        let transaction = parse_transaction_update(update).unwrap();
+
+        assert_eq!(transaction.slot, 42);
+        assert_eq!(transaction.signature, signature);
+        assert_eq!(transaction.account_keys.len(), 4);
+        assert_eq!(transaction.account_keys[1], tree);
+        assert_eq!(transaction.account_keys[2], mpl_bubblegum::ID);
+        // Addresses loaded from lookup tables come after the static keys
+        assert_eq!(transaction.account_keys[3], spl_noop::ID);
+
+        assert_eq!(
+            transaction.message_instructions,
+            vec![CompiledInstruction {
+                program_id_index: 2,
+                accounts: vec![0, 1],
+                data: vec![1, 2, 3],
+            }]
+        );
+
+        assert_eq!(transaction.meta_inner_instructions.len(), 1);
+        let inner = &transaction.meta_inner_instructions[0];
+        assert_eq!(inner.index, 0);
+        assert_eq!(inner.instructions.len(), 1);
+        assert_eq!(inner.instructions[0].instruction.program_id_index, 3);
+        assert_eq!(inner.instructions[0].instruction.accounts, vec![1]);
+        assert_eq!(inner.instructions[0].instruction.data, vec![4, 5]);
+        assert_eq!(inner.instructions[0].stack_height, Some(2));
+    }
+
+    #[test]
+    fn rejects_update_without_meta() {
+        let Some(UpdateOneof::Transaction(mut update)) =
+            transaction_update(42, Signature::new_unique(), Pubkey::new_unique()).update_oneof
+        else {
+            unreachable!()
+        };
+        update.transaction.as_mut().unwrap().meta = None;
+
+        assert!(parse_transaction_update(update).is_err());
+    }
+
+    #[tokio::test]
+    async fn resubscribes_from_last_slot_without_duplicates() {
+        let tree = Pubkey::new_unique();
+        let signatures: Vec<Signature> = (0..3).map(|_| Signature::new_unique()).collect();
+
+        let (address, mut subscriptions) = start_mock_geyser(vec![
+            vec![
+                transaction_update(10, signatures[0], tree),
+                transaction_update(11, signatures[1], tree),
+            ],
+            // Replayed from slot 11 after the first stream ended
+            vec![
+                transaction_update(11, signatures[1], tree),
+                transaction_update(12, signatures[2], tree),
+            ],
+        ])
+        .await;
+
+        let mut source =
+            YellowstoneSource::new(&format!("http://{address}"), Some("secret")).unwrap();
+        source.reconnect_backoff =
+            ExponentialBuilder::default().with_min_delay(Duration::from_millis(10));
+
+        let (sender, mut receiver) = unbounded_channel();
+        tokio::spawn(async move { source.subscribe(tree, sender).await });
+
+        let mut received = Vec::new();
+        for _ in 0..signatures.len() {
+            received.push(receiver.recv().await.unwrap().signature);
+        }
+        assert_eq!(received, signatures);
+
+        let (x_token, first) = subscriptions.recv().await.unwrap();
+        assert_eq!(x_token.as_deref(), Some("secret"));
+        assert_eq!(first.from_slot, None);
+        assert_eq!(first.commitment, Some(CommitmentLevel::Confirmed as i32));
+        let filter = &first.transactions[TRANSACTIONS_FILTER];
+        assert_eq!(filter.account_include, vec![tree.to_string()]);
+        assert_eq!(filter.vote, Some(false));
+        assert_eq!(filter.failed, Some(false));
+
+        let (_, second) = subscriptions.recv().await.unwrap();
+        assert_eq!(second.from_slot, Some(11));
+
+        // Nothing but the replayed duplicate was left undelivered
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use das_bubblegum_backfill::TreeResponse;
+use das_core::Rpc;
+use digital_asset_types::dao::ld_merkle_trees;
+use mpl_bubblegum::accounts::TreeConfig;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, Set, SqlxPostgresConnector};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::{Pool, Postgres};
+
+/// Trees fetched per `getMultipleAccounts` call, along with their tree config.
+const TREES_PER_REQUEST: usize = 50;
+
+/// The on-chain state of a tree stored in `ld_merkle_trees`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeMetadata {
+    pub capacity: i32,
+    pub max_depth: i32,
+    pub canopy_depth: i32,
+    pub max_buffer_size: i32,
+    pub creation_slot: i64,
+    pub seq: i64,
+    pub num_minted: Option<i64>,
+    pub tree_creator: Option<String>,
+    pub tree_delegate: Option<String>,
+}
+
+impl TreeMetadata {
+    /// `tree_config` is missing for trees not created through Bubblegum.
+    pub fn new(tree: &TreeResponse, tree_config: Option<&TreeConfig>) -> Self {
+        let header = &tree.tree_header;
+
+        Self {
+            capacity: 1 << header.max_depth,
+            max_depth: header.max_depth as i32,
+            canopy_depth: tree.canopy_depth as i32,
+            max_buffer_size: header.max_buffer_size as i32,
+            creation_slot: header.creation_slot as i64,
+            seq: tree.seq as i64,
+            num_minted: tree_config.map(|config| config.num_minted as i64),
+            tree_creator: tree_config.map(|config| config.tree_creator.to_string()),
+            tree_delegate: tree_config.map(|config| config.tree_delegate.to_string()),
+        }
+    }
+
+    fn into_active_model(self) -> ld_merkle_trees::ActiveModel {
+        ld_merkle_trees::ActiveModel {
+            capacity: Set(Some(self.capacity)),
+            max_depth: Set(Some(self.max_depth)),
+            canopy_depth: Set(Some(self.canopy_depth)),
+            max_buffer_size: Set(Some(self.max_buffer_size)),
+            creation_slot: Set(Some(self.creation_slot)),
+            seq: Set(Some(self.seq)),
+            num_minted: Set(self.num_minted),
+            tree_creator: Set(self.tree_creator),
+            tree_delegate: Set(self.tree_delegate),
+            ..Default::default()
+        }
+    }
+}
+
+/// Fetches the tree accounts and tree configs of `addresses` and stores their metadata in
+/// `ld_merkle_trees`.
+///
+/// Trees that can't be fetched or decoded are skipped and left as they were.
+pub async fn refresh_tree_metadata(
+    rpc: &Rpc,
+    database_pool: &Pool<Postgres>,
+    addresses: &[String],
+) -> Result<()> {
+    let database_connection = SqlxPostgresConnector::from_sqlx_postgres_pool(database_pool.clone());
+    let trees = addresses
+        .iter()
+        .filter_map(|address| Pubkey::from_str(address).ok())
+        .collect::<Vec<_>>();
+
+    for trees in trees.chunks(TREES_PER_REQUEST) {
+        let tree_configs = trees
+            .iter()
+            .map(|tree| TreeConfig::find_pda(tree).0)
+            .collect::<Vec<_>>();
+
+        let mut accounts = rpc
+            .get_multiple_accounts(&[trees, tree_configs.as_slice()].concat())
+            .await?;
+        let tree_config_accounts = accounts.split_off(trees.len());
+
+        for ((tree, account), tree_config_account) in
+            trees.iter().zip(accounts).zip(tree_config_accounts)
+        {
+            let Some(account) = account else {
+                eprintln!("Tree {:} not found on chain", tree);
+                continue;
+            };
+
+            let tree_response = match TreeResponse::try_from_rpc(*tree, account) {
+                Ok(tree_response) => tree_response,
+                Err(e) => {
+                    eprintln!("Error decoding tree {:}: {:?}", tree, e);
+                    continue;
+                }
+            };
+            let tree_config =
+                tree_config_account.and_then(|account| TreeConfig::from_bytes(&account.data).ok());
+
+            let res = ld_merkle_trees::Entity::update_many()
+                .set(TreeMetadata::new(&tree_response, tree_config.as_ref()).into_active_model())
+                .col_expr(
+                    ld_merkle_trees::Column::UpdatedAt,
+                    Expr::cust("CURRENT_TIMESTAMP"),
+                )
+                .filter(ld_merkle_trees::Column::Address.eq(tree.to_string()))
+                .exec(&database_connection)
+                .await;
+
+            if let Err(e) = res {
+                eprintln!("Error storing metadata of tree {:}: {:?}", tree, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use das_bubblegum_backfill::TreeHeaderResponse;
+    use mpl_bubblegum::types::DecompressibleState;
+
+    use super::*;
+
+    fn tree_response() -> TreeResponse {
+        TreeResponse {
+            pubkey: Pubkey::new_unique(),
+            tree_header: TreeHeaderResponse {
+                max_depth: 14,
+                max_buffer_size: 64,
+                creation_slot: 250_000_000,
+                size: 0,
+            },
+            seq: 1200,
+            canopy_depth: 10,
+        }
+    }
+
+    #[test]
+    fn reads_capacity_and_fullness_from_the_header_and_tree_config() {
+        let tree = tree_response();
+        let tree_config = TreeConfig {
+            discriminator: [0; 8],
+            tree_creator: Pubkey::new_unique(),
+            tree_delegate: Pubkey::new_unique(),
+            total_mint_capacity: 1 << 14,
+            num_minted: 1100,
+            is_public: false,
+            is_decompressible: DecompressibleState::Disabled,
+        };
+
+        assert_eq!(
+            TreeMetadata::new(&tree, Some(&tree_config)),
+            TreeMetadata {
+                capacity: 16384,
+                max_depth: 14,
+                canopy_depth: 10,
+                max_buffer_size: 64,
+                creation_slot: 250_000_000,
+                seq: 1200,
+                num_minted: Some(1100),
+                tree_creator: Some(tree_config.tree_creator.to_string()),
+                tree_delegate: Some(tree_config.tree_delegate.to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_the_tree_config_columns_empty_without_a_tree_config() {
+        let metadata = TreeMetadata::new(&tree_response(), None);
+
+        assert_eq!(metadata.capacity, 16384);
+        assert_eq!(metadata.num_minted, None);
+        assert_eq!(metadata.tree_creator, None);
+        assert_eq!(metadata.tree_delegate, None);
+    }
+}
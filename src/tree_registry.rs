@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use migration::LD_MERKLE_TREES_CHANNEL;
+use sqlx::postgres::PgListener;
+use sqlx::{Pool, Postgres};
+use tokio::sync::mpsc::Sender;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Requests a reload on `reload_sender` whenever a tree is inserted in or deleted from
+/// `ld_merkle_trees`, or its `should_index` or its settings change.
+///
+/// Notifications sent while the connection is lost are missed, so a reload is also requested
+/// every time the listener reconnects.
+pub async fn listen_tree_registry_changes(
+    database_pool: Pool<Postgres>,
+    reload_sender: Sender<()>,
+) {
+    let mut reconnecting = false;
+
+    loop {
+        let mut listener = match PgListener::connect_with(&database_pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Error connecting the tree registry listener: {:?}", e);
+                reconnecting = true;
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+
+        if let Err(e) = listener.listen(LD_MERKLE_TREES_CHANNEL).await {
+            eprintln!("Error listening to tree registry changes: {:?}", e);
+            reconnecting = true;
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            continue;
+        }
+
+        println!("Listening to tree registry changes");
+        if reconnecting {
+            let _ = reload_sender.try_send(());
+        }
+
+        loop {
+            match listener.try_recv().await {
+                Ok(Some(notification)) => {
+                    println!("Tree {:} changed in the registry", notification.payload());
+                }
+                Ok(None) => {
+                    eprintln!("Tree registry listener disconnected, reconnecting...");
+
+                    // Listening again reconnects, changes made from now on are notified
+                    if let Err(e) = listener.listen(LD_MERKLE_TREES_CHANNEL).await {
+                        eprintln!("Error listening to tree registry changes: {:?}", e);
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error receiving tree registry changes: {:?}", e);
+                    break;
+                }
+            }
+
+            // A full channel means a reload is already pending
+            let _ = reload_sender.try_send(());
+        }
+
+        reconnecting = true;
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
@@ -0,0 +1,292 @@
+use std::str::FromStr;
+
+use das_bubblegum_backfill::{BubblegumBackfillArgs, CrawlLowerBound};
+use digital_asset_types::dao::ld_merkle_trees;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TreeSettingsError {
+    #[error("invalid backfill_after_signature `{0}`")]
+    Signature(String),
+    #[error("invalid backfill_from_slot {0}")]
+    Slot(i64),
+    #[error("{0} must be greater than 0, got {1}")]
+    WorkerCount(&'static str, i32),
+    #[error("invalid commitment `{0}`, expected `confirmed` or `finalized`")]
+    Commitment(String),
+}
+
+/// The settings of a tree in `ld_merkle_trees`.
+///
+/// Unset worker counts fall back to the ones LightDAS is configured with, a tree whose settings
+/// change is restarted with them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeSettings {
+    /// Whether the history of the tree is backfilled before it is indexed live.
+    pub backfill: bool,
+    /// Only the transactions landed at or after this slot are indexed.
+    pub backfill_from_slot: Option<u64>,
+    /// Only the transactions landed after this signature are indexed.
+    pub backfill_after_signature: Option<Signature>,
+    pub signature_worker_count: Option<usize>,
+    pub gap_worker_count: Option<usize>,
+    pub metadata_json_download_worker_count: Option<usize>,
+    /// The commitment live transactions are applied at.
+    pub commitment: CommitmentConfig,
+    /// Whether the metadata JSON of the backfilled assets is downloaded.
+    pub download_metadata_json: bool,
+    /// Trees with a higher priority are backfilled first.
+    pub priority: i32,
+}
+
+impl Default for TreeSettings {
+    fn default() -> Self {
+        Self {
+            backfill: true,
+            backfill_from_slot: None,
+            backfill_after_signature: None,
+            signature_worker_count: None,
+            gap_worker_count: None,
+            metadata_json_download_worker_count: None,
+            commitment: CommitmentConfig::confirmed(),
+            download_metadata_json: true,
+            priority: 0,
+        }
+    }
+}
+
+impl TryFrom<&ld_merkle_trees::Model> for TreeSettings {
+    type Error = TreeSettingsError;
+
+    fn try_from(tree: &ld_merkle_trees::Model) -> Result<Self, Self::Error> {
+        let worker_count = |name, count: Option<i32>| match count {
+            Some(count) if count <= 0 => Err(TreeSettingsError::WorkerCount(name, count)),
+            count => Ok(count.map(|count| count as usize)),
+        };
+
+        let commitment = match tree.commitment.as_str() {
+            "confirmed" => CommitmentConfig::confirmed(),
+            "finalized" => CommitmentConfig::finalized(),
+            commitment => return Err(TreeSettingsError::Commitment(commitment.to_string())),
+        };
+
+        Ok(Self {
+            backfill: tree.backfill,
+            backfill_from_slot: tree
+                .backfill_from_slot
+                .map(|slot| u64::try_from(slot).map_err(|_| TreeSettingsError::Slot(slot)))
+                .transpose()?,
+            backfill_after_signature: tree
+                .backfill_after_signature
+                .as_deref()
+                .map(|signature| {
+                    Signature::from_str(signature)
+                        .map_err(|_| TreeSettingsError::Signature(signature.to_string()))
+                })
+                .transpose()?,
+            signature_worker_count: worker_count(
+                "signature_worker_count",
+                tree.signature_worker_count,
+            )?,
+            gap_worker_count: worker_count("gap_worker_count", tree.gap_worker_count)?,
+            metadata_json_download_worker_count: worker_count(
+                "metadata_json_download_worker_count",
+                tree.metadata_json_download_worker_count,
+            )?,
+            commitment,
+            download_metadata_json: tree.download_metadata_json,
+            priority: tree.priority,
+        })
+    }
+}
+
+impl TreeSettings {
+    /// The backfill arguments of the tree at `address`, `args` overridden by its settings.
+    pub fn backfill_args(
+        &self,
+        address: &str,
+        args: &BubblegumBackfillArgs,
+    ) -> BubblegumBackfillArgs {
+        let mut args = args.clone();
+        args.only_trees = Some(vec![address.to_string()]);
+
+        let tree_worker = &mut args.tree_worker;
+        if let Some(count) = self.signature_worker_count {
+            tree_worker.signature_worker.signature_worker_count = count;
+        }
+        if let Some(count) = self.gap_worker_count {
+            tree_worker.gap_worker.gap_worker_count = count;
+        }
+        if let Some(count) = self.metadata_json_download_worker_count {
+            tree_worker
+                .metadata_json_download_worker
+                .metadata_json_download_worker_count = count;
+        }
+        tree_worker.backfill_from_slot = self.backfill_from_slot.or(tree_worker.backfill_from_slot);
+        tree_worker.backfill_after_signature = self.backfill_after_signature;
+        tree_worker.skip_metadata_json_download |= !self.download_metadata_json;
+
+        args
+    }
+
+    /// The bound live processing crawls back to when no transaction was applied yet, the slot of
+    /// `args` unless the tree overrides it, like [`Self::backfill_args`].
+    pub const fn lower_bound(&self, args: &BubblegumBackfillArgs) -> CrawlLowerBound {
+        CrawlLowerBound {
+            slot: match self.backfill_from_slot {
+                Some(slot) => Some(slot),
+                None => args.tree_worker.backfill_from_slot,
+            },
+            signature: self.backfill_after_signature,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use digital_asset_types::dao::sea_orm_active_enums::LdMerkleTreeStatus;
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        backfill: BubblegumBackfillArgs,
+    }
+
+    fn tree(update: impl FnOnce(&mut ld_merkle_trees::Model)) -> ld_merkle_trees::Model {
+        let mut tree = ld_merkle_trees::Model {
+            address: "tree".to_string(),
+            tag: None,
+            capacity: None,
+            max_depth: None,
+            canopy_depth: None,
+            max_buffer_size: None,
+            should_index: true,
+            status: LdMerkleTreeStatus::Pending,
+            created_at: Default::default(),
+            updated_at: Default::default(),
+            creation_slot: None,
+            seq: None,
+            num_minted: None,
+            tree_creator: None,
+            tree_delegate: None,
+            backfill: true,
+            backfill_from_slot: None,
+            backfill_after_signature: None,
+            signature_worker_count: None,
+            gap_worker_count: None,
+            metadata_json_download_worker_count: None,
+            commitment: "confirmed".to_string(),
+            download_metadata_json: true,
+            priority: 0,
+        };
+        update(&mut tree);
+        tree
+    }
+
+    #[test]
+    fn reads_the_settings_of_the_registry() {
+        assert_eq!(
+            TreeSettings::try_from(&tree(|_| {})),
+            Ok(TreeSettings::default())
+        );
+
+        let signature = Signature::new_unique();
+        let settings = TreeSettings::try_from(&tree(|tree| {
+            tree.backfill_from_slot = Some(100);
+            tree.backfill_after_signature = Some(signature.to_string());
+            tree.gap_worker_count = Some(4);
+            tree.commitment = "finalized".to_string();
+            tree.priority = 10;
+        }))
+        .unwrap();
+        assert_eq!(settings.backfill_from_slot, Some(100));
+        assert_eq!(settings.backfill_after_signature, Some(signature));
+        assert_eq!(settings.gap_worker_count, Some(4));
+        assert_eq!(settings.commitment, CommitmentConfig::finalized());
+        assert_eq!(settings.priority, 10);
+
+        assert!(
+            TreeSettings::try_from(&tree(|tree| tree.commitment = "processed".to_string()))
+                .is_err()
+        );
+        assert!(TreeSettings::try_from(&tree(|tree| {
+            tree.backfill_after_signature = Some("not-a-signature".to_string())
+        }))
+        .is_err());
+        assert!(
+            TreeSettings::try_from(&tree(|tree| tree.signature_worker_count = Some(0))).is_err()
+        );
+        assert!(TreeSettings::try_from(&tree(|tree| tree.backfill_from_slot = Some(-1))).is_err());
+    }
+
+    #[test]
+    fn overrides_the_backfill_arguments() {
+        let args = Cli::parse_from([
+            "lightdas",
+            "--signature-worker-count=100",
+            "--gap-worker-count=100",
+        ])
+        .backfill;
+
+        let tree_args = TreeSettings::default().backfill_args("tree", &args);
+        assert_eq!(tree_args.only_trees, Some(vec!["tree".to_string()]));
+        assert_eq!(
+            tree_args
+                .tree_worker
+                .signature_worker
+                .signature_worker_count,
+            100
+        );
+        assert!(!tree_args.tree_worker.skip_metadata_json_download);
+
+        let settings = TreeSettings {
+            signature_worker_count: Some(2),
+            backfill_from_slot: Some(100),
+            download_metadata_json: false,
+            ..Default::default()
+        };
+        let tree_args = settings.backfill_args("tree", &args);
+        assert_eq!(
+            tree_args
+                .tree_worker
+                .signature_worker
+                .signature_worker_count,
+            2
+        );
+        assert_eq!(tree_args.tree_worker.gap_worker.gap_worker_count, 100);
+        assert_eq!(tree_args.tree_worker.backfill_from_slot, Some(100));
+        assert!(tree_args.tree_worker.skip_metadata_json_download);
+    }
+
+    #[test]
+    fn bounds_the_handoff_like_the_backfill() {
+        let args = Cli::parse_from(["lightdas", "--backfill-from-slot=50"]).backfill;
+        let signature = Signature::new_unique();
+
+        let settings = TreeSettings {
+            backfill_after_signature: Some(signature),
+            ..Default::default()
+        };
+        let lower_bound = settings.lower_bound(&args);
+        assert_eq!(lower_bound.slot, Some(50));
+        assert_eq!(lower_bound.signature, Some(signature));
+        assert_eq!(
+            settings
+                .backfill_args("tree", &args)
+                .tree_worker
+                .backfill_from_slot,
+            lower_bound.slot
+        );
+
+        let settings = TreeSettings {
+            backfill_from_slot: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(settings.lower_bound(&args).slot, Some(100));
+    }
+}
@@ -0,0 +1,246 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use clap::Args;
+use das_bubblegum_backfill::{verify_tree, BubblegumBackfillContext, TreeIntegrity};
+use digital_asset_types::dao::ld_merkle_trees;
+use digital_asset_types::dao::sea_orm_active_enums::LdMerkleTreeStatus;
+use digital_asset_types::dapi::{get_drifted_assets_for_tree, get_invalid_proofs_for_tree};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, SqlxPostgresConnector};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::mpsc::Sender;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Args)]
+pub struct VerifyCommand {
+    /// Only verify this tree, every tree to index otherwise.
+    #[arg(long)]
+    tree: Option<Pubkey>,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckProofsCommand {
+    /// Only check the proofs of this tree, of every tree to index otherwise.
+    #[arg(long)]
+    tree: Option<Pubkey>,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckLeavesCommand {
+    /// Only check the assets of this tree, of every tree to index otherwise.
+    #[arg(long)]
+    tree: Option<Pubkey>,
+}
+
+/// Verifies the trees indexed live every `interval` until `shutdown` is cancelled, sending the
+/// ones whose nodes differ from their account to `rebackfill` when set.
+pub async fn verify_trees(
+    context: BubblegumBackfillContext,
+    interval: Duration,
+    rebackfill: Option<Sender<String>>,
+    shutdown: CancellationToken,
+) {
+    let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+    // The trees are still starting on the first tick
+    let mut verification = tokio::time::interval_at(Instant::now() + interval, interval);
+
+    loop {
+        tokio::select! {
+            _ = verification.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+
+        // Backfilling trees are behind their account until they caught up
+        let trees = match find_trees(&conn, Some(LdMerkleTreeStatus::Indexing)).await {
+            Ok(trees) => trees,
+            Err(e) => {
+                eprintln!("Error fetching trees to verify: {:?}", e);
+                continue;
+            }
+        };
+
+        for tree in trees {
+            if shutdown.is_cancelled() {
+                return;
+            }
+
+            let verification = match verify_tree(&context.solana_rpc, &conn, tree).await {
+                Ok(verification) => verification,
+                Err(e) => {
+                    eprintln!("Error verifying tree {:}: {:?}", tree, e);
+                    continue;
+                }
+            };
+
+            match verification.integrity {
+                TreeIntegrity::Verified { .. } => {}
+                TreeIntegrity::Unverifiable { reason } => {
+                    println!("Tree {:} could not be verified, {}", tree, reason);
+                }
+                TreeIntegrity::Mismatch { nodes } => {
+                    eprintln!(
+                        "Tree {:} differs from its account at seq {:?}, nodes {:?}",
+                        tree, verification.indexed_seq, nodes
+                    );
+
+                    if let Some(rebackfill) = &rebackfill {
+                        let _ = rebackfill.send(tree.to_string()).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Verifies the trees of `command`, failing when any of them differs from its account.
+pub async fn run_verify_command(
+    command: VerifyCommand,
+    context: BubblegumBackfillContext,
+) -> Result<()> {
+    let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+    let trees = match command.tree {
+        Some(tree) => vec![tree],
+        None => find_trees(&conn, None).await?,
+    };
+
+    let mut mismatches = 0;
+    for tree in trees {
+        match verify_tree(&context.solana_rpc, &conn, tree).await {
+            Ok(verification) => {
+                println!(
+                    "{:} onchain_seq={} indexed_seq={} {}",
+                    tree,
+                    verification.onchain_seq,
+                    verification
+                        .indexed_seq
+                        .map_or_else(|| "none".to_string(), |seq| seq.to_string()),
+                    verification.integrity
+                );
+
+                if matches!(verification.integrity, TreeIntegrity::Mismatch { .. }) {
+                    mismatches += 1;
+                }
+            }
+            Err(e) => eprintln!("Error verifying tree {:}: {:?}", tree, e),
+        }
+    }
+
+    if mismatches > 0 {
+        bail!("{} trees differ from their account", mismatches);
+    }
+
+    Ok(())
+}
+
+/// Checks that the proof of every leaf of the trees of `command` hashes up to the indexed root,
+/// failing when any of them doesn't.
+pub async fn run_check_proofs_command(
+    command: CheckProofsCommand,
+    context: BubblegumBackfillContext,
+) -> Result<()> {
+    let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+    let trees = match command.tree {
+        Some(tree) => vec![tree],
+        None => find_trees(&conn, None).await?,
+    };
+
+    let mut invalid_proofs = 0;
+    for tree in trees {
+        let invalid = get_invalid_proofs_for_tree(&conn, tree.to_bytes().to_vec()).await?;
+        if invalid.is_empty() {
+            println!("{:} every proof hashes up to its root", tree);
+        }
+
+        for invalid in &invalid {
+            println!(
+                "{:} leaf_index={} leaf={} root={} computed_root={}",
+                tree,
+                invalid.leaf_idx,
+                invalid.proof.leaf,
+                invalid.proof.root,
+                invalid.computed_root.as_deref().unwrap_or("invalid")
+            );
+        }
+        invalid_proofs += invalid.len();
+    }
+
+    if invalid_proofs > 0 {
+        bail!("{} proofs don't hash up to their root", invalid_proofs);
+    }
+
+    Ok(())
+}
+
+/// Checks that the rows of every compressed asset of the trees of `command` hash to its leaf,
+/// failing when any of them drifted.
+pub async fn run_check_leaves_command(
+    command: CheckLeavesCommand,
+    context: BubblegumBackfillContext,
+) -> Result<()> {
+    let conn = SqlxPostgresConnector::from_sqlx_postgres_pool(context.database_pool.clone());
+    let trees = match command.tree {
+        Some(tree) => vec![tree],
+        None => find_trees(&conn, None).await?,
+    };
+
+    let mut drifted_assets = 0;
+    for tree in trees {
+        let drifted = get_drifted_assets_for_tree(&conn, tree.to_bytes().to_vec()).await?;
+        if drifted.is_empty() {
+            println!("{:} every asset hashes to its leaf", tree);
+        }
+
+        for asset in &drifted {
+            println!(
+                "{:} asset={} nonce={} leaf={} computed_leaf={} drifted={}",
+                tree,
+                bs58::encode(&asset.id).into_string(),
+                asset
+                    .nonce
+                    .map_or_else(|| "none".to_string(), |nonce| nonce.to_string()),
+                asset.leaf.as_ref().map_or_else(
+                    || "none".to_string(),
+                    |leaf| bs58::encode(leaf).into_string()
+                ),
+                asset.computed_leaf.map_or_else(
+                    || "none".to_string(),
+                    |leaf| bs58::encode(leaf).into_string()
+                ),
+                asset
+                    .drifts
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            );
+        }
+        drifted_assets += drifted.len();
+    }
+
+    if drifted_assets > 0 {
+        bail!("{} assets drifted from their leaf", drifted_assets);
+    }
+
+    Ok(())
+}
+
+/// The trees to index, only the ones with `status` when set.
+async fn find_trees(
+    conn: &DatabaseConnection,
+    status: Option<LdMerkleTreeStatus>,
+) -> Result<Vec<Pubkey>> {
+    let mut query =
+        ld_merkle_trees::Entity::find().filter(ld_merkle_trees::Column::ShouldIndex.eq(true));
+    if let Some(status) = status {
+        query = query.filter(ld_merkle_trees::Column::Status.eq(status));
+    }
+
+    Ok(query
+        .all(conn)
+        .await?
+        .into_iter()
+        .filter_map(|tree| Pubkey::from_str(&tree.address).ok())
+        .collect())
+}